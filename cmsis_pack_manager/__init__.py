@@ -17,9 +17,10 @@
 import sys
 import time
 import collections
+from os import listdir
 from os.path import join, dirname, exists
 from shutil import rmtree
-from json import load
+from json import dumps, load, loads
 from zipfile import ZipFile
 from appdirs import user_data_dir
 from .cmsis_pack_manager import ffi, lib
@@ -36,6 +37,13 @@ class _RaiseRust(object):
             raise Exception(ffi.string(maybe_err))
 
 
+def _entries(dump, key):
+    """The entries of a JSON dump, written with or without a format version"""
+    if "format_version" in dump:
+        return dump[key]
+    return dump
+
+
 class CmsisPackRef(collections.namedtuple(
         "CmsisPackRef",
         "vendor, pack, version"
@@ -63,24 +71,55 @@ class Cache (object):
                         connection timeout and low speed timeout for
                         downloading things.
     :type no_timeouts: bool
+    :param proxy: URL of a proxy used for all downloads.
+    :type proxy: str
+    :param user_agent: User agent sent with every download request.
+    :type user_agent: str
+    :param ca_bundle: Path to a PEM file with additional root certificates.
+    :type ca_bundle: str
+    :param client_cert: Path to a PEM file with a client certificate and
+                        its private key.
+    :type client_cert: str
+    :param offline_mirror: Directory holding a mirror of the pack indexes,
+                           descriptors and packs. When set, nothing is
+                           downloaded from the network.
+    :type offline_mirror: str
+    :param legacy_json: A boolean that, when True, writes the device and
+                        board indexes without a format version, as read by
+                        older tools.
+    :type legacy_json: bool
     """
     def __init__(
             self,
             silent,
-            __,
+            no_timeouts,
             json_path=None,
             data_path=None,
-            vidx_list=None
+            vidx_list=None,
+            proxy=None,
+            user_agent=None,
+            ca_bundle=None,
+            client_cert=None,
+            offline_mirror=None,
+            legacy_json=False
     ):
         default_path = user_data_dir('cmsis-pack-manager')
         json_path = default_path if not json_path else json_path
         self._index = {}
         self._aliases = {}
+        self._device_index = None
         self.index_path = join(json_path, "index.json")
         self.aliases_path = join(json_path, "aliases.json")
         self.data_path = default_path if not data_path else data_path
         self.vidx_list = vidx_list
         self.silent = silent
+        self.no_timeouts = no_timeouts
+        self.proxy = proxy
+        self.user_agent = user_agent
+        self.ca_bundle = ca_bundle
+        self.client_cert = client_cert
+        self.offline_mirror = offline_mirror
+        self.legacy_json = legacy_json
 
     def get_flash_algorithm_binary(self, device_name, all=False):
         """Retrieve the flash algorithm file for a particular part.
@@ -144,7 +183,7 @@ class Cache (object):
         if not self._index:
             try:
                 with open(self.index_path) as i:
-                    self._index = load(i)
+                    self._index = _entries(load(i), "devices")
             except IOError:
                 return {}
         return self._index
@@ -165,7 +204,7 @@ class Cache (object):
         if not self._aliases:
             try:
                 with open(self.aliases_path) as i:
-                    self._aliases = load(i)
+                    self._aliases = _entries(load(i), "boards")
             except IOError:
                 return {}
         return self._aliases
@@ -211,13 +250,29 @@ class Cache (object):
         progress_fn = None if self.silent else self._verbose_on_tick_fn
         self._call_rust_update_packs(parsed_packs, progress_fn)
 
+    def _download_options(self):
+        options = ffi.new("DownloadOptions *")
+        options.no_timeouts = bool(self.no_timeouts)
+        # Keep the C strings alive for as long as the options are
+        strings = []
+        for field in ("proxy", "user_agent", "ca_bundle", "client_cert",
+                      "offline_mirror"):
+            value = getattr(self, field)
+            if value:
+                cvalue = ffi.new("char[]", value.encode("utf-8"))
+                strings.append(cvalue)
+                name = "client_identity" if field == "client_cert" else field
+                setattr(options, name, cvalue)
+        return options, strings
+
     def _call_rust_update_packs(self, parsed_packs, on_tick_fn):
         if self.data_path:
             cdata_path = ffi.new("char[]", self.data_path.encode("utf-8"))
         else:
             cdata_path = ffi.NULL
+        options, _strings = self._download_options()
         with _RaiseRust():
-            poll_obj = lib.update_packs(cdata_path, parsed_packs)
+            poll_obj = lib.update_packs(cdata_path, parsed_packs, options)
         return self._poll_rust_update(poll_obj, on_tick_fn)
 
     def _verbose_on_tick_fn(self, total, current):
@@ -239,17 +294,25 @@ class Cache (object):
             cvidx_path = ffi.new("char[]", self.vidx_list.encode("utf-8"))
         else:
             cvidx_path = ffi.NULL
+        options, _strings = self._download_options()
         with _RaiseRust():
-            poll_obj = lib.update_pdsc_index(cdata_path, cvidx_path)
+            poll_obj = lib.update_pdsc_index(cdata_path, cvidx_path, options)
         return self._poll_rust_update(poll_obj, on_tick_fn)
 
     def _poll_rust_update(self, poll_obj, on_tick_fn):
         with _RaiseRust():
             total_downloads = None
             current_downloads = 0
+            cancelled = False
             while not lib.update_pdsc_poll(poll_obj):
                 prev_downloads = current_downloads
-                time.sleep(1/2)
+                try:
+                    time.sleep(1/2)
+                except KeyboardInterrupt:
+                    # Stop the download thread, then wait for it to exit
+                    lib.update_pdsc_cancel(poll_obj)
+                    cancelled = True
+                    continue
                 message = ffi.gc(
                     lib.update_pdsc_get_status(poll_obj),
                     lib.update_pdsc_status_free
@@ -268,15 +331,27 @@ class Cache (object):
             pdsc_index = ffi.gc(
                 lib.update_pdsc_result(poll_obj), lib.update_pdsc_index_free
             )
+            if cancelled:
+                # Discard the "cancelled" error in favour of the interrupt
+                lib.err_last_message_free(lib.err_get_last_message())
+                raise KeyboardInterrupt
         return pdsc_index
 
     def _call_rust_parse(self, pdsc_index):
+        if self.data_path:
+            cdata_path = ffi.new("char[]", self.data_path.encode("utf-8"))
+        else:
+            cdata_path = ffi.NULL
         with _RaiseRust():
-            parsed_packs = ffi.gc(lib.parse_packs(pdsc_index),
+            parsed_packs = ffi.gc(lib.parse_packs(pdsc_index, cdata_path),
                                   lib.parse_packs_free)
         return parsed_packs
 
-    def _call_rust_dump(self, parsed_packs):
+    def _call_rust_dump(self, parsed_packs, rebuild=False):
+        if self.data_path:
+            cdata_path = ffi.new("char[]", self.data_path.encode("utf-8"))
+        else:
+            cdata_path = ffi.NULL
         if self.index_path:
             cindex_path = ffi.new("char[]", self.index_path.encode("utf-8"))
         else:
@@ -286,7 +361,8 @@ class Cache (object):
         else:
             calias_path = ffi.NULL
         with _RaiseRust():
-            lib.dump_pdsc_json(parsed_packs, cindex_path, calias_path)
+            lib.dump_pdsc_json(parsed_packs, cdata_path, cindex_path,
+                               calias_path, rebuild, self.legacy_json)
         return parsed_packs
 
     def cache_descriptors(self):
@@ -299,10 +375,77 @@ class Cache (object):
         pdsc_index = self._call_rust_update(progress_fn)
         parsed_packs = self._call_rust_parse(pdsc_index)
         self._call_rust_dump(parsed_packs)
+        self._device_index = None
         return parsed_packs
 
+    def _pdsc_index_from_paths(self, paths):
+        pdsc_index = ffi.gc(
+            lib.update_pdsc_index_new(),
+            lib.update_pdsc_index_free
+        )
+        for path in paths:
+            cpdsc_path = ffi.new("char[]", path.encode("utf-8"))
+            with _RaiseRust():
+                lib.update_pdsc_index_push(pdsc_index, cpdsc_path)
+        return pdsc_index
+
+    def _installed_pdscs(self):
+        if not exists(self.data_path):
+            return []
+        return [join(self.data_path, name)
+                for name in sorted(listdir(self.data_path))
+                if name.endswith(".pdsc")]
+
+    def rebuild_index(self):
+        """Regenerate the index from the Pack Descriptions in the cache,
+        dropping every device and board of packs that are not installed."""
+        pdsc_index = self._pdsc_index_from_paths(self._installed_pdscs())
+        parsed_packs = self._call_rust_parse(pdsc_index)
+        self._index = {}
+        self._aliases = {}
+        self._device_index = None
+        return self._call_rust_dump(parsed_packs, rebuild=True)
+
+    def find_parts(self, names=(), **criteria):
+        """Search the devices in the index and the boards they are mounted on.
+
+        The Pack Descriptions are loaded once, on the first search, so
+        repeated searches are cheap.
+
+        :param names: Words that must all appear in the part name
+        :param criteria: Any of vendor, family, core, fpu, min_flash,
+                         max_flash, min_ram, max_ram and board
+        :return: The matching parts, in the format of `index`, under
+                 "devices" and the boards matching `names` under "boards"
+        :rtype: dict
+
+        :Example:
+
+        >>> Cache().find_parts(["stm32f4"], core="Cortex-M4", min_flash=1 << 20)
+        """
+        if self._device_index is None:
+            paths = self._installed_pdscs()
+            for device in self.index.values():
+                path = device.get("from_pack", {}).get("path")
+                if path and path.endswith(".pdsc") and path not in paths \
+                        and exists(path):
+                    paths.append(path)
+            parsed_packs = self._call_rust_parse(
+                self._pdsc_index_from_paths(paths))
+            with _RaiseRust():
+                self._device_index = ffi.gc(
+                    lib.device_index_new(parsed_packs), lib.device_index_free)
+        criteria["name"] = list(names)
+        cquery = ffi.new("char[]", dumps(criteria).encode("utf-8"))
+        with _RaiseRust():
+            found = ffi.gc(
+                lib.device_index_search(self._device_index, cquery),
+                lib.cstring_free)
+        return loads(ffi.string(found).decode("utf-8"))
+
     def cache_clean(self):
         """Clean the entire cache."""
+        self._device_index = None
         if exists(self.data_path):
             rmtree(self.data_path)
         json_path = dirname(self.index_path)
@@ -364,4 +507,5 @@ class Cache (object):
                                 lib.update_pdsc_index_free)
         parsed_packs = self._call_rust_parse(pack_files)
         self._call_rust_dump(parsed_packs)
+        self._device_index = None
         return parsed_packs
@@ -79,12 +79,19 @@ def subcommand(name, *args, **kwargs):
         subparser.add_argument("--vidx-list", dest="vidx_list")
         subparser.add_argument("--data-path", dest="data_path")
         subparser.add_argument("--json-path", dest="json_path")
+        subparser.add_argument(
+            "--legacy-json",
+            action="store_true",
+            dest="legacy_json",
+            help="Write indexes without a format version, for older tools",
+        )
 
         def thunk(parsed_args):
             cache = Cache(not parsed_args.verbose, False,
                           vidx_list=parsed_args.vidx_list,
                           data_path=parsed_args.data_path,
-                          json_path=parsed_args.json_path)
+                          json_path=parsed_args.json_path,
+                          legacy_json=parsed_args.legacy_json)
             argv = [
                 arg['dest'] if 'dest' in arg else arg['name'] for arg in args
             ]
@@ -156,8 +163,20 @@ def command_cache(cache, action, devices, verbose=False, intersection=True):
         print("No action specified; nothing to do")
 
 
+def size(text):
+    """Parse a memory size such as 4096, 0x1000, 64K or 1M."""
+    units = {"K": 1 << 10, "M": 1 << 20, "G": 1 << 30}
+    scale = units.get(text[-1:].upper(), 1)
+    if scale != 1:
+        text = text[:-1]
+    try:
+        return int(text, 0) * scale
+    except ValueError:
+        raise argparse.ArgumentTypeError("invalid size: %s" % text)
+
+
 @subcommand('find-part',
-            dict(name='matches', nargs="+",
+            dict(name='matches', nargs="*",
                  help="Words to match to processors"),
             dict(name=['-l', "--long"], action="store_true",
                  help="Print out part details with part"),
@@ -165,22 +184,47 @@ def command_cache(cache, action, devices, verbose=False, intersection=True):
                  dest="print_aliases"),
             dict(name=['-a', '--aliases-only'], action="store_false",
                  dest="print_parts"),
+            dict(name="--vendor", help="Only parts from this vendor"),
+            dict(name="--family", help="Only parts in this family or subfamily"),
+            dict(name="--core", help="Only parts with this core, e.g. Cortex-M4"),
+            dict(name="--fpu", help="Only parts with this FPU, e.g. SP_FPU"),
+            dict(name="--board", help="Only parts mounted on this board"),
+            dict(name="--min-flash", dest="min_flash", type=size,
+                 help="Only parts with at least this much flash"),
+            dict(name="--max-flash", dest="max_flash", type=size,
+                 help="Only parts with at most this much flash"),
+            dict(name="--min-ram", dest="min_ram", type=size,
+                 help="Only parts with at least this much RAM"),
+            dict(name="--max-ram", dest="max_ram", type=size,
+                 help="Only parts with at most this much RAM"),
             help="Find a part and its description within the cache")
-def command_find_part(cache, matches, long=False, intersection=True,
-                      print_aliases=True, print_parts=True):
-    op = operator.and_ if intersection else operator.or_
+def command_find_part(cache, matches=(), long=False, intersection=True,
+                      print_aliases=True, print_parts=True, **criteria):
+    # Each search combines its words with an `and`, so an `or` takes one
+    # search per word
+    searches = [matches] if intersection or not matches else \
+        [[match] for match in matches]
+    parts = {}
+    aliases = {}
+    for words in searches:
+        found = cache.find_parts(words, **criteria)
+        parts.update(found["devices"])
+        aliases.update(found["boards"])
+    # Boards only match words, so leave them out of searches by criteria
+    if criteria or not matches:
+        aliases = {}
     to_dump = {} if long else []
     if print_parts:
-        for part in fuzzy_find(matches, cache.index.keys(), op):
+        for part in sorted(parts):
             if long:
-                to_dump.update({part: cache.index[part]})
+                to_dump.update({part: parts[part]})
             else:
                 to_dump.append(part)
     if print_aliases:
-        for alias in fuzzy_find(matches, cache.aliases.keys(), op):
+        for alias in sorted(aliases):
             if long:
-                if cache.aliases[alias]["mounted_devices"]:
-                    part = cache.aliases[alias]["mounted_devices"][0]
+                if aliases[alias]["mounted_devices"]:
+                    part = aliases[alias]["mounted_devices"][0]
                     try:
                         to_dump.update({alias: cache.index[part]})
                     except KeyError:
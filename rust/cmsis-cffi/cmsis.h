@@ -19,12 +19,28 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+typedef struct DeviceIndex DeviceIndex;
+
 typedef struct ParsedPacks ParsedPacks;
 
 typedef struct UpdatePoll UpdatePoll;
 
 typedef struct UpdateReturn UpdateReturn;
 
+typedef struct DownloadOptions {
+        bool no_timeouts;
+        uint64_t timeout_secs;
+        uintptr_t concurrency;
+        uintptr_t host_limit;
+        uintptr_t max_redirects;
+        const char *user_agent;
+        const char *proxy;
+        const char *ca_bundle;
+        const char *client_identity;
+        const char *offline_mirror;
+        uint64_t lock_timeout_secs;
+} DownloadOptions;
+
 typedef struct DownloadUpdate {
         bool is_size;
         uintptr_t size;
@@ -32,11 +48,20 @@ typedef struct DownloadUpdate {
 
 void cstring_free(char *ptr);
 
+void device_index_free(DeviceIndex *ptr);
+
+DeviceIndex *device_index_new(ParsedPacks *ptr);
+
+const char *device_index_search(DeviceIndex *ptr, const char *query);
+
 void dump_pdsc_json(ParsedPacks *packs,
+                    const char *pack_store,
                     const char *devices_dest,
-                    const char *boards_dest);
+                    const char *boards_dest,
+                    bool rebuild,
+                    bool legacy_json);
 
-const char *dumps_components(ParsedPacks *ptr);
+const char *dumps_components(ParsedPacks *ptr, bool legacy_json);
 
 const char *err_get_last_message(void);
 
@@ -44,15 +69,21 @@ void err_last_message_free(char *ptr);
 
 UpdateReturn *pack_from_path(const char *ptr);
 
-ParsedPacks *parse_packs(UpdateReturn *ptr);
+ParsedPacks *parse_packs(UpdateReturn *ptr, const char *pack_store);
 
 void parse_packs_free(ParsedPacks *ptr);
 
-UpdatePoll *update_packs(const char *pack_store, ParsedPacks *parsed_packs);
+UpdatePoll *update_packs(const char *pack_store,
+                         ParsedPacks *parsed_packs,
+                         const DownloadOptions *options);
+
+void update_pdsc_cancel(UpdatePoll *ptr);
 
 DownloadUpdate *update_pdsc_get_status(UpdatePoll *ptr);
 
-UpdatePoll *update_pdsc_index(const char *pack_store, const char *vidx_list);
+UpdatePoll *update_pdsc_index(const char *pack_store,
+                              const char *vidx_list,
+                              const DownloadOptions *options);
 
 void update_pdsc_index_free(UpdateReturn *ptr);
 
@@ -1,6 +1,9 @@
+use std::ffi::CStr;
 use std::fs::{create_dir_all, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
+use std::os::raw::c_char;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use cmsis_pack::update::DownloadConfig;
 
@@ -8,25 +11,243 @@ use anyhow::{anyhow, Error};
 
 pub const DEFAULT_VIDX_LIST: [&str; 1] = ["http://www.keil.com/pack/index.pidx"];
 
+/// Download settings passed in from C. Zero and null fields select the
+/// built-in defaults.
+#[repr(C)]
+pub struct DownloadOptions {
+    pub no_timeouts: bool,
+    pub timeout_secs: u64,
+    pub concurrency: usize,
+    pub host_limit: usize,
+    pub max_redirects: usize,
+    pub user_agent: *const c_char,
+    pub proxy: *const c_char,
+    pub ca_bundle: *const c_char,
+    pub client_identity: *const c_char,
+    pub offline_mirror: *const c_char,
+    pub lock_timeout_secs: u64,
+}
+
 pub struct Config {
     pack_store: PathBuf,
+    no_timeouts: bool,
+    timeout: Option<Duration>,
+    concurrency: Option<usize>,
+    host_limit: Option<usize>,
+    max_redirects: Option<usize>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    ca_bundle: Option<PathBuf>,
+    client_identity: Option<PathBuf>,
+    offline_mirror: Option<PathBuf>,
+    lock_timeout: Option<Duration>,
 }
 
 #[derive(Default)]
 pub struct ConfigBuilder {
     pack_store: Option<PathBuf>,
+    no_timeouts: bool,
+    timeout: Option<Duration>,
+    concurrency: Option<usize>,
+    host_limit: Option<usize>,
+    max_redirects: Option<usize>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    ca_bundle: Option<PathBuf>,
+    client_identity: Option<PathBuf>,
+    offline_mirror: Option<PathBuf>,
+    lock_timeout: Option<Duration>,
 }
 
 impl DownloadConfig for Config {
     fn pack_store(&self) -> PathBuf {
         self.pack_store.clone()
     }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+
+    fn host_limit(&self) -> Option<usize> {
+        self.host_limit
+    }
+
+    fn no_timeouts(&self) -> bool {
+        self.no_timeouts
+    }
+
+    fn connect_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn max_redirects(&self) -> Option<usize> {
+        self.max_redirects
+    }
+
+    fn user_agent(&self) -> Option<String> {
+        self.user_agent.clone()
+    }
+
+    fn proxy(&self) -> Option<String> {
+        self.proxy.clone()
+    }
+
+    fn ca_bundle(&self) -> Option<PathBuf> {
+        self.ca_bundle.clone()
+    }
+
+    fn client_identity(&self) -> Option<PathBuf> {
+        self.client_identity.clone()
+    }
+
+    fn offline_mirror(&self) -> Option<PathBuf> {
+        self.offline_mirror.clone()
+    }
+
+    fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+}
+
+fn nonzero<T: Default + PartialEq>(val: T) -> Option<T> {
+    if val == T::default() {
+        None
+    } else {
+        Some(val)
+    }
+}
+
+pub(crate) unsafe fn opt_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
 }
 
 impl ConfigBuilder {
     pub fn with_pack_store<T: Into<PathBuf>>(self, ps: T) -> Self {
         Self {
             pack_store: Some(ps.into()),
+            ..self
+        }
+    }
+
+    pub fn with_no_timeouts(self, no_timeouts: bool) -> Self {
+        Self {
+            no_timeouts,
+            ..self
+        }
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self {
+            concurrency: Some(concurrency),
+            ..self
+        }
+    }
+
+    pub fn with_host_limit(self, host_limit: usize) -> Self {
+        Self {
+            host_limit: Some(host_limit),
+            ..self
+        }
+    }
+
+    pub fn with_max_redirects(self, max_redirects: usize) -> Self {
+        Self {
+            max_redirects: Some(max_redirects),
+            ..self
+        }
+    }
+
+    pub fn with_user_agent<T: Into<String>>(self, user_agent: T) -> Self {
+        Self {
+            user_agent: Some(user_agent.into()),
+            ..self
+        }
+    }
+
+    pub fn with_proxy<T: Into<String>>(self, proxy: T) -> Self {
+        Self {
+            proxy: Some(proxy.into()),
+            ..self
+        }
+    }
+
+    pub fn with_ca_bundle<T: Into<PathBuf>>(self, ca_bundle: T) -> Self {
+        Self {
+            ca_bundle: Some(ca_bundle.into()),
+            ..self
+        }
+    }
+
+    pub fn with_client_identity<T: Into<PathBuf>>(self, identity: T) -> Self {
+        Self {
+            client_identity: Some(identity.into()),
+            ..self
+        }
+    }
+
+    pub fn with_offline_mirror<T: Into<PathBuf>>(self, mirror: T) -> Self {
+        Self {
+            offline_mirror: Some(mirror.into()),
+            ..self
+        }
+    }
+
+    pub fn with_lock_timeout(self, timeout: Duration) -> Self {
+        Self {
+            lock_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Apply the settings from a possibly null `DownloadOptions` pointer.
+    ///
+    /// # Safety
+    ///
+    /// `options` must be null or point to a valid `DownloadOptions`, whose
+    /// string fields are each null or a valid NUL-terminated string.
+    pub unsafe fn with_options(self, options: *const DownloadOptions) -> Self {
+        let opts = match options.as_ref() {
+            Some(opts) => opts,
+            None => return self,
+        };
+        Self {
+            no_timeouts: opts.no_timeouts || self.no_timeouts,
+            timeout: nonzero(opts.timeout_secs)
+                .map(Duration::from_secs)
+                .or(self.timeout),
+            concurrency: nonzero(opts.concurrency).or(self.concurrency),
+            host_limit: nonzero(opts.host_limit).or(self.host_limit),
+            max_redirects: nonzero(opts.max_redirects).or(self.max_redirects),
+            user_agent: opt_string(opts.user_agent).or(self.user_agent),
+            proxy: opt_string(opts.proxy).or(self.proxy),
+            ca_bundle: opt_string(opts.ca_bundle)
+                .map(PathBuf::from)
+                .or(self.ca_bundle),
+            client_identity: opt_string(opts.client_identity)
+                .map(PathBuf::from)
+                .or(self.client_identity),
+            offline_mirror: opt_string(opts.offline_mirror)
+                .map(PathBuf::from)
+                .or(self.offline_mirror),
+            lock_timeout: nonzero(opts.lock_timeout_secs)
+                .map(Duration::from_secs)
+                .or(self.lock_timeout),
+            ..self
         }
     }
 
@@ -37,7 +258,20 @@ impl ConfigBuilder {
                 return Err(anyhow!("Pack Store missing"));
             }
         };
-        Ok(Config { pack_store })
+        Ok(Config {
+            pack_store,
+            no_timeouts: self.no_timeouts,
+            timeout: self.timeout,
+            concurrency: self.concurrency,
+            host_limit: self.host_limit,
+            max_redirects: self.max_redirects,
+            user_agent: self.user_agent,
+            proxy: self.proxy,
+            ca_bundle: self.ca_bundle,
+            client_identity: self.client_identity,
+            offline_mirror: self.offline_mirror,
+            lock_timeout: self.lock_timeout,
+        })
     }
 }
 
@@ -76,7 +310,12 @@ pub fn read_vidx_list(vidx_list: &Path) -> Vec<String> {
                     log::error!("Could not get parent directory for vendors.list");
                 }
             }
-            match OpenOptions::new().create(true).write(true).open(vidx_list) {
+            match OpenOptions::new()
+                .create(true)
+                .truncate(true)
+                .write(true)
+                .open(vidx_list)
+            {
                 Ok(mut fd) => {
                     let lines = new_content.join("\n");
                     fd.write_all(lines.as_bytes()).unwrap_or_else(|e| {
@@ -1,3 +1,4 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
 extern crate cmsis_pack;
 #[macro_use]
 extern crate ctor;
@@ -18,14 +19,14 @@ macro_rules! with_from_raw {
         #[allow(unused_unsafe)]
         let $boxed = unsafe { Box::from_raw($ptr) };
         let ret = $block;
-        Box::into_raw($boxed);
+        let _ = Box::into_raw($boxed);
         ret
     }};
     (let mut $boxed:ident = $ptr:ident, $block:block) => {{
         #[allow(unused_unsafe)]
         let mut $boxed = unsafe { Box::from_raw($ptr) };
         let ret = $block;
-        Box::into_raw($boxed);
+        let _ = Box::into_raw($boxed);
         ret
     }};
 }
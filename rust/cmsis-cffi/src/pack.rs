@@ -5,8 +5,8 @@ use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::thread;
 
-use crate::config::ConfigBuilder;
-use cmsis_pack::update::install;
+use crate::config::{ConfigBuilder, DownloadOptions};
+use cmsis_pack::update::{install_with_cancel, CancellationToken};
 
 use crate::pack_index::{DownloadSender, RunningUpdateContext, UpdatePoll, UpdateReturn};
 use crate::pdsc::ParsedPacks;
@@ -14,9 +14,10 @@ use crate::pdsc::ParsedPacks;
 cffi! {
     fn update_packs(
         pack_store: *const c_char,
-        parsed_packs: *mut ParsedPacks
+        parsed_packs: *mut ParsedPacks,
+        options: *const DownloadOptions,
     ) -> Result<*mut UpdatePoll> {
-        let conf_bld = ConfigBuilder::default();
+        let conf_bld = unsafe { ConfigBuilder::default().with_options(options) };
         let conf_bld = if !pack_store.is_null() {
             let pstore = unsafe { CStr::from_ptr(pack_store) }.to_string_lossy();
             conf_bld.with_pack_store(pstore.into_owned())
@@ -27,6 +28,8 @@ cffi! {
         let (send, recv) = channel();
         let done_flag = Arc::new(AtomicBool::new(false));
         let threads_done_flag = done_flag.clone();
+        let cancel = CancellationToken::new();
+        let threads_cancel = cancel.clone();
         if !parsed_packs.is_null() {
             with_from_raw!(let mut packs = parsed_packs, {
                 let size = packs.0.len();
@@ -34,10 +37,11 @@ cffi! {
                 let thread = thread::Builder::new()
                     .name("update".to_string())
                     .spawn(move || {
-                        let res = install(
+                        let res = install_with_cancel(
                             &conf,
                             packs.iter(),
-                            DownloadSender::from_sender(send)
+                            DownloadSender::from_sender(send),
+                            threads_cancel,
                         ).map(UpdateReturn);
                         threads_done_flag.store(true, Ordering::Release);
                         res
@@ -46,6 +50,7 @@ cffi! {
                     thread_handle: thread,
                     done_flag,
                     result_stream: recv,
+                    cancel,
                 }))))
             })
         } else {
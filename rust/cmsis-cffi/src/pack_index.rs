@@ -12,10 +12,10 @@ use std::thread;
 
 use anyhow::{anyhow, Error};
 
-use crate::config::{read_vidx_list, ConfigBuilder, DEFAULT_VIDX_LIST};
+use crate::config::{read_vidx_list, ConfigBuilder, DownloadOptions, DEFAULT_VIDX_LIST};
 use crate::utils::set_last_error;
-use cmsis_pack::update::update;
-use cmsis_pack::update::DownloadProgress;
+use cmsis_pack::update::update_with_cancel;
+use cmsis_pack::update::{CancellationToken, DownloadProgress};
 
 pub struct UpdateReturn(pub(crate) Vec<PathBuf>);
 
@@ -23,6 +23,7 @@ pub struct RunningUpdateContext {
     pub(crate) thread_handle: thread::JoinHandle<Result<UpdateReturn, Error>>,
     pub(crate) done_flag: Arc<AtomicBool>,
     pub(crate) result_stream: Receiver<DownloadUpdate>,
+    pub(crate) cancel: CancellationToken,
 }
 
 #[repr(C)]
@@ -85,8 +86,9 @@ cffi! {
     fn update_pdsc_index(
         pack_store: *const c_char,
         vidx_list: *const c_char,
+        options: *const DownloadOptions,
     ) -> Result<*mut UpdatePoll> {
-        let conf_bld = ConfigBuilder::default();
+        let conf_bld = unsafe { ConfigBuilder::default().with_options(options) };
         let conf_bld = if !pack_store.is_null() {
             let pstore = unsafe { CStr::from_ptr(pack_store) }.to_string_lossy();
             conf_bld.with_pack_store(pstore.into_owned())
@@ -103,13 +105,16 @@ cffi! {
         let (send, recv) = channel();
         let done_flag = Arc::new(AtomicBool::new(false));
         let threads_done_flag = done_flag.clone();
+        let cancel = CancellationToken::new();
+        let threads_cancel = cancel.clone();
         let thread = thread::Builder::new()
             .name("update".to_string())
             .spawn(move || {
-                let res = update(
+                let res = update_with_cancel(
                     &conf,
                     vidx_list,
-                    DownloadSender::from_sender(send)
+                    DownloadSender::from_sender(send),
+                    threads_cancel,
                 ).map(UpdateReturn);
                 threads_done_flag.store(true, Ordering::Release);
                 res
@@ -118,6 +123,7 @@ cffi! {
             thread_handle: thread,
             done_flag,
             result_stream: recv,
+            cancel,
         }))))
     }
 }
@@ -150,6 +156,19 @@ pub unsafe extern "C" fn update_pdsc_poll(ptr: *mut UpdatePoll) -> bool {
     }
 }
 
+/// Ask a running update to stop. The update finishes with an error shortly
+/// afterwards, so keep calling `update_pdsc_poll` until it returns true.
+#[no_mangle]
+pub unsafe extern "C" fn update_pdsc_cancel(ptr: *mut UpdatePoll) {
+    if !ptr.is_null() {
+        with_from_raw!(let boxed = ptr, {
+            if let UpdatePoll::Running(ref cont) = boxed.borrow() {
+                cont.cancel.cancel();
+            }
+        })
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn update_pdsc_get_status(ptr: *mut UpdatePoll) -> *mut DownloadUpdate {
     if !ptr.is_null() {
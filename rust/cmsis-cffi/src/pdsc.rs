@@ -3,17 +3,24 @@ use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 use std::path::{Path, PathBuf};
 
-use cmsis_pack::pdsc::{dump_devices, Package};
-use cmsis_pack::utils::FromElem;
+use cmsis_pack::pdsc::{
+    dump_devices_with, parse_pdscs, DeviceIndex, DeviceQuery, DumpOptions, JsonFormat, Package,
+    PackageCache, PACKAGE_CACHE,
+};
+use cmsis_pack::store::{LockMode, StoreLock};
 use cmsis_pack::utils::ResultLogExt;
 
+use crate::config::opt_string;
 use crate::pack_index::UpdateReturn;
 
 cffi! {
     fn dump_pdsc_json(
         packs: *mut ParsedPacks,
+        pack_store: *const c_char,
         devices_dest: *const c_char,
         boards_dest: *const c_char,
+        rebuild: bool,
+        legacy_json: bool,
     ) -> Result<()> {
         let dev_dest: Option<Cow<str>> = if !devices_dest.is_null() {
             let fname = unsafe { CStr::from_ptr(devices_dest) }.to_string_lossy();
@@ -27,10 +34,16 @@ cffi! {
         } else {
             None
         };
+        let format = if legacy_json { JsonFormat::Legacy } else { JsonFormat::Versioned };
+        // Entries whose pdsc left the pack store are dropped from the indexes
+        let _lock = unsafe { opt_string(pack_store) }
+            .map(|store| StoreLock::acquire(Path::new(&store), LockMode::Shared, None))
+            .transpose()?;
         with_from_raw!(let filenames = packs, {
-            dump_devices(&filenames.0,
-                         dev_dest.map(|d| d.to_string()),
-                         brd_dest.map(|d| d.to_string()),
+            dump_devices_with(&filenames.0,
+                 dev_dest.map(|d| d.to_string()),
+                 brd_dest.map(|d| d.to_string()),
+                 DumpOptions { rebuild, format, ..DumpOptions::default() },
             )
         })
     }
@@ -62,14 +75,26 @@ cffi! {
 }
 
 cffi! {
-    fn parse_packs(ptr: *mut UpdateReturn) -> Result<*mut ParsedPacks>{
+    fn parse_packs(ptr: *mut UpdateReturn, pack_store: *const c_char) -> Result<*mut ParsedPacks>{
         if !ptr.is_null() {
+            let pack_store = unsafe { opt_string(pack_store) }.map(PathBuf::from);
+            let _lock = pack_store
+                .as_ref()
+                .map(|store| StoreLock::acquire(store, LockMode::Shared, None))
+                .transpose()?;
             with_from_raw!(let boxed = ptr,{
-                let pdsc_files = boxed.iter();
-                Ok(Box::into_raw(Box::new(ParsedPacks(
-                    pdsc_files
-                        .filter_map(|input| Package::from_path(Path::new(input)).ok_warn())
-                        .collect()))))
+                let pdsc_files: Vec<&PathBuf> = boxed.iter().collect();
+                let parsed = match pack_store {
+                    Some(pack_store) => {
+                        let mut cache = PackageCache::open(pack_store.join(PACKAGE_CACHE));
+                        let parsed = cache.load_all(&pdsc_files);
+                        cache.save().ok_warn();
+                        parsed
+                    }
+                    None => parse_pdscs(&pdsc_files),
+                };
+                let packs = parsed.into_iter().filter_map(|p| p.ok_warn()).collect();
+                Ok(Box::into_raw(Box::new(ParsedPacks(packs))))
             })
         } else {
             Err(anyhow::anyhow!("Null Passed into parse packs."))
@@ -86,11 +111,47 @@ cffi! {
 }
 
 cffi! {
-    fn dumps_components(ptr: *mut ParsedPacks) -> Result<*const c_char> {
+    fn dumps_components(ptr: *mut ParsedPacks, legacy_json: bool) -> Result<*const c_char> {
+        let format = if legacy_json { JsonFormat::Legacy } else { JsonFormat::Versioned };
         with_from_raw!(let boxed = ptr, {
             let pdscs = boxed.iter();
-            let dumped_components = cmsis_pack::pdsc::dumps_components(pdscs)?;
+            let dumped_components = cmsis_pack::pdsc::dumps_components(pdscs, format)?;
             Ok(CString::new(dumped_components).unwrap().into_raw())
         })
     }
 }
+
+cffi! {
+    fn device_index_new(ptr: *mut ParsedPacks) -> Result<*mut DeviceIndex> {
+        if !ptr.is_null() {
+            with_from_raw!(let boxed = ptr, {
+                Ok(Box::into_raw(Box::new(DeviceIndex::new(boxed.iter()))))
+            })
+        } else {
+            Err(anyhow::anyhow!("Null passed into device_index_new"))
+        }
+    }
+}
+
+cffi! {
+    fn device_index_search(ptr: *mut DeviceIndex, query: *const c_char) -> Result<*const c_char> {
+        if ptr.is_null() || query.is_null() {
+            return Err(anyhow::anyhow!("Null passed into device_index_search"));
+        }
+        let query = unsafe { CStr::from_ptr(query) }.to_string_lossy();
+        let query: DeviceQuery = serde_json::from_str(&query)
+            .map_err(|e| anyhow::anyhow!("Invalid device query: {}", e))?;
+        with_from_raw!(let boxed = ptr, {
+            let found = boxed.search_json(&query)?;
+            Ok(CString::new(found).unwrap().into_raw())
+        })
+    }
+}
+
+cffi! {
+    fn device_index_free(ptr: *mut DeviceIndex) {
+        if !ptr.is_null() {
+            drop(unsafe { Box::from_raw(ptr) })
+        }
+    }
+}
@@ -3,8 +3,10 @@ extern crate cmsis_pack;
 use std::fs::{create_dir_all, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
+use std::time::Duration;
 
-use anyhow::Error;
+use anyhow::{format_err, Error};
+use clap::ArgMatches;
 
 use cmsis_pack::update::DownloadConfig;
 
@@ -13,12 +15,88 @@ use directories::ProjectDirs;
 pub struct Config {
     pub pack_store: PathBuf,
     pub vidx_list: PathBuf,
+    pub no_timeouts: bool,
+    pub timeout: Option<Duration>,
+    pub concurrency: Option<usize>,
+    pub host_limit: Option<usize>,
+    pub max_redirects: Option<usize>,
+    pub user_agent: Option<String>,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+    pub client_identity: Option<PathBuf>,
+    pub offline_mirror: Option<PathBuf>,
+    pub lock_timeout: Option<Duration>,
 }
 
 impl DownloadConfig for Config {
     fn pack_store(&self) -> PathBuf {
         self.pack_store.clone()
     }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+
+    fn host_limit(&self) -> Option<usize> {
+        self.host_limit
+    }
+
+    fn no_timeouts(&self) -> bool {
+        self.no_timeouts
+    }
+
+    fn connect_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn max_redirects(&self) -> Option<usize> {
+        self.max_redirects
+    }
+
+    fn user_agent(&self) -> Option<String> {
+        self.user_agent.clone()
+    }
+
+    fn proxy(&self) -> Option<String> {
+        self.proxy.clone()
+    }
+
+    fn ca_bundle(&self) -> Option<PathBuf> {
+        self.ca_bundle.clone()
+    }
+
+    fn client_identity(&self) -> Option<PathBuf> {
+        self.client_identity.clone()
+    }
+
+    fn offline_mirror(&self) -> Option<PathBuf> {
+        self.offline_mirror.clone()
+    }
+
+    fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(args: &ArgMatches<'_>, name: &str) -> Result<Option<T>, Error> {
+    args.value_of(name)
+        .map(|val| {
+            val.parse()
+                .map_err(|_| format_err!("Invalid value for --{}: {}", name, val))
+        })
+        .transpose()
+}
+
+/// Like `parse_arg`, for limits where 0 would stop all progress.
+fn parse_limit(args: &ArgMatches<'_>, name: &str) -> Result<Option<usize>, Error> {
+    match parse_arg(args, name)? {
+        Some(0) => Err(format_err!("--{} must be at least 1", name)),
+        limit => Ok(limit),
+    }
 }
 
 impl Config {
@@ -35,6 +113,35 @@ impl Config {
         Ok(Config {
             pack_store,
             vidx_list,
+            no_timeouts: false,
+            timeout: None,
+            concurrency: None,
+            host_limit: None,
+            max_redirects: None,
+            user_agent: None,
+            proxy: None,
+            ca_bundle: None,
+            client_identity: None,
+            offline_mirror: None,
+            lock_timeout: None,
+        })
+    }
+
+    /// Override download settings from the global command line arguments.
+    pub fn with_args(self, args: &ArgMatches<'_>) -> Result<Config, Error> {
+        Ok(Config {
+            no_timeouts: args.is_present("no-timeouts"),
+            timeout: parse_arg(args, "timeout")?.map(Duration::from_secs),
+            concurrency: parse_limit(args, "concurrency")?,
+            host_limit: parse_limit(args, "host-limit")?,
+            max_redirects: parse_arg(args, "max-redirects")?,
+            user_agent: args.value_of("user-agent").map(String::from),
+            proxy: args.value_of("proxy").map(String::from),
+            ca_bundle: args.value_of("cacert").map(PathBuf::from),
+            client_identity: args.value_of("cert").map(PathBuf::from),
+            offline_mirror: args.value_of("offline").map(PathBuf::from),
+            lock_timeout: parse_arg(args, "lock-timeout")?.map(Duration::from_secs),
+            ..self
         })
     }
 
@@ -4,14 +4,24 @@ extern crate pbr;
 use anyhow::Error;
 use clap::{App, Arg, ArgMatches, SubCommand};
 use pbr::ProgressBar;
-use std::io::Stdout;
-use std::path::Path;
+use std::io::{Stdout, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 extern crate cmsis_pack;
-use cmsis_pack::pdsc::{dump_devices, Component, FileRef, Package};
-use cmsis_pack::update::{install, update, DownloadProgress};
-use cmsis_pack::utils::FromElem;
+use cmsis_pack::export::linker::{linker_script, LinkerFormat, LinkerOptions, Security};
+use cmsis_pack::export::probe_rs::ChipFamily;
+use cmsis_pack::export::pyocd::TargetBundle;
+use cmsis_pack::export::sqlite;
+use cmsis_pack::pack_index::index_directory;
+use cmsis_pack::pdsc::{
+    dump_devices_with, json_schema, parse_pdscs, Component, Device, DeviceIndex, DumpKind,
+    DumpOptions, FileRef, IndexedDevice, JsonFormat, Package, PackageCache, PACKAGE_CACHE,
+};
+use cmsis_pack::store::{self, EntryKind, LockMode, PackFiles, PruneOptions, StoreLock};
+use cmsis_pack::svd::Svd;
+use cmsis_pack::update::{crawl, install, mirror, update, DownloadProgress, MirrorOptions};
+use cmsis_pack::utils::{FromElem, ParseFailure, ParseMode, ResultLogExt, ToElem};
 
 mod config;
 
@@ -49,6 +59,63 @@ impl CliProgress {
     }
 }
 
+pub fn download_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("no-timeouts")
+            .long("no-timeouts")
+            .help("Disable connect and read timeouts"),
+        Arg::with_name("timeout")
+            .long("timeout")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .help("Connect and read timeout"),
+        Arg::with_name("concurrency")
+            .long("concurrency")
+            .takes_value(true)
+            .value_name("N")
+            .help("Maximum number of concurrent downloads"),
+        Arg::with_name("host-limit")
+            .long("host-limit")
+            .takes_value(true)
+            .value_name("N")
+            .help("Maximum number of concurrent downloads per host"),
+        Arg::with_name("max-redirects")
+            .long("max-redirects")
+            .takes_value(true)
+            .value_name("N")
+            .help("Maximum number of redirects to follow"),
+        Arg::with_name("user-agent")
+            .long("user-agent")
+            .takes_value(true)
+            .help("User agent sent with every request"),
+        Arg::with_name("proxy")
+            .long("proxy")
+            .takes_value(true)
+            .value_name("URL")
+            .help("Proxy used for all requests"),
+        Arg::with_name("cacert")
+            .long("cacert")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("PEM file with additional root certificates"),
+        Arg::with_name("cert")
+            .long("cert")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("PEM file with client certificate and private key"),
+        Arg::with_name("offline")
+            .long("offline")
+            .takes_value(true)
+            .value_name("DIR")
+            .help("Never touch the network; read indexes and packs from this mirror"),
+        Arg::with_name("lock-timeout")
+            .long("lock-timeout")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .help("Give up waiting for other processes using the pack store after this long"),
+    ]
+}
+
 pub fn install_args() -> App<'static, 'static> {
     SubCommand::with_name("install")
         .about("Install a CMSIS Pack file")
@@ -113,6 +180,198 @@ pub fn update_command(conf: &Config, _: &ArgMatches<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+pub fn mirror_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("mirror")
+        .about("Create a self-contained mirror of the packs in the vendor index list")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("vendor")
+                .long("vendor")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only mirror packs from this vendor"),
+        )
+        .arg(
+            Arg::with_name("pack")
+                .long("pack")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("VENDOR.NAME")
+                .help("Only mirror this pack"),
+        )
+        .arg(
+            Arg::with_name("no-packs")
+                .long("no-packs")
+                .help("Only mirror the pack descriptions"),
+        )
+        .arg(
+            Arg::with_name("pack-url")
+                .long("pack-url")
+                .takes_value(true)
+                .value_name("URL")
+                .help("URL the mirror is served from, written into each pack description"),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("Directory to write the mirror to")
+                .required(true)
+                .index(1),
+        )
+}
+
+pub fn mirror_command(conf: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
+    let values = |name| {
+        args.values_of(name)
+            .map(|vals| vals.map(String::from).collect())
+            .unwrap_or_default()
+    };
+    let options = MirrorOptions {
+        vendors: values("vendor"),
+        packs: values("pack"),
+        skip_packs: args.is_present("no-packs"),
+        pack_url: args.value_of("pack-url").map(String::from),
+    };
+    let dest = Path::new(args.value_of("OUTPUT").unwrap());
+    let vidx_list = conf.read_vidx_list();
+    let progress = CliProgress::new();
+    let mirrored = mirror(conf, vidx_list, dest, &options, progress)?;
+    log::info!("Mirrored {} files into {:?}", mirrored.len(), dest);
+    Ok(())
+}
+
+pub fn gen_index_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("gen-index")
+        .about("Generate a vendor index for a directory of packs and pack descriptions")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("vendor")
+                .long("vendor")
+                .takes_value(true)
+                .required(true)
+                .help("Vendor publishing the index"),
+        )
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .takes_value(true)
+                .default_value("./")
+                .help("URL the packs are published at"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .takes_value(true)
+                .help("Write the index to this file instead of DIR/index.pidx"),
+        )
+        .arg(
+            Arg::with_name("DIR")
+                .help("Directory containing .pack and .pdsc files")
+                .required(true)
+                .index(1),
+        )
+}
+
+pub fn gen_index_command(_: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
+    let dir = Path::new(args.value_of("DIR").unwrap());
+    let vidx = index_directory(
+        dir,
+        args.value_of("vendor").unwrap(),
+        args.value_of("url").unwrap(),
+    )?;
+    let output = args
+        .value_of("output")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| dir.join("index.pidx"));
+    vidx.to_path(&output)?;
+    log::info!("Indexed {} packs into {:?}", vidx.pdsc_index.len(), output);
+    Ok(())
+}
+
+pub fn gc_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("gc")
+        .about("List the pack store, or remove old versions, partial downloads and unlisted packs")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("keep")
+                .long("keep")
+                .takes_value(true)
+                .value_name("N")
+                .help("Keep only the newest N versions of each pack"),
+        )
+        .arg(
+            Arg::with_name("partial")
+                .long("partial")
+                .help("Remove partial downloads"),
+        )
+        .arg(
+            Arg::with_name("unreferenced")
+                .long("unreferenced")
+                .help("Remove packs that are no longer in the vendor indexes"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only print what would be removed"),
+        )
+}
+
+pub fn gc_command(conf: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
+    let keep = match args.value_of("keep") {
+        Some(keep) => Some(
+            keep.parse()
+                .map_err(|_| anyhow::format_err!("Invalid value for --keep: {}", keep))?,
+        ),
+        None => None,
+    };
+    let referenced = if args.is_present("unreferenced") {
+        Some(crawl(conf, conf.read_vidx_list())?)
+    } else {
+        None
+    };
+    let options = PruneOptions {
+        keep,
+        partial: args.is_present("partial"),
+        referenced,
+        dry_run: args.is_present("dry-run"),
+        lock_timeout: conf.lock_timeout,
+    };
+    let pruning = options.keep.is_some() || options.partial || options.referenced.is_some();
+    let entries = if pruning {
+        store::prune(&conf.pack_store, &options)?
+    } else {
+        store::list(&conf.pack_store)?
+    };
+    for entry in entries.iter() {
+        let kind = match entry.kind {
+            EntryKind::Pdsc => "pdsc",
+            EntryKind::Pack => "pack",
+            EntryKind::Partial => "part",
+        };
+        println!(
+            "{} {:>12} {}.{} {} ({})",
+            kind,
+            entry.size,
+            entry.vendor,
+            entry.name,
+            entry.version,
+            entry.path.display()
+        );
+    }
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    match (pruning, options.dry_run) {
+        (false, _) => log::info!("{} files using {} bytes", entries.len(), total),
+        (true, true) => log::info!(
+            "Would remove {} files freeing {} bytes",
+            entries.len(),
+            total
+        ),
+        (true, false) => log::info!("Removed {} files freeing {} bytes", entries.len(), total),
+    }
+    Ok(())
+}
+
 pub fn dump_devices_args<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("dump-devices")
         .about("Dump devices as json")
@@ -129,6 +388,16 @@ pub fn dump_devices_args<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(true)
                 .help("Dump JSON in the specified file"),
         )
+        .arg(
+            Arg::with_name("rebuild")
+                .long("rebuild")
+                .help("Replace the existing contents of the JSON files"),
+        )
+        .arg(
+            Arg::with_name("legacy")
+                .long("legacy")
+                .help("Write JSON without a format version, as read by older tools"),
+        )
         .arg(
             Arg::with_name("INPUT")
                 .help("Input file to dump devices from")
@@ -136,33 +405,414 @@ pub fn dump_devices_args<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
+/// Parse `inputs`, or every pdsc in the pack store when there are none. The
+/// pack store is parsed through its package cache, under a shared lock; the
+/// cache takes its own lock to save.
+fn load_packages(c: &Config, inputs: Option<Vec<PathBuf>>) -> Result<Vec<Package>, Error> {
+    let parsed = match inputs {
+        Some(filenames) => parse_pdscs(&filenames),
+        None => {
+            let _lock = StoreLock::acquire(&c.pack_store, LockMode::Shared, c.lock_timeout)?;
+            let filenames: Vec<_> = c
+                .pack_store
+                .read_dir()
+                .map(|rd| {
+                    rd.flat_map(|dirent| dirent.into_iter().map(|p| p.path()))
+                        .filter(|path| path.extension().is_some_and(|ext| ext == "pdsc"))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let mut cache = PackageCache::open(c.pack_store.join(PACKAGE_CACHE))
+                .with_lock_timeout(c.lock_timeout);
+            let parsed = cache.load_all(&filenames);
+            cache.save()?;
+            parsed
+        }
+    };
+    Ok(parsed
+        .into_iter()
+        .filter_map(|parsed| parsed.ok_error())
+        .collect())
+}
+
 pub fn dump_devices_command(c: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
     let files = args
         .value_of("INPUT")
         .map(|input| vec![Path::new(input).to_path_buf()]);
-    let filenames = files
-        .or_else(|| {
-            c.pack_store.read_dir().ok().map(|rd| {
-                rd.flat_map(|dirent| dirent.into_iter().map(|p| p.path()))
-                    .collect()
-            })
-        })
-        .unwrap();
-    let pdscs = filenames
-        .into_iter()
-        .flat_map(|filename| match Package::from_path(&filename) {
-            Ok(c) => Some(c),
-            Err(e) => {
-                log::error!("parsing {:?}: {}", filename, e);
-                None
-            }
-        })
-        .collect::<Vec<Package>>();
-    let to_ret = dump_devices(&pdscs, args.value_of("devices"), args.value_of("boards"));
+    let pdscs = load_packages(c, files)?;
+    let format = if args.is_present("legacy") {
+        JsonFormat::Legacy
+    } else {
+        JsonFormat::Versioned
+    };
+    let options = DumpOptions {
+        rebuild: args.is_present("rebuild"),
+        format,
+        lock_timeout: c.lock_timeout,
+    };
+    let to_ret = dump_devices_with(
+        &pdscs,
+        args.value_of("devices"),
+        args.value_of("boards"),
+        options,
+    );
     log::debug!("exiting");
     to_ret
 }
 
+pub fn schema_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("schema")
+        .about("Print the JSON Schema of a JSON dump")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("DUMP")
+                .required(true)
+                .possible_values(&["devices", "boards", "components"])
+                .help("Dump to describe")
+                .index(1),
+        )
+}
+
+pub fn schema_command(args: &ArgMatches<'_>) -> Result<(), Error> {
+    let kind: DumpKind = args.value_of("DUMP").unwrap().parse()?;
+    println!("{}", json_schema(kind));
+    Ok(())
+}
+
+/// Look up the device called `name` in `packages`, along with the package it
+/// is described by, suggesting similar names when there is none.
+fn find_device<'a>(
+    packages: &'a [Package],
+    name: &str,
+) -> Result<(&'a Package, &'a Device), Error> {
+    let index = DeviceIndex::new(packages);
+    let IndexedDevice { device, pack, .. } = index.get(name).ok_or_else(|| {
+        anyhow::format_err!(
+            "No device {}; did you mean one of {}?",
+            name,
+            index.closest(name, 5).join(", ")
+        )
+    })?;
+    packages
+        .iter()
+        .filter(|package| {
+            package.vendor == pack.vendor
+                && package.name == pack.name
+                && package.releases.latest_release().version == pack.version
+        })
+        .find_map(|package| Some((package, package.devices.0.get(&device.name)?)))
+        .ok_or_else(|| anyhow::format_err!("No package describes {}", device.name))
+}
+
+pub fn gen_linker_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("gen-linker")
+        .about("Write a linker script from the memory map of a device")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("DEVICE")
+                .required(true)
+                .help("Name of the device")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("Pdsc files to find the device in instead of the pack store")
+                .index(2)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["gnu", "scatter", "icf"])
+                .default_value("gnu")
+                .help("Write a GNU ld MEMORY block, an armclang scatter file or an IAR icf file"),
+        )
+        .arg(
+            Arg::with_name("processor")
+                .short("p")
+                .long("processor")
+                .takes_value(true)
+                .help("Processor to link for, on devices with more than one"),
+        )
+        .arg(
+            Arg::with_name("secure")
+                .long("secure")
+                .conflicts_with("non-secure")
+                .help("Only use memories usable from the secure state"),
+        )
+        .arg(
+            Arg::with_name("non-secure")
+                .long("non-secure")
+                .help("Only use memories usable from the non-secure state"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Write the linker script to this file instead of printing it"),
+        )
+}
+
+pub fn gen_linker_command(c: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
+    let files = args
+        .values_of("INPUT")
+        .map(|inputs| inputs.map(PathBuf::from).collect());
+    let pdscs = load_packages(c, files)?;
+    let (_, device) = find_device(&pdscs, args.value_of("DEVICE").unwrap())?;
+    let format: LinkerFormat = args.value_of("format").unwrap().parse()?;
+    let mut options = LinkerOptions::new();
+    if let Some(processor) = args.value_of("processor") {
+        options = options.processor(processor);
+    }
+    if args.is_present("secure") {
+        options = options.security(Security::Secure);
+    } else if args.is_present("non-secure") {
+        options = options.security(Security::NonSecure);
+    }
+    let script = linker_script(device, format, &options)?;
+    match args.value_of("output") {
+        Some(output) => std::fs::write(output, script)
+            .map_err(|e| anyhow::format_err!("Could not write {}: {}", output, e))?,
+        None => print!("{}", script),
+    }
+    Ok(())
+}
+
+pub fn export_probe_rs_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("export-probe-rs")
+        .about("Write a probe-rs target description of a device, with its flash algorithms")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("DEVICE")
+                .required(true)
+                .help("Name of the device")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("Pdsc files to find the device in instead of the pack store")
+                .index(2)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("family")
+                .long("family")
+                .help("Describe every device in the family of the device"),
+        )
+        .arg(
+            Arg::with_name("pack")
+                .long("pack")
+                .takes_value(true)
+                .help("Pack archive or extracted pack to read flash algorithms from"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Write the YAML to this file instead of printing it"),
+        )
+}
+
+/// Open the pack given with `--pack`, or else the installed pack of
+/// `package`. The lock that comes with an installed pack keeps it from being
+/// pruned, and has to be held for as long as the pack is read.
+fn open_pack(
+    c: &Config,
+    args: &ArgMatches<'_>,
+    package: &Package,
+) -> Result<(Option<StoreLock>, PackFiles), Error> {
+    match args.value_of("pack") {
+        Some(pack) => Ok((None, PackFiles::open(pack)?)),
+        None => {
+            let lock = StoreLock::acquire(&c.pack_store, LockMode::Shared, c.lock_timeout)?;
+            let pack = PackFiles::for_package(package, Some(&c.pack_store))?;
+            Ok((Some(lock), pack))
+        }
+    }
+}
+
+pub fn export_probe_rs_command(c: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
+    let files = args
+        .values_of("INPUT")
+        .map(|inputs| inputs.map(PathBuf::from).collect());
+    let pdscs = load_packages(c, files)?;
+    let (package, device) = find_device(&pdscs, args.value_of("DEVICE").unwrap())?;
+    let mut devices: Vec<&Device> = if args.is_present("family") {
+        package
+            .devices
+            .0
+            .values()
+            .filter(|other| other.family == device.family)
+            .collect()
+    } else {
+        vec![device]
+    };
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    let (_lock, mut pack) = open_pack(c, args, package)?;
+    let yaml = ChipFamily::new(package, devices, &mut pack)?.to_yaml()?;
+    match args.value_of("output") {
+        Some(output) => std::fs::write(output, yaml)
+            .map_err(|e| anyhow::format_err!("Could not write {}: {}", output, e))?,
+        None => print!("{}", yaml),
+    }
+    Ok(())
+}
+
+pub fn export_pyocd_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("export-pyocd")
+        .about("Write a self-contained pyOCD target bundle of a device")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("DEVICE")
+                .required(true)
+                .help("Name of the device")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("Pdsc files to find the device in instead of the pack store")
+                .index(2)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("pack")
+                .long("pack")
+                .takes_value(true)
+                .help("Pack archive or extracted pack to read flash algorithms from"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Write the JSON to this file instead of printing it"),
+        )
+}
+
+pub fn export_pyocd_command(c: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
+    let files = args
+        .values_of("INPUT")
+        .map(|inputs| inputs.map(PathBuf::from).collect());
+    let pdscs = load_packages(c, files)?;
+    let (package, device) = find_device(&pdscs, args.value_of("DEVICE").unwrap())?;
+    let (_lock, mut pack) = open_pack(c, args, package)?;
+    let json = TargetBundle::new(package, device, &mut pack)?.to_json()?;
+    match args.value_of("output") {
+        Some(output) => std::fs::write(output, json)
+            .map_err(|e| anyhow::format_err!("Could not write {}: {}", output, e))?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+pub fn export_sqlite_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("export-sqlite")
+        .about("Write the devices, boards and components of packs to a SQLite database")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("OUTPUT")
+                .required(true)
+                .help("Database to create, replacing any existing file")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("Pdsc files to export instead of the pack store")
+                .index(2)
+                .multiple(true),
+        )
+}
+
+pub fn export_sqlite_command(c: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
+    let files = args
+        .values_of("INPUT")
+        .map(|inputs| inputs.map(PathBuf::from).collect());
+    let pdscs = load_packages(c, files)?;
+    let output = Path::new(args.value_of("OUTPUT").unwrap());
+    sqlite::export(&pdscs, output)?;
+    log::info!("Exported {} packs to {:?}", pdscs.len(), output);
+    Ok(())
+}
+
+pub fn svd_args<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("svd")
+        .about("Extract the SVD of a device from its pack")
+        .version("0.1.0")
+        .arg(
+            Arg::with_name("DEVICE")
+                .required(true)
+                .help("Name of the device")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("Pdsc files to find the device in instead of the pack store")
+                .index(2)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("processor")
+                .short("p")
+                .long("processor")
+                .takes_value(true)
+                .help("Processor of a multi-core device to extract the SVD of"),
+        )
+        .arg(
+            Arg::with_name("pack")
+                .long("pack")
+                .takes_value(true)
+                .help("Pack archive or extracted pack to read the SVD from"),
+        )
+        .arg(
+            Arg::with_name("peripherals")
+                .long("peripherals")
+                .help("List the peripherals of the device instead of writing the SVD"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Write to this file instead of printing"),
+        )
+}
+
+pub fn svd_command(c: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
+    let files = args
+        .values_of("INPUT")
+        .map(|inputs| inputs.map(PathBuf::from).collect());
+    let pdscs = load_packages(c, files)?;
+    let (package, device) = find_device(&pdscs, args.value_of("DEVICE").unwrap())?;
+    let (_lock, mut pack) = open_pack(c, args, package)?;
+    let svd = Svd::for_device(device, args.value_of("processor"), &mut pack)?;
+    let contents = if args.is_present("peripherals") {
+        let mut listing = String::new();
+        for peripheral in svd.peripherals()? {
+            let line = format!(
+                "{:#010x} {:<16} {}",
+                peripheral.base_address,
+                peripheral.name,
+                peripheral.description.unwrap_or_default()
+            );
+            listing.push_str(line.trim_end());
+            listing.push('\n');
+        }
+        listing.into_bytes()
+    } else {
+        svd.contents
+    };
+    match args.value_of("output") {
+        Some(output) => std::fs::write(output, contents)
+            .map_err(|e| anyhow::format_err!("Could not write {}: {}", output, e))?,
+        None => std::io::stdout().write_all(&contents)?,
+    }
+    Ok(())
+}
+
 pub fn check_args<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("check")
         .about("Check a project or pack for correct usage of the CMSIS standard")
@@ -173,13 +823,27 @@ pub fn check_args<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Fail when any element of the pack has to be left out"),
+        )
 }
 
 pub fn check_command(_: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
     let filename = args.value_of("INPUT").unwrap();
-    match Package::from_path(Path::new(filename)) {
-        Ok(c) => {
+    let mode = if args.is_present("strict") {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    };
+    match Package::from_path_with(Path::new(filename), mode) {
+        Ok((c, diagnostics)) => {
             log::info!("Parsing succedded");
+            for diagnostic in diagnostics.iter() {
+                log::warn!("{}:{}", filename, diagnostic);
+            }
+            log::info!("{} Parse Diagnostics", diagnostics.len());
             log::info!("{} Valid Conditions", c.conditions.0.len());
             let cond_lookup = c.make_condition_lookup();
             let mut num_components = 0;
@@ -221,13 +885,33 @@ pub fn check_command(_: &Config, args: &ArgMatches<'_>) -> Result<(), Error> {
                     }
                 }
             }
+            let mut devices: Vec<_> = c.devices.0.values().collect();
+            devices.sort_by(|a, b| a.name.cmp(&b.name));
+            let mut num_memory_issues = 0;
+            for device in devices {
+                for issue in device.check_memories() {
+                    num_memory_issues += 1;
+                    log::warn!("Device {}: {}", device.name, issue);
+                }
+            }
             log::info!("{} Valid Devices", c.devices.0.len());
+            log::info!("{} Memory Map Issues", num_memory_issues);
             log::info!("{} Valid Software Components", num_components);
             log::info!("{} Valid Files References", num_files);
         }
-        Err(e) => {
-            log::error!("parsing {}: {}", filename, e);
-        }
+        Err(e) => match e.downcast_ref::<ParseFailure>() {
+            Some(failure) if mode == ParseMode::Strict => {
+                for diagnostic in failure.diagnostics.iter() {
+                    log::error!("{}:{}", filename, diagnostic);
+                }
+                return Err(anyhow::format_err!(
+                    "{} failed strict parsing with {} problems",
+                    filename,
+                    failure.diagnostics.len()
+                ));
+            }
+            _ => log::error!("parsing {}: {}", filename, e),
+        },
     }
     log::debug!("exiting");
     Ok(())
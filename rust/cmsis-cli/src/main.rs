@@ -1,10 +1,13 @@
 extern crate clap;
 
-use anyhow::Error;
 use clap::{App, Arg};
 use cmsis_cli::{
-    check_args, check_command, dump_devices_args, dump_devices_command, install_args,
-    install_command, update_args, update_command, Config,
+    check_args, check_command, download_args, dump_devices_args, dump_devices_command,
+    export_probe_rs_args, export_probe_rs_command, export_pyocd_args, export_pyocd_command,
+    export_sqlite_args, export_sqlite_command, gc_args, gc_command, gen_index_args,
+    gen_index_command, gen_linker_args, gen_linker_command, install_args, install_command,
+    mirror_args, mirror_command, schema_args, schema_command, svd_args, svd_command, update_args,
+    update_command, Config,
 };
 
 fn main() {
@@ -15,10 +18,20 @@ fn main() {
                 .short("v")
                 .help("Sets the level of verbosity"),
         )
+        .args(&download_args())
         .subcommand(update_args())
         .subcommand(check_args())
         .subcommand(dump_devices_args())
+        .subcommand(export_probe_rs_args())
+        .subcommand(export_pyocd_args())
+        .subcommand(svd_args())
+        .subcommand(export_sqlite_args())
+        .subcommand(gen_linker_args())
         .subcommand(install_args())
+        .subcommand(mirror_args())
+        .subcommand(gen_index_args())
+        .subcommand(gc_args())
+        .subcommand(schema_args())
         .get_matches();
 
     simplelog::TermLogger::init(
@@ -33,28 +46,79 @@ fn main() {
     match matches.subcommand() {
         ("update", Some(sub_m)) => {
             Config::new()
-                .map_err(Error::from)
+                .and_then(|config| config.with_args(&matches))
                 .and_then(|config| update_command(&config, sub_m))
                 .unwrap();
         }
         ("install", Some(sub_m)) => {
             Config::new()
-                .map_err(Error::from)
+                .and_then(|config| config.with_args(&matches))
                 .and_then(|config| install_command(&config, sub_m))
                 .unwrap();
         }
         ("check", Some(sub_m)) => {
             Config::new()
-                .map_err(Error::from)
+                .and_then(|config| config.with_args(&matches))
                 .and_then(|config| check_command(&config, sub_m))
                 .unwrap();
         }
         ("dump-devices", Some(sub_m)) => {
             Config::new()
-                .map_err(Error::from)
+                .and_then(|config| config.with_args(&matches))
                 .and_then(|config| dump_devices_command(&config, sub_m))
                 .unwrap();
         }
+        ("export-probe-rs", Some(sub_m)) => {
+            Config::new()
+                .and_then(|config| config.with_args(&matches))
+                .and_then(|config| export_probe_rs_command(&config, sub_m))
+                .unwrap();
+        }
+        ("export-pyocd", Some(sub_m)) => {
+            Config::new()
+                .and_then(|config| config.with_args(&matches))
+                .and_then(|config| export_pyocd_command(&config, sub_m))
+                .unwrap();
+        }
+        ("svd", Some(sub_m)) => {
+            Config::new()
+                .and_then(|config| config.with_args(&matches))
+                .and_then(|config| svd_command(&config, sub_m))
+                .unwrap();
+        }
+        ("export-sqlite", Some(sub_m)) => {
+            Config::new()
+                .and_then(|config| config.with_args(&matches))
+                .and_then(|config| export_sqlite_command(&config, sub_m))
+                .unwrap();
+        }
+        ("gen-linker", Some(sub_m)) => {
+            Config::new()
+                .and_then(|config| config.with_args(&matches))
+                .and_then(|config| gen_linker_command(&config, sub_m))
+                .unwrap();
+        }
+        ("mirror", Some(sub_m)) => {
+            Config::new()
+                .and_then(|config| config.with_args(&matches))
+                .and_then(|config| mirror_command(&config, sub_m))
+                .unwrap();
+        }
+        ("gen-index", Some(sub_m)) => {
+            Config::new()
+                .and_then(|config| config.with_args(&matches))
+                .and_then(|config| gen_index_command(&config, sub_m))
+                .unwrap();
+        }
+        ("gc", Some(sub_m)) => {
+            Config::new()
+                .and_then(|config| config.with_args(&matches))
+                .and_then(|config| gc_command(&config, sub_m))
+                .unwrap();
+        }
+        ("schema", Some(sub_m)) => {
+            schema_command(sub_m).unwrap();
+        }
         (bad_command, Some(_)) => {
             println!("I did not understand the command {}", bad_command);
         }
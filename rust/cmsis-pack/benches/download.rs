@@ -0,0 +1,82 @@
+//! Measures download scheduling throughput against a local HTTP server.
+//!
+//! The server listens on several loopback addresses so that the per-host
+//! limit comes into play, and delays every response slightly to mimic a
+//! remote host. Where only 127.0.0.1 can be bound, as on macOS, every
+//! download goes to that one host. Run with
+//! `cargo bench -p cmsis-pack --bench download`.
+extern crate cmsis_pack;
+
+use std::env;
+use std::fs::remove_dir_all;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use cmsis_pack::update::{update, DownloadConfig};
+
+#[path = "../src/update/test_server.rs"]
+mod test_server;
+
+use test_server::{Reply, Server};
+
+const EXTRA_HOSTS: [&str; 3] = ["127.0.0.2", "127.0.0.3", "127.0.0.4"];
+const PDSCS_PER_HOST: usize = 500;
+const LATENCY: Duration = Duration::from_millis(5);
+
+struct BenchConfig(PathBuf);
+
+impl DownloadConfig for BenchConfig {
+    fn pack_store(&self) -> PathBuf {
+        self.0.clone()
+    }
+}
+
+fn pidx(port: u16, hosts: &[&str]) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n\
+         <index schemaVersion=\"1.0\">\n\
+         <vendor>Bench</vendor>\n\
+         <url>http://127.0.0.1/</url>\n\
+         <pindex>\n",
+    );
+    for host in hosts {
+        for n in 0..PDSCS_PER_HOST {
+            out.push_str(&format!(
+                "<pdsc url=\"http://{}:{}/\" vendor=\"Bench\" name=\"Pack{}\" version=\"1.0.0\"/>\n",
+                host, port, n
+            ));
+        }
+    }
+    out.push_str("</pindex>\n</index>\n");
+    out
+}
+
+fn main() {
+    let index = Arc::new(OnceLock::new());
+    let served = index.clone();
+    let server = Server::start(&EXTRA_HOSTS, LATENCY, move |path| {
+        if path.ends_with(".pidx") {
+            Reply::Body(served.get().cloned().unwrap_or_default())
+        } else {
+            Reply::Body(format!("<package><name>{}</name></package>", path))
+        }
+    });
+    let port = server.port;
+    index.set(pidx(port, &server.hosts)).unwrap();
+    let store = env::temp_dir().join(format!("cmsis-pack-bench-{}", std::process::id()));
+    let config = BenchConfig(store.clone());
+    let vidx = format!("http://127.0.0.1:{}/index.pidx", port);
+
+    let start = Instant::now();
+    let downloaded = update(&config, vec![vidx], ()).unwrap();
+    let elapsed = start.elapsed();
+    let _ = remove_dir_all(&store);
+
+    println!(
+        "downloaded {} pdscs in {:.2?} ({:.0} files/s)",
+        downloaded.len(),
+        elapsed,
+        downloaded.len() as f64 / elapsed.as_secs_f64()
+    );
+}
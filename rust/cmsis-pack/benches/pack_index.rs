@@ -1,42 +1,146 @@
-// #![feature(test)]
-// extern crate cmsis_pack;
-// extern crate test;
-
-// use cmsis_pack::pack_index::{Vidx, Pidx, PdscRef};
-// use cmsis_pack::parse::FromElem;
-// use test::Bencher;
-
-// trait BenchParse: FromElem {
-//     const SRC: &'static [u8];
-
-//     fn parse(b: &mut Bencher) {
-//         b.iter(|| {
-//             assert!(
-//                 Self::from_string(String::from_utf8_lossy(Self::SRC).into_owned().as_str()).is_ok()
-//             );
-//         });
-//     }
-// }
-
-// impl BenchParse for PdscRef {
-//     const SRC: &'static [u8] = include_bytes!("bench.pdsc");
-// }
-// impl BenchParse for Pidx {
-//     const SRC: &'static [u8] = include_bytes!("bench.pidx");
-// }
-// impl BenchParse for Vidx {
-//     const SRC: &'static [u8] = include_bytes!("bench.vidx");
-// }
-
-// #[bench]
-// fn parse_pdscref(b: &mut Bencher) {
-//     PdscRef::parse(b)
-// }
-// #[bench]
-// fn parse_pidx(b: &mut Bencher) {
-//     Pidx::parse(b)
-// }
-// #[bench]
-// fn parse_vidx(b: &mut Bencher) {
-//     Vidx::parse(b)
-// }
+//! Measures pdsc parsing throughput: one file at a time, in parallel, and
+//! from a warm package cache.
+//!
+//! The corpus is generated, with files about the size of vendor device
+//! family packs. Set `CMSIS_PACK_BENCH_DIR` to a directory of pdsc files,
+//! such as a Keil pack cache, to measure a real corpus instead. Run with
+//! `cargo bench -p cmsis-pack --bench pack_index`.
+extern crate cmsis_pack;
+
+use std::env;
+use std::fs::{create_dir_all, read_dir, remove_dir_all, write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use cmsis_pack::pack_index::Vidx;
+use cmsis_pack::pdsc::{parse_pdscs, Package, PackageCache};
+use cmsis_pack::utils::FromElem;
+
+const PACKS: usize = 200;
+const DEVICES_PER_PACK: usize = 60;
+
+fn pdsc(pack: usize) -> String {
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package schemaVersion=\"1.4\">\n\
+         <vendor>Bench</vendor>\n\
+         <name>Family{}_DFP</name>\n\
+         <description>Device family pack {}</description>\n\
+         <url>http://127.0.0.1/</url>\n\
+         <releases>\n\
+         <release version=\"1.2.0\" date=\"2024-01-01\">Second</release>\n\
+         <release version=\"1.1.0\" date=\"2023-01-01\">First</release>\n\
+         </releases>\n\
+         <devices>\n\
+         <family Dfamily=\"Family{}\" Dvendor=\"Bench:1\">\n\
+         <processor Dcore=\"Cortex-M4\" Dfpu=\"SP_FPU\" Dmpu=\"MPU\" Dendian=\"Little-endian\" Dclock=\"100000000\"/>\n\
+         <debug svd=\"SVD/Family.svd\"/>\n",
+        pack, pack, pack
+    );
+    for device in 0..DEVICES_PER_PACK {
+        let flash = 0x4_0000 << (device % 4);
+        out.push_str(&format!(
+            "<device Dname=\"BENCH{}X{}\">\n\
+             <memory id=\"IROM1\" start=\"0x08000000\" size=\"{:#x}\" startup=\"1\" default=\"1\"/>\n\
+             <memory id=\"IRAM1\" start=\"0x20000000\" size=\"0x20000\" init=\"0\" default=\"1\"/>\n\
+             <algorithm name=\"Flash/BENCH_{:x}.FLM\" start=\"0x08000000\" size=\"{:#x}\" RAMstart=\"0x20000000\" RAMsize=\"0x1000\" default=\"1\"/>\n\
+             </device>\n",
+            pack, device, flash, flash, flash
+        ));
+    }
+    out.push_str("</family>\n</devices>\n<components>\n");
+    for component in 0..20 {
+        out.push_str(&format!(
+            "<component Cclass=\"Device\" Cgroup=\"Driver\" Csub=\"Part{}\" Cversion=\"1.0.0\">\n\
+             <description>Driver {}</description>\n\
+             <files>\n\
+             <file category=\"source\" name=\"Drivers/part{}.c\"/>\n\
+             <file category=\"header\" name=\"Drivers/part{}.h\"/>\n\
+             </files>\n\
+             </component>\n",
+            component, component, component, component
+        ));
+    }
+    out.push_str("</components>\n</package>\n");
+    out
+}
+
+fn generate(dir: &Path) -> Vec<PathBuf> {
+    create_dir_all(dir).unwrap();
+    (0..PACKS)
+        .map(|pack| {
+            let path = dir.join(format!("Bench.Family{}_DFP.1.2.0.pdsc", pack));
+            write(&path, pdsc(pack)).unwrap();
+            path
+        })
+        .collect()
+}
+
+fn report(what: &str, files: usize, elapsed: Duration) {
+    println!(
+        "{:<24} {} files in {:.2?} ({:.0} files/s)",
+        what,
+        files,
+        elapsed,
+        files as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let scratch = env::temp_dir().join(format!("cmsis-pack-bench-{}", std::process::id()));
+    let paths: Vec<PathBuf> = match env::var_os("CMSIS_PACK_BENCH_DIR") {
+        Some(dir) => read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "pdsc"))
+            .collect(),
+        None => generate(&scratch.join("pdsc")),
+    };
+
+    let start = Instant::now();
+    let parsed = paths
+        .iter()
+        .filter(|path| Package::from_path(path).is_ok())
+        .count();
+    report("sequential", parsed, start.elapsed());
+
+    let start = Instant::now();
+    let parsed = parse_pdscs(&paths)
+        .into_iter()
+        .filter(Result::is_ok)
+        .count();
+    report("parallel", parsed, start.elapsed());
+
+    let cache_path = scratch.join("packages.cache");
+    let mut cache = PackageCache::open(&cache_path);
+    let start = Instant::now();
+    let parsed = cache
+        .load_all(&paths)
+        .into_iter()
+        .filter(Result::is_ok)
+        .count();
+    cache.save().unwrap();
+    report("cold cache", parsed, start.elapsed());
+
+    let start = Instant::now();
+    let mut cache = PackageCache::open(&cache_path);
+    let parsed = cache
+        .load_all(&paths)
+        .into_iter()
+        .filter(Result::is_ok)
+        .count();
+    cache.save().unwrap();
+    report("warm cache", parsed, start.elapsed());
+
+    let vidx = String::from_utf8_lossy(include_bytes!("bench.vidx")).into_owned();
+    let start = Instant::now();
+    for _ in 0..1000 {
+        Vidx::from_string(&vidx).unwrap();
+    }
+    println!(
+        "vidx                     parsed in {:.2?}",
+        start.elapsed() / 1000
+    );
+
+    let _ = remove_dir_all(&scratch);
+}
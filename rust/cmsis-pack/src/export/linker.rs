@@ -0,0 +1,441 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use anyhow::{format_err, Error};
+
+use crate::pdsc::{Device, Memory};
+
+/// The toolchain a linker script is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkerFormat {
+    /// A GNU ld `MEMORY` block, with `FLASH` and `RAM` aliases
+    Gnu,
+    /// An armclang (armlink) scatter file
+    Scatter,
+    /// An IAR ILINK `.icf` file
+    Icf,
+}
+
+impl FromStr for LinkerFormat {
+    type Err = Error;
+    fn from_str(from: &str) -> Result<Self, Error> {
+        match from {
+            "gnu" | "ld" => Ok(LinkerFormat::Gnu),
+            "scatter" | "armclang" => Ok(LinkerFormat::Scatter),
+            "icf" | "iar" => Ok(LinkerFormat::Icf),
+            unknown => Err(format_err!("Unknown linker script format {}", unknown)),
+        }
+    }
+}
+
+/// The security state an image is linked for, on devices with TrustZone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    Secure,
+    NonSecure,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LinkerOptions {
+    processor: Option<String>,
+    security: Option<Security>,
+}
+
+impl LinkerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only use the memories seen by the processor with this `Pname`.
+    /// Required for devices with more than one processor.
+    pub fn processor<S: Into<String>>(self, name: S) -> Self {
+        Self {
+            processor: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Only use the memories usable from `security`.
+    pub fn security(self, security: Security) -> Self {
+        Self {
+            security: Some(security),
+            ..self
+        }
+    }
+}
+
+/// A memory as placed in a linker script
+struct Region<'a> {
+    name: String,
+    memory: &'a Memory,
+}
+
+impl Region<'_> {
+    fn is_rom(&self) -> bool {
+        let access = &self.memory.access;
+        !access.write && (access.read || access.execute)
+    }
+
+    fn is_ram(&self) -> bool {
+        self.memory.access.write
+    }
+
+    fn is_veneer(&self) -> bool {
+        self.memory.access.non_secure_callable
+    }
+
+    fn attributes(&self) -> String {
+        let access = &self.memory.access;
+        [
+            (access.read, 'r'),
+            (access.write, 'w'),
+            (access.execute, 'x'),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, c)| *c)
+        .collect()
+    }
+}
+
+/// Memory names are used as identifiers in every format.
+fn identifier(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+/// The memory layout of a device, with the regions that code starts from
+/// and that data goes in by default.
+struct Layout<'a> {
+    name: String,
+    regions: Vec<Region<'a>>,
+    rom: usize,
+    ram: usize,
+}
+
+impl<'a> Layout<'a> {
+    fn new(device: &'a Device, options: &LinkerOptions) -> Result<Self, Error> {
+        let processors: Vec<&str> = device
+            .processors
+            .iter()
+            .filter_map(|p| p.name.as_deref())
+            .collect();
+        if let Some(name) = &options.processor {
+            if !processors.contains(&name.as_str()) {
+                return Err(format_err!(
+                    "Device {} has no processor {}",
+                    device.name,
+                    name
+                ));
+            }
+        } else if processors.len() > 1 {
+            return Err(format_err!(
+                "Device {} has processors {}; choose one",
+                device.name,
+                processors.join(", ")
+            ));
+        }
+
+        let memories: Vec<(&String, &Memory)> = device
+            .memories
+            .0
+            .iter()
+            .filter(|(_, memory)| match (&memory.p_name, &options.processor) {
+                (Some(p_name), Some(processor)) => p_name == processor,
+                _ => true,
+            })
+            .filter(|(_, memory)| {
+                let access = &memory.access;
+                let usable = match options.security {
+                    Some(Security::Secure) => !access.non_secure || access.secure,
+                    Some(Security::NonSecure) => {
+                        !(access.secure || access.non_secure_callable) || access.non_secure
+                    }
+                    None => true,
+                };
+                usable
+                    && memory.size != 0
+                    && !access.peripheral
+                    && (access.read || access.write || access.execute)
+            })
+            .collect();
+        let mut regions: Vec<Region> = memories
+            .iter()
+            .map(|(name, memory)| {
+                // A memory of this processor keeps its processor name when
+                // a shared memory has the same id
+                let id = memory.id(name);
+                let shadows = id != name.as_str() && memories.iter().any(|(other, _)| *other == id);
+                Region {
+                    name: identifier(if shadows { name } else { id }),
+                    memory,
+                }
+            })
+            .collect();
+        regions.sort_by(|a, b| (a.memory.start, &a.name).cmp(&(b.memory.start, &b.name)));
+
+        let pick = |wanted: fn(&Region) -> bool, what: &str| {
+            regions
+                .iter()
+                .position(|r| wanted(r) && r.memory.startup)
+                .or_else(|| regions.iter().position(|r| wanted(r) && r.memory.default))
+                .or_else(|| regions.iter().position(wanted))
+                .ok_or_else(|| format_err!("Device {} has no {} region", device.name, what))
+        };
+        let rom = pick(|r| r.is_rom() && !r.is_veneer(), "ROM")?;
+        let ram = pick(|r| r.is_ram() && !r.is_veneer(), "RAM")?;
+        let name = match &options.processor {
+            Some(processor) => format!("{} ({})", device.name, processor),
+            None => device.name.clone(),
+        };
+        Ok(Self {
+            name,
+            regions,
+            rom,
+            ram,
+        })
+    }
+
+    fn rom(&self) -> &Region<'a> {
+        &self.regions[self.rom]
+    }
+
+    fn ram(&self) -> &Region<'a> {
+        &self.regions[self.ram]
+    }
+}
+
+/// Write a linker script for `device` in `format`, placing code in its
+/// startup (or default) ROM region and data in its default RAM region. The
+/// other regions are declared but left for the user to place things in.
+pub fn linker_script(
+    device: &Device,
+    format: LinkerFormat,
+    options: &LinkerOptions,
+) -> Result<String, Error> {
+    let layout = Layout::new(device, options)?;
+    let mut out = String::new();
+    match format {
+        LinkerFormat::Gnu => write_gnu(&mut out, &layout),
+        LinkerFormat::Scatter => write_scatter(&mut out, &layout),
+        LinkerFormat::Icf => write_icf(&mut out, &layout),
+    }
+    .map_err(|e| format_err!("Could not write linker script: {}", e))?;
+    Ok(out)
+}
+
+fn write_gnu(out: &mut String, layout: &Layout) -> std::fmt::Result {
+    writeln!(out, "/* Memory map of {} */", layout.name)?;
+    writeln!(out, "MEMORY")?;
+    writeln!(out, "{{")?;
+    for region in layout.regions.iter() {
+        writeln!(
+            out,
+            "  {} ({}) : ORIGIN = {:#010x}, LENGTH = {:#010x}",
+            region.name,
+            region.attributes(),
+            region.memory.start,
+            region.memory.size
+        )?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "REGION_ALIAS(\"FLASH\", {});", layout.rom().name)?;
+    writeln!(out, "REGION_ALIAS(\"RAM\", {});", layout.ram().name)?;
+    Ok(())
+}
+
+fn write_scatter(out: &mut String, layout: &Layout) -> std::fmt::Result {
+    writeln!(out, "; Memory map of {}", layout.name)?;
+    let rom = layout.rom();
+    let ram = layout.ram();
+    writeln!(
+        out,
+        "LR_{} {:#010x} {:#010x} {{",
+        rom.name, rom.memory.start, rom.memory.size
+    )?;
+    writeln!(
+        out,
+        "  ER_{} {:#010x} {:#010x} {{",
+        rom.name, rom.memory.start, rom.memory.size
+    )?;
+    writeln!(out, "    *.o (RESET, +First)")?;
+    writeln!(out, "    *(InRoot$$Sections)")?;
+    writeln!(out, "    .ANY (+RO)")?;
+    writeln!(out, "    .ANY (+XO)")?;
+    writeln!(out, "  }}")?;
+    for region in layout.regions.iter().filter(|r| r.is_ram()) {
+        writeln!(
+            out,
+            "  RW_{} {:#010x} {:#010x} {{",
+            region.name, region.memory.start, region.memory.size
+        )?;
+        if region.name == ram.name {
+            writeln!(out, "    .ANY (+RW +ZI)")?;
+        }
+        writeln!(out, "  }}")?;
+    }
+    writeln!(out, "}}")?;
+    let others = layout
+        .regions
+        .iter()
+        .filter(|r| !r.is_ram() && r.name != rom.name);
+    for region in others {
+        writeln!(
+            out,
+            "LR_{} {:#010x} {:#010x} {{",
+            region.name, region.memory.start, region.memory.size
+        )?;
+        writeln!(
+            out,
+            "  ER_{} {:#010x} {:#010x} {{",
+            region.name, region.memory.start, region.memory.size
+        )?;
+        if region.is_veneer() {
+            writeln!(out, "    *(Veneer$$CMSE)")?;
+        }
+        writeln!(out, "  }}")?;
+        writeln!(out, "}}")?;
+    }
+    Ok(())
+}
+
+fn write_icf(out: &mut String, layout: &Layout) -> std::fmt::Result {
+    writeln!(out, "/* Memory map of {} */", layout.name)?;
+    writeln!(out, "define memory mem with size = 4G;")?;
+    for region in layout.regions.iter() {
+        writeln!(
+            out,
+            "define region {}_region = mem:[from {:#010x} size {:#010x}];",
+            region.name, region.memory.start, region.memory.size
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "define block CSTACK with alignment = 8, size = 0x400 {{ }};"
+    )?;
+    writeln!(
+        out,
+        "define block HEAP with alignment = 8, size = 0x400 {{ }};"
+    )?;
+    writeln!(out, "initialize by copy {{ readwrite }};")?;
+    writeln!(out, "do not initialize {{ section .noinit }};")?;
+    writeln!(out)?;
+    let rom = layout.rom();
+    writeln!(
+        out,
+        "place at address mem:{:#010x} {{ readonly section .intvec }};",
+        rom.memory.start
+    )?;
+    writeln!(out, "place in {}_region {{ readonly }};", rom.name)?;
+    writeln!(
+        out,
+        "place in {}_region {{ readwrite, block CSTACK, block HEAP }};",
+        layout.ram().name
+    )?;
+    for region in layout.regions.iter().filter(|r| r.is_veneer()) {
+        writeln!(
+            out,
+            "place in {}_region {{ section Veneer$$CMSE }};",
+            region.name
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pdsc::Package;
+    use crate::utils::FromElem;
+
+    fn device(body: &str) -> Device {
+        let pdsc = format!(
+            "<package><vendor>V</vendor><name>P</name><description/><url>./</url>\
+             <releases><release version=\"1.0.0\">r</release></releases>\
+             <devices><family Dfamily=\"F\"><device Dname=\"D\">{}</device></family>\
+             </devices></package>",
+            body
+        );
+        let mut package = Package::from_string(&pdsc).unwrap();
+        package.devices.0.remove("D").unwrap()
+    }
+
+    #[test]
+    fn trustzone_layouts() {
+        let device = device(
+            r#"<processor Dcore="Cortex-M33" DcoreVersion="r0p4"/>
+               <memory name="Flash_S" access="rxs" start="0x10000000" size="0x70000" startup="1" default="1"/>
+               <memory name="Flash_NSC" access="rxc" start="0x10070000" size="0x1000"/>
+               <memory name="Flash_NS" access="rxn" start="0x00080000" size="0x80000" default="1"/>
+               <memory name="SRAM_S" access="rwxs" start="0x30000000" size="0x10000" default="1"/>
+               <memory name="SRAM_NS" access="rwxn" start="0x20010000" size="0x10000" default="1"/>
+               <memory name="Periph" access="rwp" start="0x40000000" size="0x10000"/>"#,
+        );
+        let secure = LinkerOptions::new().security(Security::Secure);
+        let ld = linker_script(&device, LinkerFormat::Gnu, &secure).unwrap();
+        assert_eq!(
+            ld,
+            "/* Memory map of D */\n\
+             MEMORY\n\
+             {\n  \
+               Flash_S (rx) : ORIGIN = 0x10000000, LENGTH = 0x00070000\n  \
+               Flash_NSC (rx) : ORIGIN = 0x10070000, LENGTH = 0x00001000\n  \
+               SRAM_S (rwx) : ORIGIN = 0x30000000, LENGTH = 0x00010000\n\
+             }\n\
+             \n\
+             REGION_ALIAS(\"FLASH\", Flash_S);\n\
+             REGION_ALIAS(\"RAM\", SRAM_S);\n"
+        );
+        let scatter = linker_script(&device, LinkerFormat::Scatter, &secure).unwrap();
+        assert!(scatter.contains("LR_Flash_NSC 0x10070000 0x00001000 {\n  ER_Flash_NSC"));
+        assert!(scatter.contains("*(Veneer$$CMSE)"));
+
+        let non_secure = LinkerOptions::new().security(Security::NonSecure);
+        let icf = linker_script(&device, LinkerFormat::Icf, &non_secure).unwrap();
+        assert!(icf.contains("place in Flash_NS_region { readonly };"));
+        assert!(icf.contains("place in SRAM_NS_region { readwrite, block CSTACK, block HEAP };"));
+        assert!(!icf.contains("Flash_S"));
+    }
+
+    #[test]
+    fn multicore_layouts() {
+        let pdsc = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../tests/multicore/MyVendor.Multicore.pdsc"
+        );
+        let mut package = Package::from_path(pdsc.as_ref()).unwrap();
+        let device = package.devices.0.remove("DUAL1").unwrap();
+        assert!(linker_script(&device, LinkerFormat::Gnu, &LinkerOptions::new()).is_err());
+        let cm4 = LinkerOptions::new().processor("CM4");
+        let ld = linker_script(&device, LinkerFormat::Gnu, &cm4).unwrap();
+        assert_eq!(
+            ld,
+            "/* Memory map of DUAL1 (CM4) */\n\
+             MEMORY\n\
+             {\n  \
+               IROM1 (rx) : ORIGIN = 0x08100000, LENGTH = 0x00100000\n  \
+               IRAM1 (rw) : ORIGIN = 0x10000000, LENGTH = 0x00048000\n\
+             }\n\
+             \n\
+             REGION_ALIAS(\"FLASH\", IROM1);\n\
+             REGION_ALIAS(\"RAM\", IRAM1);\n"
+        );
+        let bad = LinkerOptions::new().processor("CM0");
+        assert!(linker_script(&device, LinkerFormat::Gnu, &bad).is_err());
+
+        let device = package.devices.0.remove("SHARED1").unwrap();
+        let b = LinkerOptions::new().processor("B");
+        let ld = linker_script(&device, LinkerFormat::Gnu, &b).unwrap();
+        assert!(ld.contains("  IRAM1 (rw) : ORIGIN = 0x20000000, LENGTH = 0x00010000\n"));
+        assert!(ld.contains("  IRAM1_B (rw) : ORIGIN = 0x30000000, LENGTH = 0x00008000\n"));
+        assert!(!ld.contains("IROM1_A"));
+    }
+}
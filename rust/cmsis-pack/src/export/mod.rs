@@ -0,0 +1,7 @@
+//! Writers of the catalogue of parsed packs in formats used by other tools.
+
+pub mod linker;
+pub mod probe_rs;
+pub mod pyocd;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
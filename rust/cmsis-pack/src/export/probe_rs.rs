@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{format_err, Error};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Serialize;
+
+use crate::flash::{FlashAlgorithm, FlashSector};
+use crate::pdsc::{AccessPort, Algorithm, Core, Device, Memory, Package};
+use crate::store::PackFiles;
+
+/// A probe-rs target description: a family of chips sharing flash
+/// algorithms, as read by `probe-rs` from a target YAML file.
+#[derive(Debug, Serialize)]
+pub struct ChipFamily {
+    pub name: String,
+    pub generated_from_pack: bool,
+    pub pack_file_release: String,
+    pub variants: Vec<Chip>,
+    pub flash_algorithms: Vec<RawFlashAlgorithm>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Chip {
+    pub name: String,
+    pub cores: Vec<ChipCore>,
+    pub memory_map: Vec<MemoryRegion>,
+    pub flash_algorithms: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChipCore {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub core_type: &'static str,
+    pub core_access_options: CoreAccessOptions,
+}
+
+#[derive(Debug, Serialize)]
+pub enum CoreAccessOptions {
+    Arm(ArmCoreAccessOptions),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArmCoreAccessOptions {
+    pub ap: ApAddress,
+}
+
+/// An access port, by index for ADIv5 or by address for ADIv6
+#[derive(Debug, Serialize)]
+pub enum ApAddress {
+    #[serde(rename = "v1")]
+    V1(u8),
+    #[serde(rename = "v2")]
+    V2(u64),
+}
+
+#[derive(Debug, Serialize)]
+pub enum MemoryRegion {
+    Ram(Region),
+    Nvm(Region),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Region {
+    pub name: String,
+    pub range: AddressRange,
+    pub cores: Vec<String>,
+    pub access: MemoryAccess,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddressRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemoryAccess {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub boot: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RawFlashAlgorithm {
+    pub name: String,
+    pub description: String,
+    pub default: bool,
+    /// The algorithm blob, in base64
+    pub instructions: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pc_init: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pc_uninit: Option<u32>,
+    pub pc_program_page: u32,
+    pub pc_erase_sector: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pc_erase_all: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pc_verify: Option<u32>,
+    pub data_section_offset: u32,
+    pub flash_properties: FlashProperties,
+    pub cores: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlashProperties {
+    pub address_range: AddressRange,
+    pub page_size: u32,
+    pub erased_byte_value: u8,
+    pub program_page_timeout: u32,
+    pub erase_sector_timeout: u32,
+    pub sectors: Vec<FlashSector>,
+}
+
+/// The probe-rs name of the architecture of `core`, for the cores probe-rs
+/// can debug.
+fn core_type(core: &Core) -> Option<&'static str> {
+    use Core::*;
+    match core {
+        CortexM0 | CortexM0Plus | CortexM1 | SC000 => Some("armv6m"),
+        CortexM3 | SC300 => Some("armv7m"),
+        CortexM4 | CortexM7 => Some("armv7em"),
+        CortexM23 | CortexM33 | CortexM35P | CortexM52 | CortexM55 | CortexM85 | StarMC1
+        | ARMV8MBL | ARMV8MML | ARMV81MML => Some("armv8m"),
+        CortexA5 | CortexA7 | CortexA8 | CortexA9 | CortexA15 | CortexA17 => Some("armv7a"),
+        CortexA32 | CortexA35 | CortexA53 | CortexA55 | CortexA57 | CortexA72 | CortexA73
+        | CortexA76 => Some("armv8a"),
+        _ => None,
+    }
+}
+
+/// Algorithm names are identifiers shared by every chip in the family.
+fn algorithm_name(algorithm: &Algorithm) -> String {
+    let stem = algorithm
+        .file_name
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    stem.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn cores(device: &Device) -> Result<Vec<ChipCore>, Error> {
+    let mut cores = Vec::new();
+    for (i, processor) in device.processors.iter().enumerate() {
+        let core_type = core_type(&processor.core).ok_or_else(|| {
+            format_err!(
+                "{} has a {} core, which probe-rs does not support",
+                device.name,
+                processor.core
+            )
+        })?;
+        let name = match &processor.name {
+            Some(name) if device.processors.len() > 1 && processor.unit > 0 => {
+                format!("{}_{}", name, processor.unit)
+            }
+            Some(name) => name.clone(),
+            None if i == 0 => "main".to_string(),
+            None => format!("core{}", i),
+        };
+        let ap = match processor.ap {
+            AccessPort::Index(index) => ApAddress::V1(index),
+            AccessPort::Address(address) => ApAddress::V2(address),
+        };
+        cores.push(ChipCore {
+            name,
+            core_type,
+            core_access_options: CoreAccessOptions::Arm(ArmCoreAccessOptions { ap }),
+        });
+    }
+    if cores.is_empty() {
+        return Err(format_err!("{} has no processors", device.name));
+    }
+    Ok(cores)
+}
+
+/// The cores of the processor named by a `Pname`, or all.
+fn processor_cores(p_name: Option<&str>, cores: &[ChipCore]) -> Vec<String> {
+    let names = cores.iter().map(|core| core.name.clone());
+    match p_name {
+        Some(p_name) => {
+            let unit_prefix = format!("{}_", p_name);
+            names
+                .filter(|name| name == p_name || name.starts_with(&unit_prefix))
+                .collect()
+        }
+        None => names.collect(),
+    }
+}
+
+fn chip(device: &Device, cores: Vec<ChipCore>, algorithms: Vec<String>) -> Chip {
+    let mut memories: Vec<(&String, &Memory)> = device.memories.0.iter().collect();
+    memories.sort_by_key(|(name, memory)| (memory.start, *name));
+    let memory_map = memories
+        .into_iter()
+        .filter(|(_, memory)| memory.size != 0 && !memory.access.peripheral)
+        .map(|(name, memory)| {
+            let region = Region {
+                name: name.clone(),
+                range: AddressRange {
+                    start: memory.start,
+                    end: memory.start.saturating_add(memory.size),
+                },
+                cores: processor_cores(memory.p_name.as_deref(), &cores),
+                access: MemoryAccess {
+                    read: memory.access.read,
+                    write: memory.access.write,
+                    execute: memory.access.execute,
+                    boot: memory.startup,
+                },
+            };
+            if memory.access.write {
+                MemoryRegion::Ram(region)
+            } else {
+                MemoryRegion::Nvm(region)
+            }
+        })
+        .collect();
+    Chip {
+        name: device.name.clone(),
+        cores,
+        memory_map,
+        flash_algorithms: algorithms,
+    }
+}
+
+fn raw_algorithm(
+    name: String,
+    algorithm: &Algorithm,
+    decoded: FlashAlgorithm,
+    cores: Vec<String>,
+) -> RawFlashAlgorithm {
+    RawFlashAlgorithm {
+        name,
+        description: decoded.name,
+        default: algorithm.default,
+        instructions: BASE64.encode(&decoded.instructions),
+        pc_init: decoded.pc_init,
+        pc_uninit: decoded.pc_uninit,
+        pc_program_page: decoded.pc_program_page,
+        pc_erase_sector: decoded.pc_erase_sector,
+        pc_erase_all: decoded.pc_erase_all,
+        pc_verify: decoded.pc_verify,
+        data_section_offset: decoded.data_section_offset,
+        flash_properties: FlashProperties {
+            // The pdsc decides where an algorithm is used, which may differ
+            // from the address it was built for
+            address_range: AddressRange {
+                start: algorithm.start,
+                end: algorithm.start.saturating_add(algorithm.size),
+            },
+            page_size: decoded.page_size,
+            erased_byte_value: decoded.erased_byte_value,
+            program_page_timeout: decoded.program_page_timeout,
+            erase_sector_timeout: decoded.erase_sector_timeout,
+            sectors: decoded.sectors,
+        },
+        cores,
+    }
+}
+
+impl ChipFamily {
+    /// Describe `devices`, all from `package`, with the flash algorithms
+    /// decoded from `pack`.
+    ///
+    /// Devices with cores probe-rs can't debug are left out, as are
+    /// algorithms that can't be decoded, with a warning.
+    pub fn new<'a, I>(package: &Package, devices: I, pack: &mut PackFiles) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a Device>,
+    {
+        let mut variants = Vec::new();
+        // Keyed by file and placement, as a file may be used at different
+        // addresses by different devices
+        let mut decoded: BTreeMap<(&Path, u64, u64), Option<String>> = BTreeMap::new();
+        let mut flash_algorithms: Vec<RawFlashAlgorithm> = Vec::new();
+        let mut family = None;
+        for device in devices {
+            let chip_cores = match cores(device) {
+                Ok(cores) => cores,
+                Err(e) => {
+                    log::warn!("Skipping {}", e);
+                    continue;
+                }
+            };
+            let mut names = Vec::new();
+            for algorithm in device.algorithms.iter() {
+                let key = (
+                    algorithm.file_name.as_path(),
+                    algorithm.start,
+                    algorithm.size,
+                );
+                if let Some(name) = decoded.get(&key) {
+                    names.extend(name.clone());
+                    continue;
+                }
+                let file_name = algorithm.file_name.to_string_lossy();
+                let flm = pack
+                    .read(&file_name)
+                    .and_then(|bytes| FlashAlgorithm::from_elf(&bytes));
+                let name = match flm {
+                    Ok(flm) => {
+                        let mut name = algorithm_name(algorithm);
+                        if flash_algorithms.iter().any(|algo| algo.name == name) {
+                            name = format!("{}_{:x}", name, algorithm.start);
+                        }
+                        let cores = processor_cores(algorithm.p_name.as_deref(), &chip_cores);
+                        flash_algorithms.push(raw_algorithm(name.clone(), algorithm, flm, cores));
+                        Some(name)
+                    }
+                    Err(e) => {
+                        log::warn!("Skipping flash algorithm {}: {}", file_name, e);
+                        None
+                    }
+                };
+                names.extend(name.clone());
+                decoded.insert(key, name);
+            }
+            family.get_or_insert_with(|| device.family.clone());
+            variants.push(chip(device, chip_cores, names));
+        }
+        let name = family.ok_or_else(|| format_err!("No devices probe-rs can debug"))?;
+        Ok(Self {
+            name,
+            generated_from_pack: true,
+            pack_file_release: package.releases.latest_release().version.clone(),
+            variants,
+            flash_algorithms,
+        })
+    }
+
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::test_pack::{package, TestPack};
+
+    #[test]
+    fn export_family() {
+        let test_pack = TestPack::new("probe-rs");
+        test_pack.flash_algo("Flash/Test.FLM");
+        let package = package(
+            r#"<family Dfamily="Test Series">
+                 <processor Dcore="Cortex-M4" Dfpu="SP_FPU"/>
+                 <algorithm name="Flash\Test.FLM" start="0x08000000" size="0x10000" default="1"/>
+                 <device Dname="TEST1">
+                   <memory id="IROM1" start="0x08000000" size="0x10000" startup="1" default="1"/>
+                   <memory id="IRAM1" start="0x20000000" size="0x5000" default="1"/>
+                 </device>
+                 <device Dname="TEST2">
+                   <memory id="IROM1" start="0x08000000" size="0x10000" startup="1" default="1"/>
+                   <memory id="IRAM1" start="0x20000000" size="0xA000" default="1"/>
+                   <algorithm name="Flash/Missing.FLM" start="0x90000000" size="0x1000"/>
+                 </device>
+               </family>"#,
+        );
+        let mut devices: Vec<&Device> = package.devices.0.values().collect();
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut pack = test_pack.files();
+        let family = ChipFamily::new(&package, devices, &mut pack).unwrap();
+        assert_eq!(family.name, "Test Series");
+        assert_eq!(family.variants.len(), 2);
+        assert_eq!(family.flash_algorithms.len(), 1);
+        assert_eq!(family.variants[1].flash_algorithms, vec!["test"]);
+
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&family.to_yaml().unwrap()).unwrap();
+        let chip = &yaml["variants"][0];
+        assert_eq!(chip["name"], "TEST1");
+        assert_eq!(chip["cores"][0]["type"], "armv7em");
+        let algo = &yaml["flash_algorithms"][0];
+        assert_eq!(algo["flash_properties"]["page_size"], 0x400);
+        assert_eq!(algo["pc_program_page"], 0x11);
+        let blob = BASE64
+            .decode(algo["instructions"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(blob.len(), 0x24);
+        let text = family.to_yaml().unwrap();
+        assert!(text.contains("!Nvm"));
+        assert!(text.contains("ap: !v1 0"));
+    }
+}
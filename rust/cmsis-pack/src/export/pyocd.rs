@@ -0,0 +1,305 @@
+use anyhow::{format_err, Error};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Serialize;
+
+use crate::flash::FlashAlgorithm;
+use crate::pdsc::{DebugVars, Device, Memory, Package, Processor, Sequence};
+use crate::store::PackFiles;
+
+/// Version of the bundle layout, increased when readers need to notice.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Everything pyOCD needs to connect to a device, without opening its pack:
+/// the memory map, the decoded flash algorithms, the access port of each
+/// core and its SVD, and the debug sequences.
+#[derive(Debug, Serialize)]
+pub struct TargetBundle<'a> {
+    pub format_version: u32,
+    pub name: &'a str,
+    pub vendor: Option<&'a str>,
+    pub family: &'a str,
+    pub sub_family: Option<&'a str>,
+    pub pack: BundlePack<'a>,
+    /// The cores, with their debug and access ports and SVD files
+    pub processors: &'a [Processor],
+    pub memory_map: Vec<BundleRegion<'a>>,
+    pub flash_algorithms: Vec<BundleAlgorithm>,
+    pub sequences: &'a [Sequence],
+    pub debug_vars: &'a [DebugVars],
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundlePack<'a> {
+    pub vendor: &'a str,
+    pub name: &'a str,
+    pub version: &'a str,
+    pub url: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleRegion<'a> {
+    pub name: &'a str,
+    /// One of `flash`, `rom`, `ram` or `device`
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub start: u64,
+    pub size: u64,
+    /// Any of `r`, `w` and `x`
+    pub access: String,
+    pub p_name: Option<&'a str>,
+    pub is_boot_memory: bool,
+    pub is_default: bool,
+    /// Indices in `flash_algorithms` of the algorithms that program this
+    /// region
+    pub algorithms: Vec<usize>,
+}
+
+/// A run of sectors of the same size, from `start` to the start of the next
+#[derive(Debug, Serialize)]
+pub struct BundleSector {
+    pub start: u64,
+    pub size: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleAlgorithm {
+    /// The FLM in the pack
+    pub file_name: String,
+    pub description: String,
+    /// The processor that uses the algorithm, or all when `None`
+    pub p_name: Option<String>,
+    pub default: bool,
+    pub start: u64,
+    pub size: u64,
+    /// RAM to run the algorithm in, when the pack gives one
+    pub ram_start: Option<u64>,
+    pub ram_size: Option<u64>,
+    pub page_size: u32,
+    pub erased_byte_value: u8,
+    pub program_page_timeout: u32,
+    pub erase_sector_timeout: u32,
+    pub sectors: Vec<BundleSector>,
+    /// The algorithm blob, in base64. Function addresses are offsets into it.
+    pub instructions: String,
+    pub data_section_offset: u32,
+    pub pc_init: Option<u32>,
+    pub pc_uninit: Option<u32>,
+    pub pc_program_page: u32,
+    pub pc_erase_sector: u32,
+    pub pc_erase_all: Option<u32>,
+    pub pc_verify: Option<u32>,
+    pub pc_blank_check: Option<u32>,
+}
+
+/// Whether `algorithm` programs (part of) `memory`, as seen by the same core.
+fn overlaps(memory: &Memory, algorithm: &BundleAlgorithm) -> bool {
+    let same_core = match (&memory.p_name, &algorithm.p_name) {
+        (Some(memory), Some(algorithm)) => memory == algorithm,
+        _ => true,
+    };
+    let memory_end = u128::from(memory.start) + u128::from(memory.size);
+    let algorithm_end = u128::from(algorithm.start) + u128::from(algorithm.size);
+    same_core
+        && u128::from(memory.start) < algorithm_end
+        && u128::from(algorithm.start) < memory_end
+}
+
+impl<'a> TargetBundle<'a> {
+    /// Bundle `device`, described by `package`, decoding its flash
+    /// algorithms from `pack`. Algorithms that can't be read or decoded are
+    /// left out, with a warning.
+    pub fn new(
+        package: &'a Package,
+        device: &'a Device,
+        pack: &mut PackFiles,
+    ) -> Result<Self, Error> {
+        let mut flash_algorithms = Vec::new();
+        for algorithm in device.algorithms.iter() {
+            let file_name = algorithm.file_name.to_string_lossy().into_owned();
+            let decoded = pack
+                .read(&file_name)
+                .and_then(|bytes| FlashAlgorithm::from_elf(&bytes));
+            let decoded = match decoded {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    log::warn!("Skipping flash algorithm {}: {}", file_name, e);
+                    continue;
+                }
+            };
+            let sectors = decoded
+                .sectors
+                .iter()
+                .map(|sector| BundleSector {
+                    start: algorithm.start.saturating_add(u64::from(sector.address)),
+                    size: sector.size,
+                })
+                .collect();
+            flash_algorithms.push(BundleAlgorithm {
+                file_name,
+                description: decoded.name,
+                p_name: algorithm.p_name.clone(),
+                default: algorithm.default,
+                start: algorithm.start,
+                size: algorithm.size,
+                ram_start: algorithm.ram_start,
+                ram_size: algorithm.ram_size,
+                page_size: decoded.page_size,
+                erased_byte_value: decoded.erased_byte_value,
+                program_page_timeout: decoded.program_page_timeout,
+                erase_sector_timeout: decoded.erase_sector_timeout,
+                sectors,
+                instructions: BASE64.encode(&decoded.instructions),
+                data_section_offset: decoded.data_section_offset,
+                pc_init: decoded.pc_init,
+                pc_uninit: decoded.pc_uninit,
+                pc_program_page: decoded.pc_program_page,
+                pc_erase_sector: decoded.pc_erase_sector,
+                pc_erase_all: decoded.pc_erase_all,
+                pc_verify: decoded.pc_verify,
+                pc_blank_check: decoded.pc_blank_check,
+            });
+        }
+
+        let mut memories: Vec<(&String, &Memory)> = device.memories.0.iter().collect();
+        memories.sort_by_key(|(name, memory)| (memory.start, *name));
+        let memory_map = memories
+            .into_iter()
+            .map(|(name, memory)| {
+                let algorithms: Vec<usize> = flash_algorithms
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, algorithm)| overlaps(memory, algorithm))
+                    .map(|(i, _)| i)
+                    .collect();
+                let access = &memory.access;
+                let kind = if access.peripheral {
+                    "device"
+                } else if !algorithms.is_empty() {
+                    "flash"
+                } else if access.write {
+                    "ram"
+                } else {
+                    "rom"
+                };
+                BundleRegion {
+                    name,
+                    kind,
+                    start: memory.start,
+                    size: memory.size,
+                    access: [
+                        (access.read, 'r'),
+                        (access.write, 'w'),
+                        (access.execute, 'x'),
+                    ]
+                    .iter()
+                    .filter(|(set, _)| *set)
+                    .map(|(_, c)| *c)
+                    .collect(),
+                    p_name: memory.p_name.as_deref(),
+                    is_boot_memory: memory.startup,
+                    is_default: memory.default,
+                    algorithms,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            format_version: BUNDLE_VERSION,
+            name: &device.name,
+            vendor: device.vendor.as_deref(),
+            family: &device.family,
+            sub_family: device.sub_family.as_deref(),
+            pack: BundlePack {
+                vendor: &package.vendor,
+                name: &package.name,
+                version: &package.releases.latest_release().version,
+                url: &package.url,
+            },
+            processors: &device.processors,
+            memory_map,
+            flash_algorithms,
+            sequences: &device.sequences,
+            debug_vars: &device.debug_vars,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| format_err!("Could not write bundle of {}: {}", self.name, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pdsc::SequenceStep;
+    use crate::store::test_pack::{package, TestPack};
+
+    #[test]
+    fn bundle_device() {
+        let test_pack = TestPack::new("pyocd");
+        test_pack.flash_algo("Flash/Test.FLM");
+        let package = package(
+            r#"<family Dfamily="Test Series">
+                 <processor Dcore="Cortex-M4" Dfpu="SP_FPU"/>
+                 <debug svd="SVD/Test.svd"/>
+                 <debugvars configfile="Debug/Test.dbgconf">__var DbgMCU_CR = 0x7;</debugvars>
+                 <sequences>
+                   <sequence name="DebugDeviceUnlock">
+                     <block>Sequence("CheckID");</block>
+                   </sequence>
+                   <sequence name="ResetSystem" disable="1"/>
+                 </sequences>
+                 <device Dname="TEST1">
+                   <memory id="IROM1" start="0x08000000" size="0x10000" startup="1" default="1"/>
+                   <memory id="IRAM1" start="0x20000000" size="0x5000" default="1"/>
+                   <algorithm name="Flash/Test.FLM" start="0x08000000" size="0x10000" RAMstart="0x20000000" RAMsize="0x1000" default="1"/>
+                   <sequences>
+                     <sequence name="DebugDeviceUnlock">
+                       <control if="DbgMCU_CR" timeout="1000">
+                         <block atomic="1">Write32(0xE0042004, DbgMCU_CR);</block>
+                       </control>
+                     </sequence>
+                   </sequences>
+                 </device>
+                 <device Dname="BAD">
+                   <algorithm name="Flash/Test.FLM" start="0xFFFFFFFFFFFFF000" size="0x1000"/>
+                 </device>
+               </family>"#,
+        );
+        let device = &package.devices.0["TEST1"];
+        let mut pack = test_pack.files();
+        let bundle = TargetBundle::new(&package, device, &mut pack).unwrap();
+        assert_eq!(bundle.memory_map[0].kind, "flash");
+        assert_eq!(bundle.memory_map[0].algorithms, vec![0]);
+        assert_eq!(bundle.memory_map[1].kind, "ram");
+        let algo = &bundle.flash_algorithms[0];
+        assert_eq!(algo.sectors[1].start, 0x0800_8000);
+        assert_eq!(algo.ram_start, Some(0x2000_0000));
+
+        // The device's own sequence replaces the family's
+        let unlock: Vec<&Sequence> = bundle
+            .sequences
+            .iter()
+            .filter(|seq| seq.name == "DebugDeviceUnlock")
+            .collect();
+        assert_eq!(unlock.len(), 1);
+        assert!(matches!(
+            &unlock[0].steps[0],
+            SequenceStep::Control { condition: Some(c), timeout: Some(1000), steps, .. }
+                if c == "DbgMCU_CR" && steps.len() == 1
+        ));
+        assert!(bundle.sequences.iter().any(|seq| seq.disable));
+
+        let json: serde_json::Value = serde_json::from_str(&bundle.to_json().unwrap()).unwrap();
+        assert_eq!(json["format_version"], BUNDLE_VERSION);
+        assert_eq!(json["processors"][0]["svd"], "SVD/Test.svd");
+        assert_eq!(json["debug_vars"][0]["code"], "__var DbgMCU_CR = 0x7;");
+
+        // Sectors past the end of the address space are clamped to it
+        let bad = TargetBundle::new(&package, &package.devices.0["BAD"], &mut pack).unwrap();
+        let sectors = &bad.flash_algorithms[0].sectors;
+        assert_eq!(sectors.last().unwrap().start, u64::MAX);
+    }
+}
@@ -0,0 +1,161 @@
+-- Schema of the catalogue written by `export::sqlite`.
+--
+-- Every table has an integer `id` key. Addresses and sizes are stored as
+-- integers, and flags as 0 or 1. Cores, FPUs and MPUs are spelled as in a
+-- pdsc, such as 'Cortex-M4' and 'SP_FPU'. Text that a pdsc may leave out is
+-- NULL when it does.
+
+-- The version of this schema, under the key 'schema_version', along with
+-- the version of cmsis-pack that wrote the database under 'generator'.
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+-- A pack, as described by its pdsc. `version` is its latest release.
+CREATE TABLE packs (
+    id INTEGER PRIMARY KEY,
+    vendor TEXT NOT NULL,
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    release_date TEXT,
+    url TEXT NOT NULL,
+    description TEXT NOT NULL,
+    license TEXT,
+    -- The pdsc or pack file the pack was read from
+    path TEXT,
+    UNIQUE (vendor, name, version)
+);
+
+-- A device, with the family and subfamily it was declared in.
+CREATE TABLE devices (
+    id INTEGER PRIMARY KEY,
+    pack_id INTEGER NOT NULL REFERENCES packs (id),
+    name TEXT NOT NULL,
+    vendor TEXT,
+    family TEXT NOT NULL,
+    sub_family TEXT,
+    UNIQUE (pack_id, name)
+);
+CREATE INDEX devices_name ON devices (name);
+
+-- A processor of a device; single core devices have one, with no name.
+CREATE TABLE processors (
+    id INTEGER PRIMARY KEY,
+    device_id INTEGER NOT NULL REFERENCES devices (id),
+    name TEXT,
+    unit INTEGER NOT NULL,
+    core TEXT NOT NULL,
+    fpu TEXT NOT NULL,
+    mpu TEXT NOT NULL,
+    -- Processor features, spelled as in a pdsc; endian, clock (in Hz) and
+    -- secure are NULL when the pack doesn't say
+    endian TEXT,
+    clock INTEGER,
+    dsp TEXT NOT NULL,
+    trustzone TEXT NOT NULL,
+    mve TEXT NOT NULL,
+    pacbti TEXT NOT NULL,
+    secure TEXT,
+    -- Debug port, and access port given either by index or by address
+    dp INTEGER NOT NULL,
+    ap_index INTEGER,
+    ap_address INTEGER,
+    -- Base address of the debug block, when given
+    address INTEGER,
+    svd TEXT,
+    default_reset_sequence TEXT
+);
+CREATE INDEX processors_device ON processors (device_id);
+
+-- A memory region of a device, by its id in the pdsc. `processor` names the
+-- processor it belongs to on multi-processor devices, where processors may
+-- each have a region of the same name.
+CREATE TABLE memories (
+    id INTEGER PRIMARY KEY,
+    device_id INTEGER NOT NULL REFERENCES devices (id),
+    name TEXT NOT NULL,
+    processor TEXT,
+    start INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    readable INTEGER NOT NULL,
+    writable INTEGER NOT NULL,
+    executable INTEGER NOT NULL,
+    peripheral INTEGER NOT NULL,
+    secure INTEGER NOT NULL,
+    non_secure INTEGER NOT NULL,
+    non_secure_callable INTEGER NOT NULL,
+    startup INTEGER NOT NULL,
+    is_default INTEGER NOT NULL
+);
+CREATE INDEX memories_device ON memories (device_id);
+
+-- A flash algorithm of a device, with the range of memory it programs and
+-- the RAM it runs from. `processor` names the processor it is for on
+-- multi-processor devices.
+CREATE TABLE algorithms (
+    id INTEGER PRIMARY KEY,
+    device_id INTEGER NOT NULL REFERENCES devices (id),
+    file_name TEXT NOT NULL,
+    processor TEXT,
+    start INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    ram_start INTEGER,
+    ram_size INTEGER,
+    is_default INTEGER NOT NULL,
+    -- One of 'Keil', 'IAR' or 'CMSIS'
+    style TEXT NOT NULL
+);
+CREATE INDEX algorithms_device ON algorithms (device_id);
+
+-- A development board.
+CREATE TABLE boards (
+    id INTEGER PRIMARY KEY,
+    pack_id INTEGER NOT NULL REFERENCES packs (id),
+    name TEXT NOT NULL
+);
+
+-- The devices mounted on a board, by name, as the device may be declared in
+-- another pack or in none at all. Join on `devices.name` to find it.
+CREATE TABLE board_devices (
+    board_id INTEGER NOT NULL REFERENCES boards (id),
+    device_name TEXT NOT NULL
+);
+CREATE INDEX board_devices_board ON board_devices (board_id);
+CREATE INDEX board_devices_device ON board_devices (device_name);
+
+-- A software component, identified by its vendor, class, group, subgroup,
+-- variant and version.
+CREATE TABLE components (
+    id INTEGER PRIMARY KEY,
+    pack_id INTEGER NOT NULL REFERENCES packs (id),
+    vendor TEXT NOT NULL,
+    class TEXT NOT NULL,
+    "group" TEXT NOT NULL,
+    sub_group TEXT,
+    variant TEXT,
+    version TEXT NOT NULL,
+    api_version TEXT,
+    condition TEXT,
+    max_instances INTEGER,
+    is_default INTEGER NOT NULL,
+    deprecated INTEGER NOT NULL,
+    description TEXT NOT NULL
+);
+CREATE INDEX components_pack ON components (pack_id);
+
+-- A file of a component. `category` is one of the categories of a pdsc,
+-- written as in `FileCategory`, such as 'Header' or 'SourceC'; `attr` is
+-- 'Config', 'Template' or NULL.
+CREATE TABLE component_files (
+    id INTEGER PRIMARY KEY,
+    component_id INTEGER NOT NULL REFERENCES components (id),
+    path TEXT NOT NULL,
+    category TEXT NOT NULL,
+    attr TEXT,
+    condition TEXT,
+    "select" TEXT,
+    src TEXT,
+    version TEXT
+);
+CREATE INDEX component_files_component ON component_files (component_id);
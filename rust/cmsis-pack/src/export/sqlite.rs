@@ -0,0 +1,350 @@
+use std::ffi::OsString;
+use std::fs::{remove_file, rename};
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Error};
+use rusqlite::{params, Connection, Transaction};
+
+use crate::pdsc::{AccessPort, Device, Package};
+
+/// The tables written by `export`, documented in `schema.sql`.
+pub const SCHEMA: &str = include_str!("schema.sql");
+
+/// Increased whenever `SCHEMA` changes in a way readers could notice.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Write the devices, boards and components of `packages` to a new SQLite
+/// database at `path`, replacing any file already there.
+///
+/// The database is built next to `path` and renamed over it once complete,
+/// so readers never see a partial catalogue. A pack listed more than once is
+/// written once.
+pub fn export<'a, I>(packages: I, path: &Path) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a Package>,
+{
+    let mut temp: OsString = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let _ = remove_file(&temp);
+    let res = write_database(packages, &temp);
+    if let Err(err) = res {
+        let _ = remove_file(&temp);
+        return Err(format_err!("Could not write {:?}: {}", path, err));
+    }
+    rename(&temp, path).map_err(|e| format_err!("Could not replace {:?}: {}", path, e))
+}
+
+fn write_database<'a, I>(packages: I, path: &Path) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a Package>,
+{
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    tx.execute(
+        "INSERT INTO meta (key, value) VALUES ('schema_version', ?1), ('generator', ?2)",
+        params![
+            SCHEMA_VERSION.to_string(),
+            concat!("cmsis-pack ", env!("CARGO_PKG_VERSION"))
+        ],
+    )?;
+    for package in packages {
+        insert_package(&tx, package)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn insert_package(tx: &Transaction, package: &Package) -> Result<(), Error> {
+    let release = package.releases.latest_release();
+    let inserted = tx.execute(
+        "INSERT OR IGNORE INTO packs
+         (vendor, name, version, release_date, url, description, license, path)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            package.vendor,
+            package.name,
+            release.version,
+            release.date,
+            package.url,
+            package.description,
+            package.license,
+            package.path.as_ref().map(|p| p.to_string_lossy()),
+        ],
+    )?;
+    if inserted == 0 {
+        log::debug!(
+            "Skipping {}.{} {}, already exported",
+            package.vendor,
+            package.name,
+            release.version
+        );
+        return Ok(());
+    }
+    let pack_id = tx.last_insert_rowid();
+
+    let mut devices: Vec<&Device> = package.devices.0.values().collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    for device in devices {
+        insert_device(tx, pack_id, device)?;
+    }
+
+    let mut board_stmt = tx.prepare_cached("INSERT INTO boards (pack_id, name) VALUES (?1, ?2)")?;
+    let mut mounted_stmt =
+        tx.prepare_cached("INSERT INTO board_devices (board_id, device_name) VALUES (?1, ?2)")?;
+    for board in package.boards.iter() {
+        board_stmt.execute(params![pack_id, board.name()])?;
+        let board_id = tx.last_insert_rowid();
+        for device in board.mounted_devices() {
+            mounted_stmt.execute(params![board_id, device])?;
+        }
+    }
+
+    let mut component_stmt = tx.prepare_cached(
+        "INSERT INTO components
+         (pack_id, vendor, class, \"group\", sub_group, variant, version, api_version,
+          condition, max_instances, is_default, deprecated, description)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+    )?;
+    let mut file_stmt = tx.prepare_cached(
+        "INSERT INTO component_files
+         (component_id, path, category, attr, condition, \"select\", src, version)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+    for component in package.make_components() {
+        component_stmt.execute(params![
+            pack_id,
+            component.vendor,
+            component.class,
+            component.group,
+            component.sub_group,
+            component.variant,
+            component.version,
+            component.api_version,
+            component.condition,
+            component.max_instances,
+            component.is_default,
+            component.deprecated,
+            component.description,
+        ])?;
+        let component_id = tx.last_insert_rowid();
+        for file in component.files.iter() {
+            file_stmt.execute(params![
+                component_id,
+                file.path.to_string_lossy(),
+                format!("{:?}", file.category()),
+                file.attr().map(|attr| format!("{:?}", attr)),
+                file.condition,
+                file.select(),
+                file.src(),
+                file.version(),
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+fn insert_device(tx: &Transaction, pack_id: i64, device: &Device) -> Result<(), Error> {
+    tx.prepare_cached(
+        "INSERT INTO devices (pack_id, name, vendor, family, sub_family)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?
+    .execute(params![
+        pack_id,
+        device.name,
+        device.vendor,
+        device.family,
+        device.sub_family,
+    ])?;
+    let device_id = tx.last_insert_rowid();
+
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO processors
+         (device_id, name, unit, core, fpu, mpu, endian, clock, dsp, trustzone, mve, pacbti,
+          secure, dp, ap_index, ap_address, address, svd, default_reset_sequence)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+          ?18, ?19)",
+    )?;
+    for processor in device.processors.iter() {
+        let (ap_index, ap_address) = match processor.ap {
+            AccessPort::Index(index) => (Some(index), None),
+            AccessPort::Address(address) => (None, Some(address as i64)),
+        };
+        stmt.execute(params![
+            device_id,
+            processor.name,
+            processor.unit as i64,
+            processor.core.to_string(),
+            processor.fpu.to_string(),
+            processor.mpu.to_string(),
+            processor.endian.as_ref().map(ToString::to_string),
+            processor.clock.map(|clock| clock as i64),
+            processor.dsp.to_string(),
+            processor.trustzone.to_string(),
+            processor.mve.to_string(),
+            processor.pacbti.to_string(),
+            processor.secure.as_ref().map(ToString::to_string),
+            processor.dp,
+            ap_index,
+            ap_address,
+            processor.address,
+            processor.svd,
+            processor.default_reset_sequence,
+        ])?;
+    }
+
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO memories
+         (device_id, name, processor, start, size, readable, writable, executable,
+          peripheral, secure, non_secure, non_secure_callable, startup, is_default)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+    )?;
+    let mut memories: Vec<_> = device.memories.0.iter().collect();
+    memories.sort_by_key(|(name, memory)| (memory.start, *name));
+    for (name, memory) in memories {
+        let access = &memory.access;
+        stmt.execute(params![
+            device_id,
+            memory.id(name),
+            memory.p_name,
+            memory.start as i64,
+            memory.size as i64,
+            access.read,
+            access.write,
+            access.execute,
+            access.peripheral,
+            access.secure,
+            access.non_secure,
+            access.non_secure_callable,
+            memory.startup,
+            memory.default,
+        ])?;
+    }
+
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO algorithms
+         (device_id, file_name, processor, start, size, ram_start, ram_size, is_default,
+          style)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?;
+    for algorithm in device.algorithms.iter() {
+        stmt.execute(params![
+            device_id,
+            algorithm.file_name.to_string_lossy(),
+            algorithm.p_name,
+            algorithm.start as i64,
+            algorithm.size as i64,
+            algorithm.ram_start.map(|start| start as i64),
+            algorithm.ram_size.map(|size| size as i64),
+            algorithm.default,
+            format!("{:?}", algorithm.style),
+        ])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::FromElem;
+
+    #[test]
+    fn export_test_pack() {
+        let pdsc = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../tests/test-pack-index/MyVendor.MyPack.pdsc"
+        );
+        let package = Package::from_path(Path::new(pdsc)).unwrap();
+        let dir = std::env::temp_dir().join(format!("cmsis-sqlite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("catalogue.db");
+        export([&package, &package], &path).unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        let count = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(count("packs"), 1);
+        assert_eq!(count("devices"), 1);
+        let (core, algo): (String, String) = conn
+            .query_row(
+                "SELECT p.core, a.file_name FROM devices d
+                 JOIN processors p ON p.device_id = d.id
+                 JOIN algorithms a ON a.device_id = d.id
+                 WHERE d.name = 'MyDevice'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (core.as_str(), algo.as_str()),
+            ("Cortex-M0+", "flash/algo.FLM")
+        );
+        let mounted: String = conn
+            .query_row(
+                "SELECT bd.device_name FROM boards b
+                 JOIN board_devices bd ON bd.board_id = b.id WHERE b.name = 'MyBoard'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(mounted, "MyDevice");
+        drop(conn);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn export_multicore_pack() {
+        let pdsc = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../tests/multicore/MyVendor.Multicore.pdsc"
+        );
+        let package = Package::from_path(Path::new(pdsc)).unwrap();
+        let dir = std::env::temp_dir().join(format!("cmsis-sqlite-mc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("catalogue.db");
+        export([&package], &path).unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT m.name, m.processor FROM devices d
+                 JOIN memories m ON m.device_id = d.id
+                 WHERE d.name = 'DUAL1' ORDER BY m.processor, m.name",
+            )
+            .unwrap();
+        let memories: Vec<(String, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let memories: Vec<_> = memories
+            .iter()
+            .map(|(name, p_name)| (name.as_str(), p_name.as_str()))
+            .collect();
+        assert_eq!(
+            memories,
+            [
+                ("IRAM1", "CM4"),
+                ("IROM1", "CM4"),
+                ("IRAM1", "CM7"),
+                ("IROM1", "CM7")
+            ]
+        );
+        let processor: String = conn
+            .query_row(
+                "SELECT a.processor FROM algorithms a WHERE a.file_name = 'Flash/Bank2.FLM'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(processor, "CM4");
+        drop(stmt);
+        drop(conn);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+//! Flash algorithms, as shipped in packs as `.FLM` files.
+//!
+//! An FLM is an ELF file holding position independent code in a `PrgCode`
+//! section, its data in a `PrgData` section and a `FlashDevice` structure
+//! describing the flash it programs, as defined by `FlashOS.h`.
+
+use std::ops::Range;
+
+use anyhow::{format_err, Error};
+use goblin::elf::section_header::SHT_NOBITS;
+use goblin::elf::Elf;
+use serde::{Deserialize, Serialize};
+
+/// Size of `FlashDevice` up to its list of sectors
+const DEVICE_HEADER: usize = 160;
+const DEVICE_NAME: Range<usize> = 2..130;
+/// Marks the end of the sector list
+const SECTOR_END: (u32, u32) = (0xFFFF_FFFF, 0xFFFF_FFFF);
+
+/// A run of sectors of the same size, starting at `address`, an offset from
+/// the start of the flash. Each run lasts until the next one starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlashSector {
+    pub size: u32,
+    pub address: u32,
+}
+
+/// A decoded flash algorithm. Function addresses are offsets into
+/// `instructions`, including the Thumb bit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlashAlgorithm {
+    /// `DevName` from `FlashDevice`
+    pub name: String,
+    pub version: u16,
+    pub device_type: u16,
+    /// Start of the flash programmed by this algorithm
+    pub address: u32,
+    pub size: u32,
+    pub page_size: u32,
+    pub erased_byte_value: u8,
+    /// Timeouts in milliseconds
+    pub program_page_timeout: u32,
+    pub erase_sector_timeout: u32,
+    pub sectors: Vec<FlashSector>,
+    /// The code followed by the data of the algorithm, to be loaded as one
+    pub instructions: Vec<u8>,
+    pub data_section_offset: u32,
+    pub pc_init: Option<u32>,
+    pub pc_uninit: Option<u32>,
+    pub pc_program_page: u32,
+    pub pc_erase_sector: u32,
+    pub pc_erase_all: Option<u32>,
+    pub pc_verify: Option<u32>,
+    pub pc_blank_check: Option<u32>,
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// The bytes of a section in memory; sections without contents in the file
+/// are zero filled.
+fn section_bytes(elf: &Elf, bytes: &[u8], name: &str) -> Result<Option<(u64, Vec<u8>)>, Error> {
+    let header = elf
+        .section_headers
+        .iter()
+        .find(|sh| elf.shdr_strtab.get_at(sh.sh_name) == Some(name));
+    let header = match header {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let contents = if header.sh_type == SHT_NOBITS {
+        vec![0; header.sh_size as usize]
+    } else {
+        header
+            .file_range()
+            .and_then(|range| bytes.get(range))
+            .ok_or_else(|| format_err!("Section {} is outside of the file", name))?
+            .to_vec()
+    };
+    Ok(Some((header.sh_addr, contents)))
+}
+
+impl FlashAlgorithm {
+    /// Decode the contents of an FLM file.
+    pub fn from_elf(bytes: &[u8]) -> Result<Self, Error> {
+        let elf = Elf::parse(bytes).map_err(|e| format_err!("Not an ELF file: {}", e))?;
+        let symbol = |name: &str| -> Option<u64> {
+            elf.syms
+                .iter()
+                .find(|sym| elf.strtab.get_at(sym.st_name) == Some(name))
+                .map(|sym| sym.st_value)
+        };
+        let function = |name: &str| symbol(name).map(|addr| addr as u32);
+        let required = |name: &str| {
+            function(name).ok_or_else(|| format_err!("No {} function in the algorithm", name))
+        };
+
+        let (code_start, code) = section_bytes(&elf, bytes, "PrgCode")?
+            .ok_or_else(|| format_err!("No PrgCode section in the algorithm"))?;
+        if code_start != 0 {
+            return Err(format_err!("PrgCode starts at {:#x}, not 0", code_start));
+        }
+        let mut instructions = code;
+        let data_section_offset = match section_bytes(&elf, bytes, "PrgData")? {
+            Some((start, data)) => {
+                let start = start as usize;
+                if start < instructions.len() {
+                    return Err(format_err!("PrgData overlaps PrgCode"));
+                }
+                instructions.resize(start, 0);
+                instructions.extend(data);
+                start as u32
+            }
+            None => instructions.len() as u32,
+        };
+
+        let (descr_start, descr) = section_bytes(&elf, bytes, "DevDscr")?
+            .ok_or_else(|| format_err!("No DevDscr section in the algorithm"))?;
+        let device = symbol("FlashDevice").unwrap_or(descr_start);
+        let device = descr
+            .get(device.wrapping_sub(descr_start) as usize..)
+            .filter(|device| device.len() >= DEVICE_HEADER)
+            .ok_or_else(|| format_err!("FlashDevice is outside of DevDscr"))?;
+        let name = &device[DEVICE_NAME];
+        let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+        let sectors = device[DEVICE_HEADER..]
+            .chunks_exact(8)
+            .map(|sector| (read_u32(sector, 0), read_u32(sector, 4)))
+            .take_while(|&sector| sector != SECTOR_END)
+            .map(|(size, address)| FlashSector { size, address })
+            .collect();
+
+        Ok(Self {
+            name: String::from_utf8_lossy(name).into_owned(),
+            version: read_u16(device, 0),
+            device_type: read_u16(device, 130),
+            address: read_u32(device, 132),
+            size: read_u32(device, 136),
+            page_size: read_u32(device, 140),
+            erased_byte_value: device[148],
+            program_page_timeout: read_u32(device, 152),
+            erase_sector_timeout: read_u32(device, 156),
+            sectors,
+            instructions,
+            data_section_offset,
+            pc_init: function("Init"),
+            pc_uninit: function("UnInit"),
+            pc_program_page: required("ProgramPage")?,
+            pc_erase_sector: required("EraseSector")?,
+            pc_erase_all: function("EraseChip"),
+            pc_verify: function("Verify"),
+            pc_blank_check: function("BlankCheck"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_FLM: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../tests/flash-algo/Test.FLM"
+    );
+
+    #[test]
+    fn decode_flm() {
+        let algo = FlashAlgorithm::from_elf(&std::fs::read(TEST_FLM).unwrap()).unwrap();
+        assert_eq!(algo.name, "Test 64kB Flash");
+        assert_eq!(
+            (algo.address, algo.size, algo.page_size),
+            (0x0800_0000, 0x1_0000, 0x400)
+        );
+        assert_eq!(algo.erased_byte_value, 0xFF);
+        assert_eq!(
+            (algo.program_page_timeout, algo.erase_sector_timeout),
+            (100, 3000)
+        );
+        assert_eq!(
+            algo.sectors,
+            vec![
+                FlashSector {
+                    size: 0x1000,
+                    address: 0
+                },
+                FlashSector {
+                    size: 0x4000,
+                    address: 0x8000
+                },
+            ]
+        );
+        assert_eq!(algo.instructions.len(), 0x24);
+        assert_eq!(algo.data_section_offset, 0x20);
+        assert_eq!(
+            (algo.pc_init, algo.pc_program_page, algo.pc_erase_sector),
+            (Some(0x1), 0x11, 0xd)
+        );
+        assert_eq!(algo.pc_verify, None);
+
+        assert!(FlashAlgorithm::from_elf(b"not an elf").is_err());
+    }
+}
@@ -1,7 +1,11 @@
 #![allow(clippy::upper_case_acronyms)]
 
+pub mod export;
+pub mod flash;
 pub mod pack_index;
 pub mod pdsc;
+pub mod store;
+pub mod svd;
 pub mod update;
 #[macro_use]
 pub mod utils;
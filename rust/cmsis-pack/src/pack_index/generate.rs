@@ -0,0 +1,192 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::{read_dir, File};
+use std::io::copy;
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Error};
+
+use crate::pack_index::{PdscRef, Vidx};
+use crate::pdsc::Package;
+use crate::utils::compare_versions;
+use crate::utils::prelude::*;
+
+/// Build a vendor index of every `.pack` and `.pdsc` file in `dir` and its
+/// subdirectories, with each entry published under `url` plus the path of its
+/// subdirectory.
+///
+/// When a pack is present in several versions, only the newest is listed.
+/// Entries read from a `.pack` also record the size of the pack. A pack
+/// without its `Vendor.Name.pdsc` next to it has the pdsc extracted, as the
+/// index entry points there.
+pub fn index_directory(dir: &Path, vendor: &str, url: &str) -> Result<Vidx, Error> {
+    let mut entries: BTreeMap<(String, String), Found> = BTreeMap::new();
+    collect_entries(dir, url, &mut entries)?;
+    let pdsc_index = entries
+        .into_values()
+        .filter_map(|found| match (found.pdsc, found.pack) {
+            (false, Some(pack)) => match extract_pdsc(&pack, &found.entry) {
+                Ok(()) => Some(found.entry),
+                Err(err) => {
+                    log::warn!("Skipping {:?}: {}", pack, err);
+                    None
+                }
+            },
+            _ => Some(found.entry),
+        })
+        .collect();
+    Ok(Vidx {
+        vendor: vendor.to_string(),
+        url: url.to_string(),
+        timestamp: None,
+        pdsc_index,
+        vendor_index: Vec::new(),
+    })
+}
+
+struct Found {
+    entry: PdscRef,
+    /// The pack the entry was read from, if any
+    pack: Option<PathBuf>,
+    /// Whether `Vendor.Name.pdsc` exists next to the pack
+    pdsc: bool,
+}
+
+fn extract_pdsc(pack: &Path, entry: &PdscRef) -> Result<(), Error> {
+    let dest = pack.with_file_name(format!("{}.{}.pdsc", entry.vendor, entry.name));
+    let mut archive = zip::ZipArchive::new(File::open(pack)?)?;
+    let name = archive
+        .file_names()
+        .find(|name| !name.contains('/') && name.ends_with(".pdsc"))
+        .map(String::from)
+        .ok_or_else(|| format_err!("No pdsc found in {:?}", pack))?;
+    log::info!("Extracting {:?} from {:?}", dest, pack);
+    copy(&mut archive.by_name(&name)?, &mut File::create(&dest)?)
+        .map_err(|e| format_err!("Could not write {:?}: {}", dest, e))?;
+    Ok(())
+}
+
+fn collect_entries(
+    dir: &Path,
+    url: &str,
+    entries: &mut BTreeMap<(String, String), Found>,
+) -> Result<(), Error> {
+    let mut paths = read_dir(dir)
+        .map_err(|e| format_err!("Could not read {:?}: {}", dir, e))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let sub_url = format!("{}/{}/", url.trim_end_matches('/'), name);
+            collect_entries(&path, &sub_url, entries)?;
+            continue;
+        }
+        let (package, size) = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pdsc") => (Package::from_path(&path), None),
+            Some("pack") => (
+                Package::from_pack_file(&path),
+                path.metadata().ok().map(|m| m.len().to_string()),
+            ),
+            _ => continue,
+        };
+        let package = match package {
+            Ok(package) => package,
+            Err(err) => {
+                log::warn!("Skipping {:?}: {}", path, err);
+                continue;
+            }
+        };
+        let release = package.releases.latest_release();
+        let entry = PdscRef {
+            url: url.to_string(),
+            vendor: package.vendor.clone(),
+            name: package.name.clone(),
+            version: release.version.clone(),
+            date: release.date.clone(),
+            deprecated: None,
+            replacement: None,
+            size,
+        };
+        let key = (entry.vendor.clone(), entry.name.clone());
+        let found = match entry.size {
+            Some(_) => Found {
+                pdsc: path
+                    .with_file_name(format!("{}.{}.pdsc", entry.vendor, entry.name))
+                    .exists(),
+                pack: Some(path.clone()),
+                entry,
+            },
+            None => Found {
+                entry,
+                pack: None,
+                pdsc: true,
+            },
+        };
+        match entries.get_mut(&key) {
+            Some(existing) => match compare_versions(&found.entry.version, &existing.entry.version)
+            {
+                Ordering::Greater => *existing = found,
+                // A pack and its unpacked pdsc: keep the size of the pack
+                Ordering::Equal if existing.entry.url == found.entry.url => {
+                    if existing.pack.is_none() {
+                        existing.entry.size = found.entry.size;
+                        existing.pack = found.pack;
+                    }
+                    existing.pdsc |= found.pdsc;
+                }
+                _ => {}
+            },
+            None => {
+                entries.insert(key, found);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn index_test_packs() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/test-pack-index");
+        let vidx = index_directory(Path::new(dir), "MyVendor", "http://example.com/").unwrap();
+        let pack = Path::new(dir).join("MyVendor.MyPack.1.1.0.pack");
+        assert_eq!(
+            vidx.pdsc_index,
+            vec![PdscRef {
+                url: String::from("http://example.com/"),
+                vendor: String::from("MyVendor"),
+                name: String::from("MyPack"),
+                version: String::from("1.1.0"),
+                date: None,
+                deprecated: None,
+                replacement: None,
+                size: Some(pack.metadata().unwrap().len().to_string()),
+            }]
+        );
+        let xml = vidx.to_xml_string();
+        assert_eq!(Vidx::from_string(&xml).unwrap(), vidx);
+    }
+
+    #[test]
+    fn index_pack_in_subdirectory() {
+        let src = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/test-pack-index");
+        let dir = std::env::temp_dir().join(format!("cmsis-gen-index-{}", std::process::id()));
+        let sub = dir.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let pack = sub.join("MyVendor.MyPack.1.1.0.pack");
+        std::fs::copy(Path::new(src).join("MyVendor.MyPack.1.1.0.pack"), &pack).unwrap();
+
+        let vidx = index_directory(&dir, "MyVendor", "http://example.com/packs").unwrap();
+        let pdsc = sub.join("MyVendor.MyPack.pdsc");
+        let extracted = pdsc.exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(vidx.pdsc_index.len(), 1);
+        assert_eq!(vidx.pdsc_index[0].url, "http://example.com/packs/sub/");
+        assert!(extracted);
+    }
+}
@@ -2,7 +2,11 @@ use crate::utils::prelude::*;
 use anyhow::Error;
 use roxmltree::Node;
 
-#[derive(Debug, Clone)]
+mod generate;
+
+pub use generate::index_directory;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct PdscRef {
     pub url: String,
     pub vendor: String,
@@ -14,14 +18,14 @@ pub struct PdscRef {
     pub size: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Pidx {
     pub url: String,
     pub vendor: String,
     pub date: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Vidx {
     pub vendor: String,
     pub url: String,
@@ -91,6 +95,73 @@ impl FromElem for Vidx {
     }
 }
 
+impl ToElem for PdscRef {
+    fn to_elem(&self, w: &mut XmlWriter) {
+        w.empty(
+            "pdsc",
+            &[
+                ("url", Some(&self.url)),
+                ("vendor", Some(&self.vendor)),
+                ("name", Some(&self.name)),
+                ("version", Some(&self.version)),
+                ("date", self.date.as_deref()),
+                ("deprecated", self.deprecated.as_deref()),
+                ("replacement", self.replacement.as_deref()),
+                ("size", self.size.as_deref()),
+            ],
+        );
+    }
+}
+
+impl ToElem for Pidx {
+    fn to_elem(&self, w: &mut XmlWriter) {
+        w.empty(
+            "pidx",
+            &[
+                ("url", Some(&self.url)),
+                ("vendor", Some(&self.vendor)),
+                ("date", self.date.as_deref()),
+            ],
+        );
+    }
+}
+
+impl ToElem for Vidx {
+    fn to_elem(&self, w: &mut XmlWriter) {
+        w.start(
+            "index",
+            &[
+                ("schemaVersion", Some("1.0.0")),
+                (
+                    "xmlns:xs",
+                    Some("http://www.w3.org/2001/XMLSchema-instance"),
+                ),
+                ("xs:noNamespaceSchemaLocation", Some("PackIndex.xsd")),
+            ],
+        );
+        w.text("vendor", &self.vendor);
+        w.text("url", &self.url);
+        if let Some(timestamp) = &self.timestamp {
+            w.text("timestamp", timestamp);
+        }
+        if !self.vendor_index.is_empty() {
+            w.start("vindex", &[]);
+            for pidx in self.vendor_index.iter() {
+                pidx.to_elem(w);
+            }
+            w.end("vindex");
+        }
+        if !self.pdsc_index.is_empty() {
+            w.start("pindex", &[]);
+            for pdsc in self.pdsc_index.iter() {
+                pdsc.to_elem(w);
+            }
+            w.end("pindex");
+        }
+        w.end("index");
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -119,7 +190,7 @@ mod test {
     #[test]
     fn pdscref_optionals() {
         let good_string =
-            "<pdsc vendor=\"Vendor\" url=\"Url\" name=\"Name\" version=\"1.2.3-alpha\">";
+            "<pdsc vendor=\"Vendor\" url=\"Url\" name=\"Name\" version=\"1.2.3-alpha\"/>";
         let response = PdscRef::from_string(good_string).unwrap();
         assert_eq!(response.vendor, String::from("Vendor"));
         assert_eq!(response.url, "Url");
@@ -127,7 +198,7 @@ mod test {
         assert_eq!(response.version, String::from("1.2.3-alpha"));
         let good_string =
             "<pdsc vendor=\"Vendor\" url=\"Url\" name=\"Name\" version=\"1.2.3-alpha\"
-                date=\"A-Date\" deprecated=\"true\" replacement=\"Other\" size=\"8MB\">";
+                date=\"A-Date\" deprecated=\"true\" replacement=\"Other\" size=\"8MB\"/>";
         let response = PdscRef::from_string(good_string).unwrap();
         assert_eq!(response.date, Some(String::from("A-Date")));
         assert_eq!(response.deprecated, Some(String::from("true")));
@@ -217,7 +288,74 @@ mod test {
         assert_eq!(response.url, "Url");
         assert_eq!(
             response.timestamp,
-            Some(String::from("Fri Sep  1 13:26:41 CDT"))
+            Some(String::from("Fri Sep  1 13:26:41 CDT 2017"))
+        );
+    }
+
+    #[test]
+    fn pdscref_round_trip() {
+        let pdsc = PdscRef {
+            url: String::from("http://example.com/packs?a=1&b=2"),
+            vendor: String::from("Vendor"),
+            name: String::from("Name"),
+            version: String::from("1.2.3-alpha"),
+            date: Some(String::from("2017-09-01")),
+            deprecated: None,
+            replacement: Some(String::from("\"Other\" <Pack>")),
+            size: Some(String::from("8MB")),
+        };
+        let xml = pdsc.to_xml_string();
+        assert!(xml.contains("url=\"http://example.com/packs?a=1&amp;b=2\""));
+        assert!(!xml.contains("deprecated"));
+        assert_eq!(PdscRef::from_string(&xml).unwrap(), pdsc);
+    }
+
+    #[test]
+    fn pidx_round_trip() {
+        let pidx = Pidx {
+            url: String::from("http://example.com/"),
+            vendor: String::from("Vendor"),
+            date: Some(String::from("Fri Sep  1 11:21:06 CDT 2017")),
+        };
+        assert_eq!(Pidx::from_string(&pidx.to_xml_string()).unwrap(), pidx);
+    }
+
+    #[test]
+    fn vidx_round_trip() {
+        let mut vidx = Vidx {
+            vendor: String::from("Vendor & Co"),
+            url: String::from("./"),
+            timestamp: None,
+            pdsc_index: Vec::new(),
+            vendor_index: Vec::new(),
+        };
+        assert_eq!(Vidx::from_string(&vidx.to_xml_string()).unwrap(), vidx);
+
+        vidx.timestamp = Some(String::from("2017-01-25T15:00:10.7300074+00:00"));
+        vidx.vendor_index.push(Pidx {
+            url: String::from("http://example.com/"),
+            vendor: String::from("Other"),
+            date: None,
+        });
+        vidx.pdsc_index.push(PdscRef {
+            url: String::from("./"),
+            vendor: String::from("Vendor"),
+            name: String::from("Name"),
+            version: String::from("1.0.0"),
+            date: None,
+            deprecated: Some(String::from("2020-01-01")),
+            replacement: None,
+            size: None,
+        });
+        let xml = vidx.to_xml_string();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        assert_eq!(Vidx::from_string(&xml).unwrap(), vidx);
+
+        let index = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../tests/test-pack-index/index.pidx"
         );
+        let parsed = Vidx::from_path(std::path::Path::new(index)).unwrap();
+        assert_eq!(Vidx::from_string(&parsed.to_xml_string()).unwrap(), parsed);
     }
 }
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{read, remove_file, rename, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::pdsc::Package;
+use crate::store::{LockMode, StoreLock};
+use crate::utils::prelude::*;
+use crate::utils::{parallel_map, Diagnostic, ParseMode};
+
+/// Name of the cache file kept alongside the pdsc files of a pack store
+pub const PACKAGE_CACHE: &str = ".packages.cache";
+
+/// Increased whenever `Package` or the parsing of pdsc files changes in a
+/// way that would make cached packages differ from freshly parsed ones.
+const CACHE_VERSION: u32 = 2;
+
+/// Written at the start of the cache file. Caches written by another version
+/// of this crate, or of the cache, may hold a different model, so they are
+/// discarded.
+fn format() -> String {
+    format!(
+        "cmsis-pack package cache {} version {}",
+        env!("CARGO_PKG_VERSION"),
+        CACHE_VERSION
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// Seconds and nanoseconds since the epoch
+    mtime: (u64, u32),
+    size: u64,
+    hash: u64,
+    /// The parsed `Package`, encoded on its own so that a bad entry only
+    /// costs a reparse of its file
+    package: Vec<u8>,
+    /// What the parse left out, reported again whenever the entry is used
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A change to make to the cache once a file has been looked up
+enum Update {
+    None,
+    Touch((u64, u32)),
+    Insert(Entry),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    format: String,
+    entries: HashMap<PathBuf, Entry>,
+}
+
+/// FNV-1a, which unlike the hasher in std is stable between releases.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn report(path: &Path, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        log::warn!("{}:{}", path.display(), diagnostic);
+    }
+}
+
+fn stat(path: &Path) -> Result<((u64, u32), u64), Error> {
+    let meta = path
+        .metadata()
+        .map_err(|e| format_err!("Could not read {:?}: {}", path, e))?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|d| (d.as_secs(), d.subsec_nanos()))
+        .unwrap_or_default();
+    Ok((mtime, meta.len()))
+}
+
+/// Parsed pdsc files, kept on disk between runs so that only the files that
+/// changed since the last run are parsed again.
+///
+/// Files are looked up by path. An entry is used as is when the modification
+/// time and size of the file are unchanged; otherwise the file is read, and
+/// only parsed again when its contents hash differently. What a parse left
+/// out is kept with its entry and logged on every load, as for a fresh parse.
+pub struct PackageCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, Entry>,
+    dirty: bool,
+    lock_timeout: Option<Duration>,
+}
+
+impl PackageCache {
+    /// Open the cache stored in `path`. A missing, corrupt or outdated cache
+    /// starts out empty.
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let entries = match read(&path) {
+            Ok(bytes) => match bincode::deserialize::<CacheFile>(&bytes) {
+                Ok(cache) if cache.format == format() => cache.entries,
+                Ok(_) => {
+                    log::info!("Discarding {:?}, written by another version", path);
+                    HashMap::new()
+                }
+                Err(e) => {
+                    log::warn!("Discarding corrupt cache {:?}: {}", path, e);
+                    HashMap::new()
+                }
+            },
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path,
+            entries,
+            dirty: false,
+            lock_timeout: None,
+        }
+    }
+
+    /// Wait at most `timeout` for other processes saving the same cache,
+    /// rather than indefinitely.
+    pub fn with_lock_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            lock_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Parse the pdsc file at `path`, reusing the result of an earlier parse
+    /// when the file has not changed.
+    pub fn load(&mut self, path: &Path) -> Result<Package, Error> {
+        let (package, update) = self.lookup(path)?;
+        self.apply(path, update);
+        Ok(package)
+    }
+
+    /// Parse every file in `paths` in parallel, returning a result for each
+    /// file in the same order.
+    pub fn load_all<P: AsRef<Path> + Sync>(&mut self, paths: &[P]) -> Vec<Result<Package, Error>> {
+        let found = parallel_map(paths, |path| {
+            let path = path.as_ref();
+            self.lookup(path)
+                .map_err(|e| format_err!("parsing {:?}: {}", path, e))
+        });
+        paths
+            .iter()
+            .zip(found)
+            .map(|(path, found)| {
+                let (package, update) = found?;
+                self.apply(path.as_ref(), update);
+                Ok(package)
+            })
+            .collect()
+    }
+
+    fn apply(&mut self, path: &Path, update: Update) {
+        match update {
+            Update::None => {}
+            Update::Touch(mtime) => {
+                if let Some(entry) = self.entries.get_mut(path) {
+                    entry.mtime = mtime;
+                }
+                self.dirty = true;
+            }
+            Update::Insert(entry) => {
+                self.entries.insert(path.to_path_buf(), entry);
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn lookup(&self, path: &Path) -> Result<(Package, Update), Error> {
+        let (mtime, size) = stat(path)?;
+        let mut contents = None;
+        if let Some(entry) = self.entries.get(path) {
+            let unchanged = if entry.mtime == mtime && entry.size == size {
+                true
+            } else {
+                let bytes =
+                    read(path).map_err(|e| format_err!("Could not read {:?}: {}", path, e))?;
+                let same = entry.hash == hash(&bytes);
+                contents = Some(bytes);
+                same
+            };
+            if unchanged {
+                match bincode::deserialize::<Package>(&entry.package) {
+                    Ok(package) => {
+                        let update = if entry.mtime != mtime {
+                            Update::Touch(mtime)
+                        } else {
+                            Update::None
+                        };
+                        let package = Package {
+                            path: Some(path.to_path_buf()),
+                            ..package
+                        };
+                        report(path, &entry.diagnostics);
+                        return Ok((package, update));
+                    }
+                    Err(e) => log::warn!("Discarding cached {:?}: {}", path, e),
+                }
+            }
+        }
+
+        let bytes = match contents {
+            Some(bytes) => bytes,
+            None => read(path).map_err(|e| format_err!("Could not read {:?}: {}", path, e))?,
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        let (package, diagnostics) = Package::from_string_with(&text, ParseMode::Lenient)?;
+        let package = Package {
+            path: Some(path.to_path_buf()),
+            ..package
+        };
+        report(path, &diagnostics);
+        let entry = Entry {
+            mtime,
+            size,
+            hash: hash(&bytes),
+            package: bincode::serialize(&package)?,
+            diagnostics,
+        };
+        Ok((package, Update::Insert(entry)))
+    }
+
+    /// Write the cache back to disk if anything changed, leaving out files
+    /// that no longer exist. The new cache replaces the old one atomically.
+    ///
+    /// Saves from several processes are taken in turn under a lock on the
+    /// cache file, and the last one wins: entries only another process added
+    /// are lost, which costs a reparse of those files later.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let before = self.entries.len();
+        self.entries.retain(|path, _| path.exists());
+        if !self.dirty && self.entries.len() == before {
+            return Ok(());
+        }
+        let _lock = StoreLock::acquire_file(&self.path, LockMode::Exclusive, self.lock_timeout)?;
+        let cache = CacheFile {
+            format: format(),
+            entries: std::mem::take(&mut self.entries),
+        };
+        // Named after the process, as readers of the pack store may share
+        // the same cache file
+        let mut temp: OsString = self.path.as_os_str().to_owned();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        let temp = PathBuf::from(temp);
+        let res = File::create(&temp).map_err(Error::from).and_then(|fd| {
+            let mut writer = BufWriter::new(fd);
+            bincode::serialize_into(&mut writer, &cache)?;
+            writer.flush()?;
+            Ok(())
+        });
+        self.entries = cache.entries;
+        if let Err(err) = res {
+            let _ = remove_file(&temp);
+            return Err(format_err!("Could not write {:?}: {}", temp, err));
+        }
+        rename(&temp, &self.path)
+            .map_err(|e| format_err!("Could not replace {:?}: {}", self.path, e))?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{copy, create_dir_all, write};
+
+    #[test]
+    fn reuses_unchanged_files() {
+        let dir = std::env::temp_dir().join(format!("cmsis-cache-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../tests/test-pack-index/MyVendor.MyPack.pdsc"
+        );
+        let pdsc = dir.join("MyVendor.MyPack.pdsc");
+        copy(fixture, &pdsc).unwrap();
+        let cache_path = dir.join("packages.cache");
+
+        let mut cache = PackageCache::open(&cache_path);
+        let first = cache.load(&pdsc).unwrap();
+        cache.save().unwrap();
+        assert!(cache_path.exists());
+
+        // A hit does not need to parse the file again
+        let mut cache = PackageCache::open(&cache_path);
+        assert_eq!(cache.entries.len(), 1);
+        let second = cache.load(&pdsc).unwrap();
+        assert_eq!(second.name, first.name);
+        assert_eq!(second.path.as_deref(), Some(pdsc.as_path()));
+        assert!(!cache.dirty);
+
+        assert!(cache.entries[&pdsc].diagnostics.is_empty());
+
+        // A changed file is parsed again
+        let text = std::fs::read_to_string(&pdsc).unwrap();
+        write(
+            &pdsc,
+            text.replace("<name>MyPack</name>", "<name>Renamed</name>"),
+        )
+        .unwrap();
+        assert_eq!(cache.load(&pdsc).unwrap().name, "Renamed");
+
+        // Saves wait for other processes saving the same cache
+        let held = StoreLock::acquire_file(&cache_path, LockMode::Exclusive, None).unwrap();
+        let mut cache = cache.with_lock_timeout(Some(Duration::from_millis(200)));
+        assert!(cache.save().is_err());
+        drop(held);
+
+        // A cache of another model starts over
+        cache.save().unwrap();
+        let mut outdated: CacheFile = bincode::deserialize(&read(&cache_path).unwrap()).unwrap();
+        assert_eq!(outdated.entries.len(), 1);
+        outdated.format = format().replace(
+            &format!("version {}", CACHE_VERSION),
+            &format!("version {}", CACHE_VERSION - 1),
+        );
+        write(&cache_path, bincode::serialize(&outdated).unwrap()).unwrap();
+        assert!(PackageCache::open(&cache_path).entries.is_empty());
+
+        // Removed files are dropped, and a corrupt cache starts over
+        std::fs::remove_file(&pdsc).unwrap();
+        cache.save().unwrap();
+        assert!(PackageCache::open(&cache_path).entries.is_empty());
+        write(&cache_path, "garbage").unwrap();
+        assert!(PackageCache::open(&cache_path).entries.is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_diagnostics() {
+        let dir = std::env::temp_dir().join(format!("cmsis-cache-diag-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let pdsc = dir.join("V.P.pdsc");
+        write(
+            &pdsc,
+            r#"<package><vendor>V</vendor><name>P</name><description/><url>./</url>
+               <releases><release version="1.0.0">r</release></releases>
+               <devices><family Dfamily="F"><processor Dcore="Cortex-M4"/>
+                 <device Dname="D"><memory id="IRAM1" start="zero" size="0x100"/></device>
+               </family></devices></package>"#,
+        )
+        .unwrap();
+        let cache_path = dir.join("packages.cache");
+
+        let mut cache = PackageCache::open(&cache_path);
+        cache.load(&pdsc).unwrap();
+        cache.save().unwrap();
+        let cache = PackageCache::open(&cache_path);
+        let diagnostics = &cache.entries[&pdsc].diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].path.ends_with("/memory[id=IRAM1]"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
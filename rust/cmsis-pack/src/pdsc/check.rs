@@ -0,0 +1,206 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::pdsc::{Device, Memory};
+
+/// A problem with the memory map of a device, found by
+/// `Device::check_memories`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryIssue {
+    /// Two regions seen by the same processor share addresses
+    Overlap(String, String),
+    /// A region with a size of zero
+    Empty(String),
+    /// A region that can't be read, written or executed
+    NoAccess(String),
+    /// More than one region seen by the same processor is marked `startup`
+    MultipleStartup(Vec<String>),
+    NoDefaultRom,
+    NoDefaultRam,
+    /// A flash algorithm programs addresses outside of every region
+    UncoveredAlgorithm(PathBuf),
+}
+
+impl fmt::Display for MemoryIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryIssue::Overlap(first, second) => {
+                write!(f, "memory regions {} and {} overlap", first, second)
+            }
+            MemoryIssue::Empty(name) => write!(f, "memory region {} is empty", name),
+            MemoryIssue::NoAccess(name) => {
+                write!(f, "memory region {} has no access permissions", name)
+            }
+            MemoryIssue::MultipleStartup(names) => write!(
+                f,
+                "memory regions {} are all marked as startup",
+                names.join(", ")
+            ),
+            MemoryIssue::NoDefaultRom => write!(f, "no default ROM region"),
+            MemoryIssue::NoDefaultRam => write!(f, "no default RAM region"),
+            MemoryIssue::UncoveredAlgorithm(file_name) => write!(
+                f,
+                "flash algorithm {:?} is not within any memory region",
+                file_name
+            ),
+        }
+    }
+}
+
+/// The end of a region, past the last byte. Computed as a u128 as some packs
+/// describe regions that reach the end of the address space.
+fn end(start: u64, size: u64) -> u128 {
+    u128::from(start) + u128::from(size)
+}
+
+/// Regions without a `Pname` are seen by every processor.
+fn same_processor(a: &Memory, b: &Memory) -> bool {
+    match (&a.p_name, &b.p_name) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+fn is_rom(memory: &Memory) -> bool {
+    !memory.access.write && (memory.access.read || memory.access.execute)
+}
+
+impl Device {
+    /// Look for mistakes in the memory map of this device that would get in
+    /// the way of generating a linker script or programming it.
+    pub fn check_memories(&self) -> Vec<MemoryIssue> {
+        let mut issues = Vec::new();
+        let mut memories: Vec<(&str, &Memory)> = self
+            .memories
+            .0
+            .iter()
+            .map(|(name, memory)| (name.as_str(), memory))
+            .collect();
+        memories.sort_by_key(|(name, memory)| (memory.start, *name));
+
+        for (name, memory) in memories.iter() {
+            if memory.size == 0 {
+                issues.push(MemoryIssue::Empty(name.to_string()));
+            }
+            let access = &memory.access;
+            if !(access.read || access.write || access.execute) {
+                issues.push(MemoryIssue::NoAccess(name.to_string()));
+            }
+        }
+
+        for (i, (name, memory)) in memories.iter().enumerate() {
+            let memory_end = end(memory.start, memory.size);
+            // Sorted by start, so only the regions after this one that start
+            // before its end can overlap it
+            for (other_name, other) in memories[i + 1..].iter() {
+                if u128::from(other.start) >= memory_end {
+                    break;
+                }
+                if other.size != 0 && memory.size != 0 && same_processor(memory, other) {
+                    issues.push(MemoryIssue::Overlap(
+                        name.to_string(),
+                        other_name.to_string(),
+                    ));
+                }
+            }
+        }
+
+        let startup: Vec<&(&str, &Memory)> = memories
+            .iter()
+            .filter(|(_, memory)| memory.startup)
+            .collect();
+        for (i, (name, memory)) in startup.iter().enumerate() {
+            let seen_before = startup[..i]
+                .iter()
+                .any(|(_, other)| same_processor(memory, other));
+            if seen_before {
+                continue;
+            }
+            let mut names = vec![name.to_string()];
+            names.extend(
+                startup[i + 1..]
+                    .iter()
+                    .filter(|(_, other)| same_processor(memory, other))
+                    .map(|(other_name, _)| other_name.to_string()),
+            );
+            if names.len() > 1 {
+                issues.push(MemoryIssue::MultipleStartup(names));
+            }
+        }
+
+        let defaults = memories.iter().filter(|(_, memory)| memory.default);
+        if !defaults.clone().any(|(_, memory)| is_rom(memory)) {
+            issues.push(MemoryIssue::NoDefaultRom);
+        }
+        if !defaults.clone().any(|(_, memory)| memory.access.write) {
+            issues.push(MemoryIssue::NoDefaultRam);
+        }
+
+        for algorithm in self.algorithms.iter() {
+            // Walk the regions in address order, following the covered range
+            // from the start of the algorithm
+            let mut covered_to = u128::from(algorithm.start);
+            let algorithm_end = end(algorithm.start, algorithm.size);
+            for (_, memory) in memories.iter() {
+                if u128::from(memory.start) <= covered_to {
+                    covered_to = covered_to.max(end(memory.start, memory.size));
+                }
+            }
+            if covered_to < algorithm_end {
+                issues.push(MemoryIssue::UncoveredAlgorithm(algorithm.file_name.clone()));
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pdsc::Package;
+    use crate::utils::FromElem;
+
+    fn device(memories: &str) -> Device {
+        let pdsc = format!(
+            "<package><vendor>V</vendor><name>P</name><description/><url>./</url>\
+             <releases><release version=\"1.0.0\">r</release></releases>\
+             <devices><family Dfamily=\"F\"><device Dname=\"D\">\
+             <processor Dcore=\"Cortex-M7\"/>{}</device></family></devices></package>",
+            memories
+        );
+        let mut package = Package::from_string(&pdsc).unwrap();
+        package.devices.0.remove("D").unwrap()
+    }
+
+    #[test]
+    fn finds_memory_issues() {
+        let good = device(
+            r#"<memory id="IROM1" start="0x0" size="0x10000" startup="1" default="1"/>
+               <memory id="IROM2" start="0x10000" size="0x10000"/>
+               <memory id="IRAM1" start="0x20000000" size="0x8000" default="1"/>
+               <algorithm name="Flash/Both.FLM" start="0x0" size="0x20000"/>"#,
+        );
+        assert_eq!(good.check_memories(), vec![]);
+
+        let broken = device(
+            r#"<memory id="IROM1" start="0x0" size="0x10000" startup="1" default="1"/>
+               <memory id="IROM2" start="0x8000" size="0x10000" startup="1"/>
+               <memory id="IRAM1" start="0x20000000" size="0x0"/>
+               <memory name="Flash7" access="rx" Pname="cm7" start="0x1000000" size="0x1000"/>
+               <memory name="Flash4" access="rx" Pname="cm4" start="0x1000000" size="0x1000"/>
+               <memory name="Regs" access="p" start="0x40000000" size="0x1000"/>
+               <algorithm name="Flash/QSPI.FLM" start="0x90000000" size="0x100000"/>"#,
+        );
+        assert_eq!(
+            broken.check_memories(),
+            vec![
+                MemoryIssue::Empty("IRAM1".into()),
+                MemoryIssue::NoAccess("Regs".into()),
+                MemoryIssue::Overlap("IROM1".into(), "IROM2".into()),
+                MemoryIssue::MultipleStartup(vec!["IROM1".into(), "IROM2".into()]),
+                MemoryIssue::NoDefaultRam,
+                MemoryIssue::UncoveredAlgorithm("Flash/QSPI.FLM".into()),
+            ]
+        );
+    }
+}
@@ -3,11 +3,12 @@ use std::str::FromStr;
 
 use anyhow::{format_err, Error};
 use roxmltree::Node;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 use crate::utils::prelude::*;
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum FileCategory {
     Doc,
     Header,
@@ -50,7 +51,7 @@ impl FromStr for FileCategory {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum FileAttribute {
     Config,
     Template,
@@ -67,7 +68,7 @@ impl FromStr for FileAttribute {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FileRef {
     pub path: PathBuf,
     category: FileCategory,
@@ -93,7 +94,29 @@ impl FromElem for FileRef {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl FileRef {
+    pub fn category(&self) -> &FileCategory {
+        &self.category
+    }
+
+    pub fn attr(&self) -> Option<&FileAttribute> {
+        self.attr.as_ref()
+    }
+
+    pub fn select(&self) -> Option<&str> {
+        self.select.as_deref()
+    }
+
+    pub fn src(&self) -> Option<&str> {
+        self.src.as_deref()
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComponentBuilder {
     pub vendor: Option<String>,
     pub class: Option<String>,
@@ -199,7 +222,7 @@ impl FromElem for Bundle {
             .children()
             .filter_map(move |chld| {
                 if chld.tag_name().name() == "component" {
-                    ComponentBuilder::from_elem(&chld).ok()
+                    ComponentBuilder::from_elem(&chld).ok_warn_at(&chld)
                 } else {
                     None
                 }
@@ -235,7 +258,7 @@ fn child_to_component_iter(e: &Node) -> Result<Box<dyn Iterator<Item = Component
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct ComponentBuilders(pub(crate) Vec<ComponentBuilder>);
 
 impl FromElem for ComponentBuilders {
@@ -244,12 +267,11 @@ impl FromElem for ComponentBuilders {
         Ok(ComponentBuilders(
             e.children()
                 .filter(|e| e.is_element())
-                .flat_map(move |c| match child_to_component_iter(&c) {
-                    Ok(iter) => iter,
-                    Err(e) => {
-                        log::error!("when trying to parse component: {}", e);
-                        Box::new(None.into_iter())
-                    }
+                .flat_map(move |c| {
+                    child_to_component_iter(&c)
+                        .ok_warn_at(&c)
+                        .into_iter()
+                        .flatten()
                 })
                 .collect(),
         ))
@@ -1,8 +1,10 @@
 use anyhow::Error;
 use roxmltree::Node;
+use serde::{Deserialize, Serialize};
 
 use crate::utils::prelude::*;
 
+#[derive(Serialize, Deserialize)]
 pub struct ConditionComponent {
     pub device_family: Option<String>,
     pub device_sub_family: Option<String>,
@@ -23,6 +25,7 @@ impl FromElem for ConditionComponent {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Condition {
     pub id: String,
     pub accept: Vec<ConditionComponent>,
@@ -65,7 +68,7 @@ impl FromElem for Condition {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Conditions(pub Vec<Condition>);
 
 impl FromElem for Conditions {
@@ -74,7 +77,7 @@ impl FromElem for Conditions {
         Ok(Conditions(
             e.children()
                 .filter(|e| e.is_element())
-                .flat_map(|c| Condition::from_elem(&c).ok_warn())
+                .flat_map(|c| Condition::from_elem(&c).ok_warn_at(&c))
                 .collect(),
         ))
     }
@@ -1,13 +1,23 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+use crate::pdsc::sequence::{merge_debug_vars, merge_sequences, parse_sequences};
+use crate::pdsc::{DebugVars, Sequence};
 use crate::utils::prelude::*;
 use anyhow::{format_err, Error};
 use roxmltree::Node;
-use serde::{Deserialize, Serialize};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::de::{value, IntoDeserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// `remote = "Self"` derives the externally tagged form as inherent functions,
+// used as is by the binary cache; JSON writes `Other` as the bare name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum Core {
     Any,
     CortexM0,
@@ -19,6 +29,7 @@ pub enum Core {
     CortexM23,
     CortexM33,
     CortexM35P,
+    CortexM52,
     CortexM55,
     CortexM85,
     StarMC1,
@@ -31,6 +42,9 @@ pub enum Core {
     CortexR5,
     CortexR7,
     CortexR8,
+    CortexR52,
+    CortexR52Plus,
+    CortexR82,
     CortexA5,
     CortexA7,
     CortexA8,
@@ -40,9 +54,54 @@ pub enum Core {
     CortexA32,
     CortexA35,
     CortexA53,
+    CortexA55,
     CortexA57,
     CortexA72,
     CortexA73,
+    CortexA76,
+    /// A core this crate doesn't know, such as a vendor's own, as spelled in
+    /// the pdsc
+    Other(String),
+}
+
+impl Serialize for Core {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Core::Other(name) if serializer.is_human_readable() => serializer.serialize_str(name),
+            _ => Core::serialize(self, serializer),
+        }
+    }
+}
+
+impl JsonSchema for Core {
+    fn schema_name() -> String {
+        "Core".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            ..Default::default()
+        };
+        schema.metadata().description = Some(
+            "A known core by variant name, such as \"CortexM4\", or any other core as \
+             spelled in the pdsc"
+                .into(),
+        );
+        schema.into()
+    }
+}
+
+impl<'de> Deserialize<'de> for Core {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Core::deserialize(deserializer);
+        }
+        let name = String::deserialize(deserializer)?;
+        let known: Result<Core, value::Error> =
+            Core::deserialize(name.as_str().into_deserializer());
+        Ok(known.unwrap_or(Core::Other(name)))
+    }
 }
 
 impl FromStr for Core {
@@ -58,6 +117,7 @@ impl FromStr for Core {
             "Cortex-M23" => Ok(Core::CortexM23),
             "Cortex-M33" => Ok(Core::CortexM33),
             "Cortex-M35P" => Ok(Core::CortexM35P),
+            "Cortex-M52" => Ok(Core::CortexM52),
             "Cortex-M55" => Ok(Core::CortexM55),
             "Cortex-M85" => Ok(Core::CortexM85),
             "Star-MC1" => Ok(Core::StarMC1),
@@ -65,10 +125,14 @@ impl FromStr for Core {
             "SC300" => Ok(Core::SC300),
             "ARMV8MBL" => Ok(Core::ARMV8MBL),
             "ARMV8MML" => Ok(Core::ARMV8MML),
+            "ARMV81MML" => Ok(Core::ARMV81MML),
             "Cortex-R4" => Ok(Core::CortexR4),
             "Cortex-R5" => Ok(Core::CortexR5),
             "Cortex-R7" => Ok(Core::CortexR7),
             "Cortex-R8" => Ok(Core::CortexR8),
+            "Cortex-R52" => Ok(Core::CortexR52),
+            "Cortex-R52+" => Ok(Core::CortexR52Plus),
+            "Cortex-R82" => Ok(Core::CortexR82),
             "Cortex-A5" => Ok(Core::CortexA5),
             "Cortex-A7" => Ok(Core::CortexA7),
             "Cortex-A8" => Ok(Core::CortexA8),
@@ -78,16 +142,68 @@ impl FromStr for Core {
             "Cortex-A32" => Ok(Core::CortexA32),
             "Cortex-A35" => Ok(Core::CortexA35),
             "Cortex-A53" => Ok(Core::CortexA53),
+            "Cortex-A55" => Ok(Core::CortexA55),
             "Cortex-A57" => Ok(Core::CortexA57),
             "Cortex-A72" => Ok(Core::CortexA72),
             "Cortex-A73" => Ok(Core::CortexA73),
+            "Cortex-A76" => Ok(Core::CortexA76),
             "*" => Ok(Core::Any),
-            unknown => Err(format_err!("Unknown core {}", unknown)),
+            "" => Err(format_err!("Empty core")),
+            other => Ok(Core::Other(other.to_string())),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl fmt::Display for Core {
+    /// Writes the core as spelled in a pdsc
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Core::CortexM0 => "Cortex-M0",
+            Core::CortexM0Plus => "Cortex-M0+",
+            Core::CortexM1 => "Cortex-M1",
+            Core::CortexM3 => "Cortex-M3",
+            Core::CortexM4 => "Cortex-M4",
+            Core::CortexM7 => "Cortex-M7",
+            Core::CortexM23 => "Cortex-M23",
+            Core::CortexM33 => "Cortex-M33",
+            Core::CortexM35P => "Cortex-M35P",
+            Core::CortexM52 => "Cortex-M52",
+            Core::CortexM55 => "Cortex-M55",
+            Core::CortexM85 => "Cortex-M85",
+            Core::StarMC1 => "Star-MC1",
+            Core::SC000 => "SC000",
+            Core::SC300 => "SC300",
+            Core::ARMV8MBL => "ARMV8MBL",
+            Core::ARMV8MML => "ARMV8MML",
+            Core::ARMV81MML => "ARMV81MML",
+            Core::CortexR4 => "Cortex-R4",
+            Core::CortexR5 => "Cortex-R5",
+            Core::CortexR7 => "Cortex-R7",
+            Core::CortexR8 => "Cortex-R8",
+            Core::CortexR52 => "Cortex-R52",
+            Core::CortexR52Plus => "Cortex-R52+",
+            Core::CortexR82 => "Cortex-R82",
+            Core::CortexA5 => "Cortex-A5",
+            Core::CortexA7 => "Cortex-A7",
+            Core::CortexA8 => "Cortex-A8",
+            Core::CortexA9 => "Cortex-A9",
+            Core::CortexA15 => "Cortex-A15",
+            Core::CortexA17 => "Cortex-A17",
+            Core::CortexA32 => "Cortex-A32",
+            Core::CortexA35 => "Cortex-A35",
+            Core::CortexA53 => "Cortex-A53",
+            Core::CortexA55 => "Cortex-A55",
+            Core::CortexA57 => "Cortex-A57",
+            Core::CortexA72 => "Cortex-A72",
+            Core::CortexA73 => "Cortex-A73",
+            Core::CortexA76 => "Cortex-A76",
+            Core::Other(core) => core,
+            Core::Any => "*",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum FPU {
     None,
     SinglePrecision,
@@ -110,7 +226,17 @@ impl FromStr for FPU {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl fmt::Display for FPU {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FPU::None => "None",
+            FPU::SinglePrecision => "SP_FPU",
+            FPU::DoublePrecision => "DP_FPU",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum MPU {
     NotPresent,
     Present,
@@ -129,11 +255,214 @@ impl FromStr for MPU {
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl fmt::Display for MPU {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MPU::NotPresent => "None",
+            MPU::Present => "MPU",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Endian {
+    Little,
+    Big,
+    Configurable,
+}
+
+impl FromStr for Endian {
+    type Err = Error;
+    fn from_str(from: &str) -> Result<Self, Error> {
+        match from {
+            "Little-endian" => Ok(Endian::Little),
+            "Big-endian" => Ok(Endian::Big),
+            "Configurable" => Ok(Endian::Configurable),
+            "*" => Ok(Endian::Configurable),
+            unknown => Err(format_err!("Unknown endianness {}", unknown)),
+        }
+    }
+}
+
+impl fmt::Display for Endian {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Endian::Little => "Little-endian",
+            Endian::Big => "Big-endian",
+            Endian::Configurable => "Configurable",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum DSP {
+    #[default]
+    NotPresent,
+    Present,
+}
+
+impl FromStr for DSP {
+    type Err = Error;
+    fn from_str(from: &str) -> Result<Self, Error> {
+        match from {
+            "DSP" => Ok(DSP::Present),
+            "1" => Ok(DSP::Present),
+            "NO_DSP" => Ok(DSP::NotPresent),
+            "0" => Ok(DSP::NotPresent),
+            unknown => Err(format_err!("Unknown dsp {}", unknown)),
+        }
+    }
+}
+
+impl fmt::Display for DSP {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DSP::NotPresent => "NO_DSP",
+            DSP::Present => "DSP",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TrustZone {
+    #[default]
+    NotPresent,
+    Present,
+}
+
+impl FromStr for TrustZone {
+    type Err = Error;
+    fn from_str(from: &str) -> Result<Self, Error> {
+        match from {
+            "TZ" => Ok(TrustZone::Present),
+            "1" => Ok(TrustZone::Present),
+            "NO_TZ" => Ok(TrustZone::NotPresent),
+            "0" => Ok(TrustZone::NotPresent),
+            unknown => Err(format_err!("Unknown trustzone {}", unknown)),
+        }
+    }
+}
+
+impl fmt::Display for TrustZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TrustZone::NotPresent => "NO_TZ",
+            TrustZone::Present => "TZ",
+        })
+    }
+}
+
+/// The M-profile Vector Extension
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum MVE {
+    #[default]
+    None,
+    Integer,
+    FloatingPoint,
+}
+
+impl FromStr for MVE {
+    type Err = Error;
+    fn from_str(from: &str) -> Result<Self, Error> {
+        match from {
+            "NO_MVE" => Ok(MVE::None),
+            "0" => Ok(MVE::None),
+            "MVE" => Ok(MVE::Integer),
+            "1" => Ok(MVE::Integer),
+            "FP_MVE" => Ok(MVE::FloatingPoint),
+            "2" => Ok(MVE::FloatingPoint),
+            unknown => Err(format_err!("Unknown mve {}", unknown)),
+        }
+    }
+}
+
+impl fmt::Display for MVE {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MVE::None => "NO_MVE",
+            MVE::Integer => "MVE",
+            MVE::FloatingPoint => "FP_MVE",
+        })
+    }
+}
+
+/// Pointer authentication and branch target identification
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum PACBTI {
+    #[default]
+    NotPresent,
+    Present,
+}
+
+impl FromStr for PACBTI {
+    type Err = Error;
+    fn from_str(from: &str) -> Result<Self, Error> {
+        match from {
+            "PACBTI" => Ok(PACBTI::Present),
+            "1" => Ok(PACBTI::Present),
+            "NO_PACBTI" => Ok(PACBTI::NotPresent),
+            "0" => Ok(PACBTI::NotPresent),
+            unknown => Err(format_err!("Unknown pacbti {}", unknown)),
+        }
+    }
+}
+
+impl fmt::Display for PACBTI {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PACBTI::NotPresent => "NO_PACBTI",
+            PACBTI::Present => "PACBTI",
+        })
+    }
+}
+
+/// The security state a core starts in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum SecureState {
+    Secure,
+    NonSecure,
+    TrustZoneDisabled,
+}
+
+impl FromStr for SecureState {
+    type Err = Error;
+    fn from_str(from: &str) -> Result<Self, Error> {
+        match from {
+            "Secure" => Ok(SecureState::Secure),
+            "Non-secure" => Ok(SecureState::NonSecure),
+            "TZ-disabled" => Ok(SecureState::TrustZoneDisabled),
+            unknown => Err(format_err!("Unknown security state {}", unknown)),
+        }
+    }
+}
+
+impl fmt::Display for SecureState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SecureState::Secure => "Secure",
+            SecureState::NonSecure => "Non-secure",
+            SecureState::TrustZoneDisabled => "TZ-disabled",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Processor {
     pub core: Core,
     pub fpu: FPU,
     pub mpu: MPU,
+    pub endian: Option<Endian>,
+    /// Maximum clock frequency in Hz
+    pub clock: Option<u64>,
+    #[serde(default)]
+    pub dsp: DSP,
+    #[serde(default)]
+    pub trustzone: TrustZone,
+    #[serde(default)]
+    pub mve: MVE,
+    #[serde(default)]
+    pub pacbti: PACBTI,
+    pub secure: Option<SecureState>,
     pub ap: AccessPort,
     pub dp: u8,
     pub address: Option<u32>,
@@ -150,6 +479,13 @@ struct ProcessorBuilder {
     name: Option<String>,
     fpu: Option<FPU>,
     mpu: Option<MPU>,
+    endian: Option<Endian>,
+    clock: Option<u64>,
+    dsp: Option<DSP>,
+    trustzone: Option<TrustZone>,
+    mve: Option<MVE>,
+    pacbti: Option<PACBTI>,
+    secure: Option<SecureState>,
 }
 
 impl ProcessorBuilder {
@@ -160,6 +496,13 @@ impl ProcessorBuilder {
             name: self.name.or(other.name.clone()),
             fpu: self.fpu.or(other.fpu.clone()),
             mpu: self.mpu.or(other.mpu.clone()),
+            endian: self.endian.or(other.endian.clone()),
+            clock: self.clock.or(other.clock),
+            dsp: self.dsp.or(other.dsp.clone()),
+            trustzone: self.trustzone.or(other.trustzone.clone()),
+            mve: self.mve.or(other.mve.clone()),
+            pacbti: self.pacbti.or(other.pacbti.clone()),
+            secure: self.secure.or(other.secure.clone()),
         }
     }
     fn build(self, debugs: &[Debug]) -> Result<Vec<Processor>, Error> {
@@ -190,6 +533,13 @@ impl ProcessorBuilder {
                         .ok_or_else(|| format_err!("No Core found!"))?,
                     fpu: self.fpu.clone().unwrap_or(FPU::None),
                     mpu: self.mpu.clone().unwrap_or(MPU::NotPresent),
+                    endian: self.endian.clone(),
+                    clock: self.clock,
+                    dsp: self.dsp.clone().unwrap_or_default(),
+                    trustzone: self.trustzone.clone().unwrap_or_default(),
+                    mve: self.mve.clone().unwrap_or_default(),
+                    pacbti: self.pacbti.clone().unwrap_or_default(),
+                    secure: self.secure.clone(),
                     dp: debugs_iterator
                         .clone()
                         .find_map(|d| d.dp)
@@ -218,6 +568,13 @@ impl FromElem for ProcessorBuilder {
             units: attr_parse(e, "Punits").ok(),
             fpu: attr_parse(e, "Dfpu").ok(),
             mpu: attr_parse(e, "Dmpu").ok(),
+            endian: attr_parse(e, "Dendian").ok(),
+            clock: attr_parse(e, "Dclock").ok(),
+            dsp: attr_parse(e, "Ddsp").ok(),
+            trustzone: attr_parse(e, "Dtz").ok(),
+            mve: attr_parse(e, "Dmve").ok(),
+            pacbti: attr_parse(e, "Dpacbti").ok(),
+            secure: attr_parse(e, "Dsecure").ok(),
             name: attr_parse(e, "Pname").ok(),
         })
     }
@@ -227,17 +584,47 @@ impl FromElem for ProcessorBuilder {
 struct ProcessorsBuilder(Vec<ProcessorBuilder>);
 
 impl ProcessorsBuilder {
-    fn merge(mut self, parent: &Option<Self>) -> Result<Self, Error> {
-        if let Some(parent) = parent {
-            if let [parent] = &parent.0[..] {
-                self.0 = self.0.into_iter().map(|x| x.merge(parent)).collect();
-            } else {
-                Err(format_err!(
-                    "Support for two parent processors not implemented!"
-                ))?;
+    /// Inherit from the processors of a parent element. A processor with a
+    /// `Pname` refines the parent processor of the same name, or the parent
+    /// processor without a name; a processor without one refines them all.
+    fn merge(self, parent: &Option<Self>) -> Self {
+        let parent = match parent {
+            Some(parent) if !parent.0.is_empty() => parent,
+            _ => return self,
+        };
+        let (named, shared): (Vec<_>, Vec<_>) =
+            self.0.into_iter().partition(|proc| proc.name.is_some());
+        let shared = shared.into_iter().reduce(|acc, proc| acc.merge(&proc));
+        let refine = |proc: ProcessorBuilder, parent: Option<&ProcessorBuilder>| {
+            let proc = match &shared {
+                Some(shared) => proc.merge(shared),
+                None => proc,
+            };
+            match parent {
+                Some(parent) => proc.merge(parent),
+                None => proc,
+            }
+        };
+        let unnamed_parent = parent.0.iter().find(|proc| proc.name.is_none());
+
+        let mut merged = Vec::new();
+        for proc in parent.0.iter() {
+            match named.iter().find(|child| child.name == proc.name) {
+                Some(child) => merged.push(refine(child.clone(), Some(proc))),
+                // Named children take the place of an unnamed parent
+                None if proc.name.is_none() && !named.is_empty() => {}
+                None => merged.push(match &shared {
+                    Some(shared) => shared.clone().merge(proc),
+                    None => proc.clone(),
+                }),
             }
         }
-        Ok(self)
+        for child in named {
+            if !parent.0.iter().any(|proc| proc.name == child.name) {
+                merged.push(refine(child, unnamed_parent));
+            }
+        }
+        Self(merged)
     }
 
     fn merge_into(&mut self, other: Self) {
@@ -259,7 +646,7 @@ impl FromElem for ProcessorsBuilder {
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, JsonSchema)]
 pub enum AccessPort {
     Index(u8),
     Address(u64),
@@ -378,7 +765,7 @@ impl DebugsBuilder {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MemoryPermissions {
     pub read: bool,
     pub write: bool,
@@ -446,7 +833,7 @@ impl FromStr for NumberBool {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Memory {
     pub p_name: Option<String>,
     pub access: MemoryPermissions,
@@ -498,27 +885,75 @@ impl FromElem for MemElem {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl Memory {
+    /// The id of the memory stored under `key`, without the processor name
+    /// that qualifies ids shared by several processors.
+    pub fn id<'a>(&self, key: &'a str) -> &'a str {
+        self.p_name
+            .as_ref()
+            .and_then(|p_name| key.strip_suffix(p_name.as_str()))
+            .and_then(|key| key.strip_suffix('@'))
+            .unwrap_or(key)
+    }
+}
+
+/// The memories of a device, by id. Processors of a multi-core device often
+/// use the same ids for their own memories; those are kept as `id@Pname`,
+/// next to the memory shared by all processors under the plain id.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Memories(pub HashMap<String, Memory>);
 
-fn merge_memories(lhs: Memories, rhs: &Memories) -> Memories {
-    let rhs: Vec<_> = rhs
-        .0
-        .iter()
-        .filter_map(|(k, v)| {
-            if lhs.0.contains_key(k) {
-                None
-            } else {
-                Some((k.clone(), v.clone()))
+fn qualified(id: &str, memory: &Memory) -> String {
+    match &memory.p_name {
+        Some(p_name) => format!("{}@{}", id, p_name),
+        None => id.to_string(),
+    }
+}
+
+impl Memories {
+    fn contains(&self, id: &str, memory: &Memory) -> bool {
+        match self.0.get(id) {
+            Some(other) if other.p_name == memory.p_name => true,
+            _ => memory.p_name.is_some() && self.0.contains_key(&qualified(id, memory)),
+        }
+    }
+
+    /// Add a memory, replacing the memory of the same id and processor.
+    fn insert(&mut self, id: String, memory: Memory) {
+        if let Some(other) = self.0.get(&id) {
+            if other.p_name == memory.p_name {
+                self.0.insert(id, memory);
+                return;
             }
-        })
-        .collect();
-    let mut lhs = lhs;
-    lhs.0.extend(rhs);
+            // The memory of a single processor gives up the plain id, a
+            // shared memory keeps it
+            if other.p_name.is_some() {
+                let other = self.0.remove(&id).unwrap();
+                self.0.insert(qualified(&id, &other), other);
+            }
+        }
+        let prefix = format!("{}@", id);
+        let qualify = memory.p_name.is_some()
+            && (self.0.contains_key(&id) || self.0.keys().any(|key| key.starts_with(&prefix)));
+        if qualify {
+            self.0.insert(qualified(&id, &memory), memory);
+        } else {
+            self.0.insert(id, memory);
+        }
+    }
+}
+
+fn merge_memories(mut lhs: Memories, rhs: &Memories) -> Memories {
+    for (key, memory) in rhs.0.iter() {
+        let id = memory.id(key);
+        if !lhs.contains(id, memory) {
+            lhs.insert(id.to_string(), memory.clone());
+        }
+    }
     lhs
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum AlgorithmStyle {
     Keil,
     IAR,
@@ -537,9 +972,11 @@ impl FromStr for AlgorithmStyle {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Algorithm {
     pub file_name: PathBuf,
+    /// The processor the algorithm is used by, or all when `None`
+    pub p_name: Option<String>,
     pub start: u64,
     pub size: u64,
     pub default: bool,
@@ -558,6 +995,7 @@ impl FromElem for Algorithm {
         let style = attr_parse(e, "style").ok().unwrap_or(AlgorithmStyle::Keil);
         Ok(Self {
             file_name: file_name.replace('\\', "/").into(),
+            p_name: e.attribute("Pname").map(String::from),
             start: attr_parse_hex(e, "start")?,
             size: attr_parse_hex(e, "size")?,
             ram_start: attr_parse_hex(e, "RAMstart").ok(),
@@ -575,17 +1013,21 @@ struct DeviceBuilder {
     memories: Memories,
     processor: Option<ProcessorsBuilder>,
     debugs: DebugsBuilder,
+    sequences: Vec<Sequence>,
+    debug_vars: Vec<DebugVars>,
     vendor: Option<String>,
     family: Option<String>,
     sub_family: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     pub name: String,
     pub memories: Memories,
     pub algorithms: Vec<Algorithm>,
     pub processors: Vec<Processor>,
+    pub sequences: Vec<Sequence>,
+    pub debug_vars: Vec<DebugVars>,
     pub vendor: Option<String>,
     pub family: String,
     pub sub_family: Option<String>,
@@ -613,6 +1055,8 @@ impl DeviceBuilder {
             algorithms: Vec::new(),
             processor: None,
             debugs: DebugsBuilder(Vec::new()),
+            sequences: Vec::new(),
+            debug_vars: Vec::new(),
             family,
             sub_family,
         }
@@ -638,6 +1082,8 @@ impl DeviceBuilder {
             name,
             memories: self.memories,
             algorithms: self.algorithms,
+            sequences: self.sequences,
+            debug_vars: self.debug_vars,
             vendor: self.vendor,
             family,
             sub_family: self.sub_family,
@@ -651,10 +1097,12 @@ impl DeviceBuilder {
             algorithms: self.algorithms,
             memories: merge_memories(self.memories, &parent.memories),
             processor: match self.processor {
-                Some(old_proc) => Some(old_proc.merge(&parent.processor)?),
+                Some(old_proc) => Some(old_proc.merge(&parent.processor)),
                 None => parent.processor.clone(),
             },
             debugs: self.debugs.merge(&parent.debugs),
+            sequences: merge_sequences(self.sequences, &parent.sequences),
+            debug_vars: merge_debug_vars(self.debug_vars, &parent.debug_vars),
             vendor: self.vendor.or(parent.vendor.clone()),
             family: self.family.or(parent.family.clone()),
             sub_family: self.sub_family.or(parent.sub_family.clone()),
@@ -674,8 +1122,18 @@ impl DeviceBuilder {
         self
     }
 
+    fn add_sequences(&mut self, sequences: Vec<Sequence>) -> &mut Self {
+        self.sequences = merge_sequences(sequences, &self.sequences);
+        self
+    }
+
+    fn add_debug_vars(&mut self, vars: DebugVars) -> &mut Self {
+        self.debug_vars = merge_debug_vars(vec![vars], &self.debug_vars);
+        self
+    }
+
     fn add_memory(&mut self, MemElem(name, mem): MemElem) -> &mut Self {
-        self.memories.0.insert(name, mem);
+        self.memories.insert(name, mem);
         self
     }
 
@@ -693,28 +1151,38 @@ fn parse_device(e: &Node) -> Vec<DeviceBuilder> {
             "variant" => Some(DeviceBuilder::from_elem(&child)),
             "memory" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|mem| device.add_memory(mem));
                 None
             }
             "algorithm" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|alg| device.add_algorithm(alg));
                 None
             }
             "processor" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|prc| device.add_processor(prc));
                 None
             }
             "debug" => {
                 DebugsBuilder::from_elem_and_parent(&child, e)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|debug| device.add_debug(debug));
                 None
             }
+            "sequences" => {
+                device.add_sequences(parse_sequences(&child));
+                None
+            }
+            "debugvars" => {
+                FromElem::from_elem(&child)
+                    .ok_warn_at(&child)
+                    .map(|vars| device.add_debug_vars(vars));
+                None
+            }
             _ => None,
         })
         .collect::<Vec<_>>();
@@ -723,7 +1191,7 @@ fn parse_device(e: &Node) -> Vec<DeviceBuilder> {
     } else {
         variants
             .into_iter()
-            .flat_map(|bld| bld.add_parent(&device).ok_warn())
+            .flat_map(|bld| bld.add_parent(&device).ok_warn_at(e))
             .collect()
     }
 }
@@ -739,30 +1207,38 @@ fn parse_sub_family(e: &Node) -> Vec<DeviceBuilder> {
             }
             "memory" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|mem| sub_family_device.add_memory(mem));
             }
             "algorithm" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|alg| sub_family_device.add_algorithm(alg));
             }
             "processor" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|prc| sub_family_device.add_processor(prc));
             }
             "debug" => {
                 DebugsBuilder::from_elem_and_parent(&child, e)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|debug| sub_family_device.add_debug(debug));
             }
+            "sequences" => {
+                sub_family_device.add_sequences(parse_sequences(&child));
+            }
+            "debugvars" => {
+                FromElem::from_elem(&child)
+                    .ok_warn_at(&child)
+                    .map(|vars| sub_family_device.add_debug_vars(vars));
+            }
             _ => continue,
         }
     }
     devices
         .into_iter()
-        .flat_map(|bldr| bldr.add_parent(&sub_family_device).ok_warn())
+        .flat_map(|bldr| bldr.add_parent(&sub_family_device).ok_warn_at(e))
         .collect()
 }
 
@@ -775,48 +1251,210 @@ fn parse_family(e: &Node) -> Result<Vec<Device>, Error> {
             "device" => parse_device(&child),
             "memory" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|mem| family_device.add_memory(mem));
                 Vec::new()
             }
             "algorithm" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|alg| family_device.add_algorithm(alg));
                 Vec::new()
             }
             "processor" => {
                 FromElem::from_elem(&child)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|prc| family_device.add_processor(prc));
                 Vec::new()
             }
             "debug" => {
                 DebugsBuilder::from_elem_and_parent(&child, e)
-                    .ok_warn()
+                    .ok_warn_at(&child)
                     .map(|debug| family_device.add_debug(debug));
                 Vec::new()
             }
+            "sequences" => {
+                family_device.add_sequences(parse_sequences(&child));
+                Vec::new()
+            }
+            "debugvars" => {
+                FromElem::from_elem(&child)
+                    .ok_warn_at(&child)
+                    .map(|vars| family_device.add_debug_vars(vars));
+                Vec::new()
+            }
             _ => Vec::new(),
         })
         .collect::<Vec<_>>();
-    all_devices
+    Ok(all_devices
         .into_iter()
-        .map(|bldr| bldr.add_parent(&family_device).and_then(|dev| dev.build()))
-        .collect()
+        .filter_map(|bldr| {
+            bldr.add_parent(&family_device)
+                .and_then(|dev| dev.build())
+                .ok_warn_at(e)
+        })
+        .collect())
 }
 
-#[derive(Default, Serialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Devices(pub HashMap<String, Device>);
 
 impl FromElem for Devices {
     fn from_elem(e: &Node) -> Result<Self, Error> {
-        e.children()
-            .try_fold(HashMap::new(), |mut res, c| {
-                let add_this = parse_family(&c)?;
-                res.extend(add_this.into_iter().map(|dev| (dev.name.clone(), dev)));
-                Ok(res)
-            })
-            .map(Devices)
+        let mut devices = HashMap::new();
+        for family in e.children().filter(|c| c.is_element()) {
+            if let Some(add_this) = parse_family(&family).ok_warn_at(&family) {
+                devices.extend(add_this.into_iter().map(|dev| (dev.name.clone(), dev)));
+            }
+        }
+        Ok(Devices(devices))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pdsc::{AccessPort, Core, Endian, Package, SecureState, TrustZone, DSP, FPU, MVE};
+    use crate::utils::FromElem;
+
+    const MULTICORE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../tests/multicore/MyVendor.Multicore.pdsc"
+    );
+
+    #[test]
+    fn multicore_devices() {
+        let package = Package::from_path(MULTICORE.as_ref()).unwrap();
+        let devices = &package.devices.0;
+        assert_eq!(devices.len(), 4);
+
+        let dual = &devices["DUAL1"];
+        let summary: Vec<_> = dual
+            .processors
+            .iter()
+            .map(|proc| (proc.name.as_deref(), &proc.core, &proc.fpu, proc.ap))
+            .collect();
+        assert!(matches!(
+            summary[..],
+            [
+                (
+                    Some("CM7"),
+                    Core::CortexM7,
+                    FPU::DoublePrecision,
+                    AccessPort::Index(0)
+                ),
+                (
+                    Some("CM4"),
+                    Core::CortexM4,
+                    FPU::SinglePrecision,
+                    AccessPort::Index(3)
+                ),
+            ]
+        ));
+        assert_eq!(dual.svd_path(Some("CM4")).unwrap(), "SVD/Dual_CM4.svd");
+        let mut memories: Vec<_> = dual.memories.0.keys().map(String::as_str).collect();
+        memories.sort_unstable();
+        assert_eq!(
+            memories,
+            ["IRAM1@CM4", "IRAM1@CM7", "IROM1@CM4", "IROM1@CM7"]
+        );
+        let cm4_rom = &dual.memories.0["IROM1@CM4"];
+        assert_eq!(cm4_rom.start, 0x0810_0000);
+        assert_eq!(cm4_rom.id("IROM1@CM4"), "IROM1");
+        let algorithms: Vec<_> = dual
+            .algorithms
+            .iter()
+            .map(|algo| algo.p_name.as_deref())
+            .collect();
+        assert_eq!(algorithms, [Some("CM7"), Some("CM4")]);
+
+        // The sub family changes one core and the device one memory of one core
+        let lite = &devices["DUAL2"];
+        assert_eq!(lite.processors.len(), 2);
+        assert_eq!(lite.processors[0].fpu, FPU::DoublePrecision);
+        assert_eq!(lite.processors[1].fpu, FPU::None);
+        assert_eq!(lite.processors[1].core, Core::CortexM4);
+        assert_eq!(lite.memories.0["IRAM1@CM4"].size, 0x20000);
+        assert_eq!(lite.memories.0["IRAM1@CM7"].size, 0x20000);
+        assert!(lite.memories.0["IROM1"].p_name.is_none());
+
+        // Memories of one processor sit next to shared memories of the same
+        // id, from the family or from the same element
+        let shared = &devices["SHARED1"];
+        let mut memories: Vec<_> = shared.memories.0.keys().map(String::as_str).collect();
+        memories.sort_unstable();
+        assert_eq!(memories, ["IRAM1", "IRAM1@B", "IROM1", "IROM1@A"]);
+        assert_eq!(shared.memories.0["IRAM1"].start, 0x2000_0000);
+        assert_eq!(shared.memories.0["IRAM1@B"].start, 0x3000_0000);
+        assert_eq!(shared.memories.0["IROM1"].start, 0x0800_0000);
+        assert_eq!(shared.memories.0["IROM1@A"].id("IROM1@A"), "IROM1");
+
+        // Named processors of a device refine the unnamed one of its family
+        let twin = &devices["TWIN1"];
+        let summary: Vec<_> = twin
+            .processors
+            .iter()
+            .map(|proc| (proc.name.as_deref(), &proc.core, &proc.fpu, proc.ap))
+            .collect();
+        assert!(matches!(
+            summary[..],
+            [
+                (
+                    Some("cm33_core0"),
+                    Core::CortexM33,
+                    FPU::SinglePrecision,
+                    AccessPort::Index(0)
+                ),
+                (
+                    Some("cm33_core1"),
+                    Core::CortexM33,
+                    FPU::None,
+                    AccessPort::Index(1)
+                ),
+            ]
+        ));
+        assert_eq!(twin.svd_path(None).unwrap(), "SVD/Twin.svd");
+    }
+
+    #[test]
+    fn processor_features() {
+        let package = Package::from_string(
+            r#"<package><vendor>V</vendor><name>P</name><description/><url>./</url>
+               <releases><release version="1.0.0">r</release></releases>
+               <devices><family Dfamily="F">
+                 <processor Dendian="Little-endian" Dclock="160000000"/>
+                 <device Dname="MAIN">
+                   <processor Dcore="ARMV81MML" Dfpu="DP_FPU" Ddsp="DSP" Dtz="TZ"
+                              Dmve="FP_MVE" Dpacbti="NO_PACBTI" Dsecure="Secure"/>
+                 </device>
+                 <device Dname="VENDOR"><processor Dcore="Acme-V1"/></device>
+                 <device Dname="NEW"><processor Dcore="Cortex-M52"/></device>
+               </family></devices></package>"#,
+        )
+        .unwrap();
+        let main = &package.devices.0["MAIN"].processors[0];
+        assert_eq!(main.core, Core::ARMV81MML);
+        assert_eq!(main.endian, Some(Endian::Little));
+        assert_eq!(main.clock, Some(160_000_000));
+        assert_eq!(
+            (&main.dsp, &main.trustzone, &main.mve),
+            (&DSP::Present, &TrustZone::Present, &MVE::FloatingPoint)
+        );
+        assert_eq!(main.secure, Some(SecureState::Secure));
+
+        let vendor = &package.devices.0["VENDOR"].processors[0];
+        assert_eq!(vendor.core, Core::Other("Acme-V1".into()));
+        assert_eq!(vendor.core.to_string(), "Acme-V1");
+        assert_eq!(vendor.mve, MVE::None);
+        assert_eq!(package.devices.0["NEW"].processors[0].core, Core::CortexM52);
+    }
+
+    #[test]
+    fn core_serialization() {
+        let cores = vec![Core::CortexM4, Core::Other("Acme-V1".into())];
+        let json = serde_json::to_string(&cores).unwrap();
+        assert_eq!(json, r#"["CortexM4","Acme-V1"]"#);
+        assert_eq!(serde_json::from_str::<Vec<Core>>(&json).unwrap(), cores);
+        let bytes = bincode::serialize(&cores).unwrap();
+        assert_eq!(bincode::deserialize::<Vec<Core>>(&bytes).unwrap(), cores);
     }
 }
@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::{format_err, Error};
+use schemars::{schema_for, JsonSchema};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::pdsc::{Component, DumpBoard, DumpDevice};
+
+/// Version of the JSON written by dumps. Files without a version are in the
+/// legacy format, which counts as version 1.
+///
+/// - 2: access ports are written as `{"Index": n}` or `{"Address": n}`
+/// - 3: a memory of one processor of a multi-core device is keyed
+///   `id@Pname` when another memory of the device has the same id
+pub const FORMAT_VERSION: u32 = 3;
+
+/// How dumps are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// An object holding `format_version` and the entries, under a key
+    /// named after the dump
+    #[default]
+    Versioned,
+    /// The entries alone, with access ports written as plain numbers and
+    /// memories keyed by their plain id, as read by consumers written before
+    /// format versions
+    Legacy,
+}
+
+/// What a JSON dump holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpKind {
+    /// The device index, keyed by device name
+    Devices,
+    /// The board index, keyed by board name
+    Boards,
+    /// A list of components
+    Components,
+}
+
+impl DumpKind {
+    fn key(self) -> &'static str {
+        match self {
+            DumpKind::Devices => "devices",
+            DumpKind::Boards => "boards",
+            DumpKind::Components => "components",
+        }
+    }
+}
+
+impl FromStr for DumpKind {
+    type Err = Error;
+    fn from_str(from: &str) -> Result<Self, Error> {
+        match from {
+            "devices" => Ok(DumpKind::Devices),
+            "boards" => Ok(DumpKind::Boards),
+            "components" => Ok(DumpKind::Components),
+            unknown => Err(format_err!("Unknown dump {}", unknown)),
+        }
+    }
+}
+
+// The shapes of versioned dumps, used only to describe them with a schema.
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(title = "Device index")]
+struct DevicesFile {
+    format_version: u32,
+    devices: BTreeMap<String, DumpDevice<'static>>,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(title = "Board index")]
+struct BoardsFile {
+    format_version: u32,
+    boards: BTreeMap<String, DumpBoard<'static>>,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(title = "Components")]
+struct ComponentsFile {
+    format_version: u32,
+    components: Vec<Component>,
+}
+
+/// The JSON Schema of a versioned dump of `kind`, generated from the types
+/// it is written from.
+pub fn json_schema(kind: DumpKind) -> String {
+    let schema = match kind {
+        DumpKind::Devices => schema_for!(DevicesFile),
+        DumpKind::Boards => schema_for!(BoardsFile),
+        DumpKind::Components => schema_for!(ComponentsFile),
+    };
+    serde_json::to_string_pretty(&schema).unwrap()
+}
+
+/// Rewrite the access port of every processor of every device in
+/// `devices`, a device index in either format.
+fn map_access_ports(devices: &mut Value, map: fn(Value) -> Value) {
+    let devices = devices
+        .as_object_mut()
+        .into_iter()
+        .flat_map(Map::values_mut);
+    for device in devices {
+        let processors = device
+            .get_mut("processors")
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten();
+        for processor in processors {
+            if let Some(ap) = processor.get_mut("ap") {
+                *ap = map(ap.take());
+            }
+        }
+    }
+}
+
+/// Key the memories of every device in `devices` by their plain id. Of the
+/// memories sharing an id, the one shared by all processors is kept, or else
+/// the first by processor name.
+fn legacy_memory_ids(devices: &mut Value) {
+    let devices = devices
+        .as_object_mut()
+        .into_iter()
+        .flat_map(Map::values_mut);
+    for device in devices {
+        let memories = match device.get_mut("memories").and_then(Value::as_object_mut) {
+            Some(memories) => memories,
+            None => continue,
+        };
+        let mut plain = Map::new();
+        let (shared, qualified): (Vec<_>, Vec<_>) = std::mem::take(memories)
+            .into_iter()
+            .partition(|(key, _)| !key.contains('@'));
+        for (key, memory) in shared.into_iter().chain(qualified) {
+            let id = key.split('@').next().unwrap_or(&key).to_string();
+            plain.entry(id).or_insert(memory);
+        }
+        *memories = plain;
+    }
+}
+
+/// `{"Index": 1}` and `{"Address": 4096}` become `1` and `4096`.
+fn legacy_access_port(ap: Value) -> Value {
+    match ap {
+        Value::Object(mut port) => port
+            .remove("Index")
+            .or_else(|| port.remove("Address"))
+            .unwrap_or(Value::Object(port)),
+        other => other,
+    }
+}
+
+/// Legacy files only had port indices, which fit in a byte; anything larger
+/// can only have been written from an address.
+fn current_access_port(ap: Value) -> Value {
+    match ap.as_u64() {
+        Some(n) if n <= u64::from(u8::MAX) => json!({ "Index": n }),
+        Some(n) => json!({ "Address": n }),
+        None => ap,
+    }
+}
+
+/// Wrap the entries of a dump of `kind` as `format` asks.
+pub(crate) fn to_format<T: Serialize>(
+    kind: DumpKind,
+    entries: &T,
+    format: JsonFormat,
+) -> Result<Value, Error> {
+    let mut entries = serde_json::to_value(entries)?;
+    Ok(match format {
+        JsonFormat::Versioned => json!({
+            "format_version": FORMAT_VERSION,
+            kind.key(): entries,
+        }),
+        JsonFormat::Legacy => {
+            if kind == DumpKind::Devices {
+                map_access_ports(&mut entries, legacy_access_port);
+                legacy_memory_ids(&mut entries);
+            }
+            entries
+        }
+    })
+}
+
+/// The entries of a dump of `kind` in either format, in the current shape,
+/// and whether the dump was already written as `format` asks.
+pub(crate) fn from_any_format(
+    kind: DumpKind,
+    mut dump: Value,
+    format: JsonFormat,
+) -> Result<(Value, bool), Error> {
+    let version = match dump.get("format_version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| format_err!("Invalid format_version {}", version))?,
+        None => 1,
+    };
+    if version > u64::from(FORMAT_VERSION) {
+        return Err(format_err!(
+            "Written in format version {}, newer than the supported version {}",
+            version,
+            FORMAT_VERSION
+        ));
+    }
+    let in_format = match format {
+        JsonFormat::Versioned => version == u64::from(FORMAT_VERSION),
+        JsonFormat::Legacy => version == 1,
+    };
+    if version == 1 {
+        if kind == DumpKind::Devices {
+            map_access_ports(&mut dump, current_access_port);
+        }
+        return Ok((dump, in_format));
+    }
+    match dump.get_mut(kind.key()) {
+        Some(entries) => Ok((entries.take(), in_format)),
+        None => Err(format_err!("Missing \"{}\"", kind.key())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn legacy_round_trip() {
+        let devices = json!({
+            "Dev": {
+                "name": "Dev",
+                "processors": [{"ap": {"Index": 1}}, {"ap": {"Address": 4096}}]
+            }
+        });
+        let legacy = to_format(DumpKind::Devices, &devices, JsonFormat::Legacy).unwrap();
+        assert_eq!(legacy["Dev"]["processors"][0]["ap"], 1);
+        assert_eq!(legacy["Dev"]["processors"][1]["ap"], 4096);
+        let read = from_any_format(DumpKind::Devices, legacy, JsonFormat::Versioned).unwrap();
+        assert_eq!(read, (devices.clone(), false));
+
+        let versioned = to_format(DumpKind::Devices, &devices, JsonFormat::Versioned).unwrap();
+        assert_eq!(versioned["format_version"], FORMAT_VERSION);
+        let read = from_any_format(DumpKind::Devices, versioned, JsonFormat::Versioned).unwrap();
+        assert_eq!(read, (devices, true));
+
+        let multicore = json!({
+            "Dev": {
+                "memories": {"IRAM1": {"start": 1}, "IRAM1@B": {"start": 2},
+                             "IROM1@A": {"start": 3}, "IROM1@B": {"start": 4}}
+            }
+        });
+        let legacy = to_format(DumpKind::Devices, &multicore, JsonFormat::Legacy).unwrap();
+        assert_eq!(
+            legacy["Dev"]["memories"],
+            json!({"IRAM1": {"start": 1}, "IROM1": {"start": 3}})
+        );
+
+        let newer = json!({"format_version": FORMAT_VERSION + 1, "devices": {}});
+        assert!(from_any_format(DumpKind::Devices, newer, JsonFormat::Versioned).is_err());
+    }
+
+    #[test]
+    fn schemas_describe_dumps() {
+        let schema: Value = serde_json::from_str(&json_schema(DumpKind::Devices)).unwrap();
+        assert_eq!(schema["title"], "Device index");
+        assert_eq!(schema["required"], json!(["devices", "format_version"]));
+        assert!(schema["definitions"]["AccessPort"].is_object());
+        for kind in [DumpKind::Boards, DumpKind::Components] {
+            assert!(serde_json::from_str::<Value>(&json_schema(kind)).is_ok());
+        }
+    }
+}
@@ -1,23 +1,44 @@
 use roxmltree::Node;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::{BTreeMap, HashMap};
-use std::fs::OpenOptions;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::store::{LockMode, StoreLock};
+use crate::utils::json::{read_json, write_json};
 use crate::utils::prelude::*;
+use crate::utils::{parallel_map, Diagnostic, ParseMode};
 use anyhow::{format_err, Error};
+use format::{from_any_format, to_format};
 
+mod cache;
+mod check;
 mod component;
 mod condition;
 mod device;
-pub use component::{ComponentBuilders, FileRef};
+mod format;
+mod query;
+mod sequence;
+pub use cache::{PackageCache, PACKAGE_CACHE};
+pub use check::MemoryIssue;
+pub use component::{ComponentBuilders, FileAttribute, FileCategory, FileRef};
 pub use condition::{Condition, Conditions};
-pub use device::{AccessPort, Algorithm, Core, Device, Devices, Memories, Processor};
+pub use device::{
+    AccessPort, Algorithm, Core, Device, Devices, Endian, Memories, Memory, Processor, SecureState,
+    TrustZone, DSP, FPU, MPU, MVE, PACBTI,
+};
+pub use format::{json_schema, DumpKind, JsonFormat, FORMAT_VERSION};
+pub use query::{DeviceIndex, DeviceQuery, IndexedDevice, PackId};
+pub use sequence::{DebugVars, Sequence, SequenceStep};
 
+#[derive(Serialize, Deserialize)]
 pub struct Release {
     pub version: String,
+    pub date: Option<String>,
     pub text: String,
 }
 
@@ -26,12 +47,13 @@ impl FromElem for Release {
         assert_root_name(e, "release")?;
         Ok(Self {
             version: attr_map(e, "version")?,
+            date: attr_map(e, "date").ok(),
             text: e.text().unwrap().to_string(),
         })
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Releases(Vec<Release>);
 
 impl Releases {
@@ -46,7 +68,7 @@ impl FromElem for Releases {
         let to_ret: Vec<_> = e
             .children()
             .filter(|e| e.is_element())
-            .flat_map(|c| Release::from_elem(&c).ok_warn())
+            .flat_map(|c| Release::from_elem(&c).ok_warn_at(&c))
             .collect();
         if to_ret.is_empty() {
             Err(format_err!("There must be at least one release!"))
@@ -56,35 +78,56 @@ impl FromElem for Releases {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DumpDevice<'a> {
     name: &'a str,
     memories: Cow<'a, Memories>,
-    algorithms: Cow<'a, Vec<Algorithm>>,
-    processors: Cow<'a, Vec<Processor>>,
+    algorithms: Cow<'a, [Algorithm]>,
+    processors: Cow<'a, [Processor]>,
     from_pack: FromPack<'a>,
     vendor: Option<&'a str>,
     family: &'a str,
     sub_family: Option<&'a str>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 struct FromPack<'a> {
     vendor: &'a str,
     pack: &'a str,
     version: &'a str,
     url: &'a str,
+    /// The pdsc or pack file the entry was read from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<Cow<'a, Path>>,
 }
 
 impl<'a> FromPack<'a> {
-    fn new(vendor: &'a str, pack: &'a str, version: &'a str, url: &'a str) -> Self {
+    fn new(package: &'a Package) -> Self {
         Self {
-            vendor,
-            pack,
-            version,
-            url,
+            vendor: &package.vendor,
+            pack: &package.name,
+            version: &package.releases.latest_release().version,
+            url: &package.url,
+            path: package.path.as_deref().map(Cow::Borrowed),
         }
     }
+
+    /// Whether an entry from this pack is out of date, because the pack is
+    /// being dumped again or because the file it came from is gone.
+    fn is_stale(&self, dumping: &HashSet<(&str, &str)>) -> bool {
+        dumping.contains(&(self.vendor, self.pack))
+            || self.path.as_ref().is_some_and(|path| !path.exists())
+    }
+}
+
+/// A board as written to the board index, along with the pack it came from.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct DumpBoard<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    mounted_devices: Cow<'a, [String]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from_pack: Option<FromPack<'a>>,
 }
 
 impl<'a> DumpDevice<'a> {
@@ -102,6 +145,7 @@ impl<'a> DumpDevice<'a> {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Package {
     pub name: String,
     pub description: String,
@@ -113,6 +157,8 @@ pub struct Package {
     pub conditions: Conditions,
     pub devices: Devices,
     pub boards: Vec<Board>,
+    /// The file this package was read from, when read from a file
+    pub path: Option<PathBuf>,
 }
 
 impl FromElem for Package {
@@ -132,17 +178,23 @@ impl FromElem for Package {
             match child.tag_name().name() {
                 "components" => {
                     components = ComponentBuilders::from_elem(&child)
-                        .ok_warn()
+                        .ok_warn_at(&child)
                         .unwrap_or_default();
                 }
                 "releases" => {
-                    releases = Releases::from_elem(&child).ok_warn().unwrap_or_default();
+                    releases = Releases::from_elem(&child)
+                        .ok_warn_at(&child)
+                        .unwrap_or_default();
                 }
                 "conditions" => {
-                    conditions = Conditions::from_elem(&child).ok_warn().unwrap_or_default();
+                    conditions = Conditions::from_elem(&child)
+                        .ok_warn_at(&child)
+                        .unwrap_or_default();
                 }
                 "devices" => {
-                    devices = Devices::from_elem(&child).ok_warn().unwrap_or_default();
+                    devices = Devices::from_elem(&child)
+                        .ok_warn_at(&child)
+                        .unwrap_or_default();
                 }
                 "boards" => {
                     boards = Board::vec_from_children(child.children());
@@ -161,16 +213,47 @@ impl FromElem for Package {
             conditions,
             devices,
             boards,
+            path: None,
         })
     }
+
+    fn from_path(p: &Path) -> Result<Self, Error> {
+        let mut xml_str = String::new();
+        File::open(p)?.read_to_string(&mut xml_str)?;
+        Ok(Self {
+            path: Some(p.to_path_buf()),
+            ..Self::from_string(&xml_str)?
+        })
+    }
+
+    fn from_path_with(p: &Path, mode: ParseMode) -> Result<(Self, Vec<Diagnostic>), Error> {
+        let mut xml_str = String::new();
+        File::open(p)?.read_to_string(&mut xml_str)?;
+        let (package, diagnostics) = Self::from_string_with(&xml_str, mode)?;
+        let package = Self {
+            path: Some(p.to_path_buf()),
+            ..package
+        };
+        Ok((package, diagnostics))
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Board {
     name: String,
     mounted_devices: Vec<String>,
 }
 
+impl Board {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mounted_devices(&self) -> &[String] {
+        &self.mounted_devices
+    }
+}
+
 impl FromElem for Board {
     fn from_elem(e: &Node) -> Result<Self, Error> {
         Ok(Self {
@@ -186,7 +269,7 @@ impl FromElem for Board {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct Component {
     pub vendor: String,
     pub class: String,
@@ -243,13 +326,24 @@ impl Package {
         map
     }
 
+    /// Parse the pdsc found at the top level of a `.pack` archive.
+    pub fn from_pack_file(path: &Path) -> Result<Self, Error> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+        let name = archive
+            .file_names()
+            .find(|name| !name.contains('/') && name.ends_with(".pdsc"))
+            .map(String::from)
+            .ok_or_else(|| format_err!("No pdsc found in {:?}", path))?;
+        let mut pdsc = String::new();
+        archive.by_name(&name)?.read_to_string(&mut pdsc)?;
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            ..Self::from_string(&pdsc)?
+        })
+    }
+
     pub fn make_dump_devices(&self) -> Vec<(&str, DumpDevice<'_>)> {
-        let from_pack = FromPack::new(
-            &self.vendor,
-            &self.name,
-            &self.releases.latest_release().version,
-            &self.url,
-        );
+        let from_pack = FromPack::new(self);
         self.devices
             .0
             .iter()
@@ -257,73 +351,164 @@ impl Package {
             .collect()
     }
 }
+
+/// Parse the pdsc files at `paths` in parallel, returning a result for each
+/// file in the same order.
+pub fn parse_pdscs<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<Result<Package, Error>> {
+    parallel_map(paths, |path| {
+        let path = path.as_ref();
+        Package::from_path(path).map_err(|e| format_err!("parsing {:?}: {}", path, e))
+    })
+}
+
+/// How `dump_devices_with` writes the JSON indexes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DumpOptions {
+    /// Rewrite the indexes to hold exactly the devices and boards dumped
+    pub rebuild: bool,
+    pub format: JsonFormat,
+    /// How long to wait for other processes writing the same indexes, or
+    /// indefinitely when `None`
+    pub lock_timeout: Option<Duration>,
+}
+
+/// Merge the devices and boards of `pdscs` into the JSON indexes at
+/// `device_dest` and `board_dest`, or print them when no file is given.
+///
+/// Entries already in an index are replaced by those of the same pack, and
+/// dropped once the file they were read from is gone.
 pub fn dump_devices<'a, P: AsRef<Path>, I: IntoIterator<Item = &'a Package>>(
     pdscs: I,
     device_dest: Option<P>,
     board_dest: Option<P>,
 ) -> Result<(), Error> {
+    dump_devices_with(pdscs, device_dest, board_dest, DumpOptions::default())
+}
+
+/// Like `dump_devices`, but the indexes are rewritten to hold exactly the
+/// devices and boards of `pdscs`.
+pub fn rebuild_devices<'a, P: AsRef<Path>, I: IntoIterator<Item = &'a Package>>(
+    pdscs: I,
+    device_dest: Option<P>,
+    board_dest: Option<P>,
+) -> Result<(), Error> {
+    let options = DumpOptions {
+        rebuild: true,
+        ..DumpOptions::default()
+    };
+    dump_devices_with(pdscs, device_dest, board_dest, options)
+}
+
+/// Read the entries of an index written in any format, re-encoded in the
+/// current shape so that they can be borrowed from, and whether the index
+/// is already in `format`.
+fn read_index(path: &Path, kind: DumpKind, format: JsonFormat) -> Result<(Vec<u8>, bool), Error> {
+    let contents: serde_json::Value = serde_json::from_slice(&read_json(path)?)
+        .map_err(|e| format_err!("{:?} is corrupt: {}", path, e))?;
+    let (entries, in_format) = from_any_format(kind, contents, format)
+        .map_err(|e| format_err!("Could not read {:?}: {}", path, e))?;
+    Ok((serde_json::to_vec(&entries)?, in_format))
+}
+
+/// `dump_devices` and `rebuild_devices`, with the format of the indexes
+/// given by `options`. Existing indexes are read in any format.
+pub fn dump_devices_with<'a, P: AsRef<Path>, I: IntoIterator<Item = &'a Package>>(
+    pdscs: I,
+    device_dest: Option<P>,
+    board_dest: Option<P>,
+    options: DumpOptions,
+) -> Result<(), Error> {
+    let DumpOptions {
+        rebuild,
+        format,
+        lock_timeout,
+    } = options;
     let pdscs: Vec<&Package> = pdscs.into_iter().collect();
+    // Other processes may be updating the same files
+    let mut lock_files: Vec<&Path> = Vec::new();
+    for dest in device_dest.iter().chain(board_dest.iter()) {
+        if !lock_files.contains(&dest.as_ref()) {
+            lock_files.push(dest.as_ref());
+        }
+    }
+    let _locks = lock_files
+        .iter()
+        .map(|file| StoreLock::acquire_file(file, LockMode::Exclusive, lock_timeout))
+        .collect::<Result<Vec<_>, _>>()?;
+    let dumping: HashSet<(&str, &str)> = pdscs
+        .iter()
+        .map(|pdsc| (pdsc.vendor.as_str(), pdsc.name.as_str()))
+        .collect();
     let devices = pdscs
         .iter()
         .flat_map(|pdsc| pdsc.make_dump_devices().into_iter())
-        .collect::<HashMap<_, _>>();
+        .collect::<BTreeMap<_, _>>();
     match device_dest {
         Some(to_file) => {
-            if !devices.is_empty() {
-                let mut file_contents = Vec::new();
-                let mut old_devices: HashMap<&str, DumpDevice> = HashMap::new();
-                if let Ok(mut fd) = OpenOptions::new().read(true).open(to_file.as_ref()) {
-                    fd.read_to_end(&mut file_contents)?;
-                    old_devices = serde_json::from_slice(&file_contents).unwrap_or_default();
-                }
+            let to_file = to_file.as_ref();
+            let (file_contents, in_format) = read_index(to_file, DumpKind::Devices, format)?;
+            let mut old_devices: HashMap<&str, DumpDevice> = serde_json::from_slice(&file_contents)
+                .map_err(|e| format_err!("{:?} is corrupt: {}", to_file, e))?;
+            let old_len = old_devices.len();
+            old_devices.retain(|_, dev| !rebuild && !dev.from_pack.is_stale(&dumping));
+            // Indexes in another format are rewritten even without changes
+            let reformat = !in_format && old_len != 0;
+            if !devices.is_empty() || old_devices.len() != old_len || reformat {
                 let mut all_devices = BTreeMap::new();
                 all_devices.extend(old_devices.iter());
                 all_devices.extend(devices.iter());
-                let mut options = OpenOptions::new();
-                options.write(true);
-                options.create(true);
-                options.truncate(true);
-                if let Ok(fd) = options.open(to_file.as_ref()) {
-                    serde_json::to_writer_pretty(fd, &all_devices).unwrap();
-                } else {
-                    println!("Could not open file {:?}", to_file.as_ref());
-                }
+                write_json(
+                    to_file,
+                    &to_format(DumpKind::Devices, &all_devices, format)?,
+                )?;
             }
         }
-        None => println!("{}", &serde_json::to_string_pretty(&devices).unwrap()),
+        None => println!(
+            "{}",
+            serde_json::to_string_pretty(&to_format(DumpKind::Devices, &devices, format)?)?
+        ),
     }
     let boards = pdscs
         .iter()
-        .flat_map(|pdsc| pdsc.boards.iter())
-        .map(|b| (&b.name, b))
-        .collect::<HashMap<_, _>>();
+        .flat_map(|pdsc| {
+            pdsc.boards.iter().map(move |b| {
+                let board = DumpBoard {
+                    name: Cow::Borrowed(b.name.as_str()),
+                    mounted_devices: Cow::Borrowed(b.mounted_devices.as_slice()),
+                    from_pack: Some(FromPack::new(pdsc)),
+                };
+                (b.name.as_str(), board)
+            })
+        })
+        .collect::<BTreeMap<_, _>>();
     match board_dest {
         Some(to_file) => {
-            let mut file_contents = Vec::new();
-            let mut old_boards: HashMap<String, Board> = HashMap::new();
-            if let Ok(mut fd) = OpenOptions::new().read(true).open(to_file.as_ref()) {
-                fd.read_to_end(&mut file_contents)?;
-                old_boards = serde_json::from_slice(&file_contents).unwrap_or_default();
-            }
+            let to_file = to_file.as_ref();
+            let (file_contents, _) = read_index(to_file, DumpKind::Boards, format)?;
+            let mut old_boards: HashMap<String, DumpBoard> = serde_json::from_slice(&file_contents)
+                .map_err(|e| format_err!("{:?} is corrupt: {}", to_file, e))?;
+            // Boards indexed before their packs were recorded are kept
+            old_boards.retain(|_, board| {
+                !rebuild
+                    && !board
+                        .from_pack
+                        .as_ref()
+                        .is_some_and(|from| from.is_stale(&dumping))
+            });
             let mut all_boards = BTreeMap::new();
-            all_boards.extend(old_boards.iter());
-            all_boards.extend(boards.iter());
-            let mut options = OpenOptions::new();
-            options.write(true);
-            options.create(true);
-            options.truncate(true);
-            if let Ok(fd) = options.open(to_file.as_ref()) {
-                serde_json::to_writer_pretty(fd, &all_boards).unwrap();
-            } else {
-                println!("Could not open file {:?}", to_file.as_ref());
-            }
+            all_boards.extend(old_boards.iter().map(|(name, b)| (name.as_str(), b)));
+            all_boards.extend(boards.iter().map(|(name, b)| (*name, b)));
+            write_json(to_file, &to_format(DumpKind::Boards, &all_boards, format)?)?;
         }
-        None => println!("{}", &serde_json::to_string_pretty(&devices).unwrap()),
+        None => println!(
+            "{}",
+            serde_json::to_string_pretty(&to_format(DumpKind::Boards, &boards, format)?)?
+        ),
     }
     Ok(())
 }
 
-pub fn dumps_components<'a, I>(pdscs: I) -> Result<String, Error>
+pub fn dumps_components<'a, I>(pdscs: I, format: JsonFormat) -> Result<String, Error>
 where
     I: IntoIterator<Item = &'a Package>,
 {
@@ -331,5 +516,176 @@ where
         .into_iter()
         .flat_map(|pdsc| pdsc.make_components().into_iter())
         .collect::<Vec<_>>();
-    Ok(serde_json::to_string_pretty(&components)?)
+    Ok(serde_json::to_string_pretty(&to_format(
+        DumpKind::Components,
+        &components,
+        format,
+    )?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::{ParseFailure, Severity};
+    use std::fs::{read, remove_file, write};
+
+    fn pdsc(name: &str, version: &str, devices: &[&str]) -> String {
+        let devices: String = devices
+            .iter()
+            .map(|dev| {
+                format!(
+                    "<device Dname=\"{}\"><processor Dcore=\"Cortex-M4\"/></device>",
+                    dev
+                )
+            })
+            .collect();
+        format!(
+            "<package><vendor>V</vendor><name>{}</name><description/><url>./</url>\
+             <releases><release version=\"{}\">r</release></releases>\
+             <devices><family Dfamily=\"F\">{}</family></devices>\
+             <boards><board name=\"{}Board\"/></boards></package>",
+            name, version, devices, name
+        )
+    }
+
+    fn index_keys(path: &Path) -> Vec<String> {
+        let index: serde_json::Value = serde_json::from_slice(&read(path).unwrap()).unwrap();
+        let kind = match path.file_name().unwrap().to_str() {
+            Some("aliases.json") => DumpKind::Boards,
+            _ => DumpKind::Devices,
+        };
+        let (entries, _) = from_any_format(kind, index, JsonFormat::Versioned).unwrap();
+        entries.as_object().unwrap().keys().cloned().collect()
+    }
+
+    #[test]
+    fn stale_devices_are_removed() {
+        let dir = std::env::temp_dir().join(format!("cmsis-dump-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let devices = dir.join("index.json");
+        let boards = dir.join("aliases.json");
+        let load = |file: &str, contents: String| {
+            let path = dir.join(file);
+            write(&path, contents).unwrap();
+            Package::from_path(&path).unwrap()
+        };
+
+        let a1 = load("V.A.1.0.0.pdsc", pdsc("A", "1.0.0", &["Old", "Kept"]));
+        let b1 = load("V.B.1.0.0.pdsc", pdsc("B", "1.0.0", &["Other"]));
+        dump_devices(vec![&a1, &b1], Some(&devices), Some(&boards)).unwrap();
+        assert_eq!(index_keys(&devices), vec!["Kept", "Old", "Other"]);
+
+        // A new release replaces every device of the pack
+        let a2 = load("V.A.2.0.0.pdsc", pdsc("A", "2.0.0", &["Kept", "New"]));
+        dump_devices(Some(&a2), Some(&devices), Some(&boards)).unwrap();
+        assert_eq!(index_keys(&devices), vec!["Kept", "New", "Other"]);
+        assert_eq!(index_keys(&boards), vec!["ABoard", "BBoard"]);
+
+        // Packs whose pdsc is gone are dropped
+        remove_file(dir.join("V.B.1.0.0.pdsc")).unwrap();
+        dump_devices(Some(&a2), Some(&devices), Some(&boards)).unwrap();
+        assert_eq!(index_keys(&devices), vec!["Kept", "New"]);
+        assert_eq!(index_keys(&boards), vec!["ABoard"]);
+
+        let c1 = load("V.C.1.0.0.pdsc", pdsc("C", "1.0.0", &["Third"]));
+        rebuild_devices(Some(&c1), Some(&devices), Some(&boards)).unwrap();
+        assert_eq!(index_keys(&devices), vec!["Third"]);
+        assert_eq!(index_keys(&boards), vec!["CBoard"]);
+
+        // Switching formats rewrites the indexes without losing entries
+        let legacy = DumpOptions {
+            format: JsonFormat::Legacy,
+            ..DumpOptions::default()
+        };
+        dump_devices_with(None, Some(&devices), Some(&boards), legacy).unwrap();
+        let contents: serde_json::Value = serde_json::from_slice(&read(&devices).unwrap()).unwrap();
+        assert_eq!(contents["Third"]["processors"][0]["ap"], 0);
+        dump_devices(None, Some(&devices), Some(&boards)).unwrap();
+        let contents: serde_json::Value = serde_json::from_slice(&read(&devices).unwrap()).unwrap();
+        assert_eq!(contents["format_version"], FORMAT_VERSION);
+        assert_eq!(index_keys(&devices), vec!["Third"]);
+
+        // Only the indexes themselves are locked, for at most the timeout
+        assert!(!dir.join(".lock").exists());
+        let held = StoreLock::acquire_file(&devices, LockMode::Exclusive, None).unwrap();
+        let impatient = DumpOptions {
+            lock_timeout: Some(Duration::from_millis(200)),
+            ..DumpOptions::default()
+        };
+        assert!(dump_devices_with(None, Some(&devices), Some(&boards), impatient).is_err());
+        drop(held);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_diagnostics() {
+        let text = "<package><vendor>V</vendor><name>P</name><description/><url>./</url>
+<releases><release version=\"1.0.0\">r</release></releases>
+<devices><family Dfamily=\"F\">
+  <device Dname=\"GOOD\"><processor Dcore=\"Cortex-M4\"/>
+    <memory id=\"IROM1\" size=\"0x1000\"/>
+  </device>
+  <device Dname=\"BAD\"/>
+</family></devices>
+<components>
+  <component Cclass=\"Good\" Cgroup=\"G\"><description>d</description></component>
+  <component Cclass=\"Bad\" Cgroup=\"G\"/>
+  <bundle Cbundle=\"B\" Cclass=\"Board\" Cversion=\"1.0.0\"><description>b</description><doc>d</doc>
+    <component Cgroup=\"Kept\"><description>d</description></component>
+    <component Cgroup=\"Dropped\"/>
+  </bundle>
+</components></package>";
+
+        let (package, diagnostics) = Package::from_string_with(text, ParseMode::Lenient).unwrap();
+        assert_eq!(package.devices.0.keys().collect::<Vec<_>>(), vec!["GOOD"]);
+        assert!(package.devices.0["GOOD"].memories.0.is_empty());
+        let components: Vec<_> = package
+            .make_components()
+            .into_iter()
+            .map(|comp| (comp.class, comp.group))
+            .collect();
+        assert_eq!(
+            components,
+            [
+                (String::from("Good"), String::from("G")),
+                (String::from("Board"), String::from("Kept"))
+            ]
+        );
+        assert_eq!(diagnostics.len(), 4);
+        let memory = &diagnostics[0];
+        assert_eq!(memory.severity, Severity::Warning);
+        assert_eq!(
+            memory.path,
+            "/package/devices/family[Dfamily=F]/device[Dname=GOOD]/memory[id=IROM1]"
+        );
+        assert_eq!((memory.line, memory.column), (5, 5));
+        assert_eq!(diagnostics[1].path, "/package/devices/family[Dfamily=F]");
+        assert_eq!(
+            diagnostics[2].path,
+            "/package/components/component[Cclass=Bad]"
+        );
+        assert_eq!(
+            diagnostics[3].path,
+            "/package/components/bundle[Cclass=Board]/component"
+        );
+
+        let failure = Package::from_string_with(text, ParseMode::Strict)
+            .err()
+            .unwrap();
+        let failure = failure.downcast_ref::<ParseFailure>().unwrap();
+        assert_eq!(failure.diagnostics.len(), 4);
+        assert!(failure
+            .diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity == Severity::Error));
+
+        let failure =
+            Package::from_string_with("<package>\n  <name>P</nmae></package>", ParseMode::Lenient)
+                .err()
+                .unwrap();
+        let failure = failure.downcast_ref::<ParseFailure>().unwrap();
+        assert_eq!(failure.diagnostics[0].line, 2);
+        assert!(failure.diagnostics[0].path.is_empty());
+    }
 }
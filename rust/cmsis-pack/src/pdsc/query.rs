@@ -0,0 +1,506 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Error;
+use serde::{Deserialize, Deserializer};
+
+use crate::pdsc::{Board, Core, Device, DumpBoard, DumpDevice, FromPack, Package, FPU};
+use crate::utils::compare_versions;
+
+/// The pack a device was declared in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackId {
+    pub vendor: String,
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub path: Option<PathBuf>,
+}
+
+impl PackId {
+    fn new(package: &Package) -> Self {
+        Self {
+            vendor: package.vendor.clone(),
+            name: package.name.clone(),
+            version: package.releases.latest_release().version.clone(),
+            url: package.url.clone(),
+            path: package.path.clone(),
+        }
+    }
+
+    fn as_from_pack(&self) -> FromPack<'_> {
+        FromPack {
+            vendor: &self.vendor,
+            pack: &self.name,
+            version: &self.version,
+            url: &self.url,
+            path: self.path.as_deref().map(Into::into),
+        }
+    }
+}
+
+/// A device in a `DeviceIndex`, with the totals used to filter on memory.
+#[derive(Debug, Clone)]
+pub struct IndexedDevice {
+    pub device: Device,
+    pub pack: PackId,
+    /// Total size of the executable, read-only memories
+    pub flash_size: u64,
+    /// Total size of the writable memories
+    pub ram_size: u64,
+    /// Names of the boards the device is mounted on
+    pub boards: Vec<String>,
+}
+
+fn parse_opt<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = Error>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Criteria for `DeviceIndex::search`. A device matches when it meets every
+/// criterion that is set; text is matched case-insensitively.
+///
+/// Read from JSON, cores and FPUs are written as in a pdsc, for example
+/// `{"name": ["stm32f4"], "core": "Cortex-M4", "min_flash": 65536}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceQuery {
+    /// Every one of these must appear in the device name
+    pub name: Vec<String>,
+    /// Appears in the device vendor, or the pack vendor when the device has
+    /// none
+    pub vendor: Option<String>,
+    /// Appears in the device family or subfamily
+    pub family: Option<String>,
+    #[serde(deserialize_with = "parse_opt")]
+    pub core: Option<Core>,
+    #[serde(deserialize_with = "parse_opt")]
+    pub fpu: Option<FPU>,
+    pub min_flash: Option<u64>,
+    pub max_flash: Option<u64>,
+    pub min_ram: Option<u64>,
+    pub max_ram: Option<u64>,
+    /// Appears in the name of a board the device is mounted on
+    pub board: Option<String>,
+}
+
+impl DeviceQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name<S: Into<String>>(self, term: S) -> Self {
+        let mut name = self.name;
+        name.push(term.into());
+        Self { name, ..self }
+    }
+
+    pub fn vendor<S: Into<String>>(self, vendor: S) -> Self {
+        Self {
+            vendor: Some(vendor.into()),
+            ..self
+        }
+    }
+
+    pub fn family<S: Into<String>>(self, family: S) -> Self {
+        Self {
+            family: Some(family.into()),
+            ..self
+        }
+    }
+
+    pub fn core(self, core: Core) -> Self {
+        Self {
+            core: Some(core),
+            ..self
+        }
+    }
+
+    pub fn fpu(self, fpu: FPU) -> Self {
+        Self {
+            fpu: Some(fpu),
+            ..self
+        }
+    }
+
+    /// Limit the total flash size to `min..=max`, either bound being optional
+    pub fn flash(self, min: Option<u64>, max: Option<u64>) -> Self {
+        Self {
+            min_flash: min,
+            max_flash: max,
+            ..self
+        }
+    }
+
+    /// Limit the total RAM size to `min..=max`, either bound being optional
+    pub fn ram(self, min: Option<u64>, max: Option<u64>) -> Self {
+        Self {
+            min_ram: min,
+            max_ram: max,
+            ..self
+        }
+    }
+
+    pub fn board<S: Into<String>>(self, board: S) -> Self {
+        Self {
+            board: Some(board.into()),
+            ..self
+        }
+    }
+
+    /// Lower cased copies of the text criteria
+    fn lowered(&self) -> Self {
+        let lower = |s: &Option<String>| s.as_ref().map(|s| s.to_lowercase());
+        Self {
+            name: self.name.iter().map(|s| s.to_lowercase()).collect(),
+            vendor: lower(&self.vendor),
+            family: lower(&self.family),
+            board: lower(&self.board),
+            ..self.clone()
+        }
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        let dev = &entry.indexed.device;
+        let within = |size: u64, min: Option<u64>, max: Option<u64>| {
+            min.map_or(true, |min| size >= min) && max.map_or(true, |max| size <= max)
+        };
+        self.name
+            .iter()
+            .all(|term| entry.name.contains(term.as_str()))
+            && self
+                .vendor
+                .as_ref()
+                .map_or(true, |vendor| entry.vendor.contains(vendor.as_str()))
+            && self.family.as_ref().map_or(true, |family| {
+                dev.family.to_lowercase().contains(family.as_str())
+                    || dev
+                        .sub_family
+                        .as_ref()
+                        .is_some_and(|sub| sub.to_lowercase().contains(family.as_str()))
+            })
+            && self
+                .core
+                .as_ref()
+                .map_or(true, |core| dev.processors.iter().any(|p| &p.core == core))
+            && self
+                .fpu
+                .as_ref()
+                .map_or(true, |fpu| dev.processors.iter().any(|p| &p.fpu == fpu))
+            && within(entry.indexed.flash_size, self.min_flash, self.max_flash)
+            && within(entry.indexed.ram_size, self.min_ram, self.max_ram)
+            && self.board.as_ref().map_or(true, |board| {
+                entry
+                    .indexed
+                    .boards
+                    .iter()
+                    .any(|name| name.to_lowercase().contains(board.as_str()))
+            })
+    }
+}
+
+struct Entry {
+    /// Lower cased device name
+    name: String,
+    /// Lower cased vendor, without the numeric vendor id
+    vendor: String,
+    indexed: IndexedDevice,
+}
+
+/// An in-memory database of the devices and boards declared by a set of
+/// packs, for repeated lookups without going through the JSON indexes.
+///
+/// When several packs declare a device, the newest pack wins.
+#[derive(Default)]
+pub struct DeviceIndex {
+    devices: Vec<Entry>,
+    by_name: HashMap<String, usize>,
+    boards: Vec<Board>,
+}
+
+/// How closely `name` matches the search terms, for ordering results:
+/// exact matches come first, then names starting with a term.
+fn rank(name: &str, terms: &[String]) -> u8 {
+    if terms.iter().any(|term| term == name) {
+        0
+    } else if terms.iter().any(|term| name.starts_with(term.as_str())) {
+        1
+    } else {
+        2
+    }
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+impl DeviceIndex {
+    pub fn new<'a, I: IntoIterator<Item = &'a Package>>(packages: I) -> Self {
+        let mut index = Self::default();
+        let mut mounted: HashMap<String, Vec<String>> = HashMap::new();
+        let mut boards: BTreeMap<&str, &Board> = BTreeMap::new();
+        for package in packages {
+            let pack = PackId::new(package);
+            for device in package.devices.0.values() {
+                index.insert(device, &pack);
+            }
+            for board in package.boards.iter() {
+                boards.insert(&board.name, board);
+            }
+        }
+        for board in boards.into_values() {
+            for device in board.mounted_devices.iter() {
+                mounted
+                    .entry(device.to_lowercase())
+                    .or_default()
+                    .push(board.name.clone());
+            }
+            index.boards.push(board.clone());
+        }
+        for entry in index.devices.iter_mut() {
+            if let Some(boards) = mounted.remove(&entry.name) {
+                entry.indexed.boards = boards;
+            }
+        }
+        index
+    }
+
+    fn insert(&mut self, device: &Device, pack: &PackId) {
+        let name = device.name.to_lowercase();
+        if let Some(&idx) = self.by_name.get(&name) {
+            let existing = &self.devices[idx].indexed.pack;
+            if compare_versions(&pack.version, &existing.version) == Ordering::Less {
+                return;
+            }
+        }
+        let (mut flash_size, mut ram_size) = (0, 0);
+        for memory in device.memories.0.values() {
+            if memory.access.peripheral {
+                continue;
+            }
+            if memory.access.write {
+                ram_size += memory.size;
+            } else if memory.access.execute {
+                flash_size += memory.size;
+            }
+        }
+        let vendor = device.vendor.as_deref().unwrap_or(&pack.vendor);
+        let vendor = vendor.split(':').next().unwrap_or_default().to_lowercase();
+        let entry = Entry {
+            name: name.clone(),
+            vendor,
+            indexed: IndexedDevice {
+                device: device.clone(),
+                pack: pack.clone(),
+                flash_size,
+                ram_size,
+                boards: Vec::new(),
+            },
+        };
+        match self.by_name.get(&name) {
+            Some(&idx) => self.devices[idx] = entry,
+            None => {
+                self.by_name.insert(name, self.devices.len());
+                self.devices.push(entry);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Look up a device by its exact name, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&IndexedDevice> {
+        self.by_name
+            .get(&name.to_lowercase())
+            .map(|&idx| &self.devices[idx].indexed)
+    }
+
+    /// The devices matching `query`: exact name matches first, then names
+    /// starting with a search term, and otherwise in name order.
+    pub fn search(&self, query: &DeviceQuery) -> Vec<&IndexedDevice> {
+        let query = query.lowered();
+        let mut found: Vec<&Entry> = self.devices.iter().filter(|e| query.matches(e)).collect();
+        found.sort_by(|a, b| {
+            rank(&a.name, &query.name)
+                .cmp(&rank(&b.name, &query.name))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        found.into_iter().map(|entry| &entry.indexed).collect()
+    }
+
+    /// Up to `limit` device names closest to `name`, for suggestions when a
+    /// lookup fails.
+    pub fn closest(&self, name: &str, limit: usize) -> Vec<&str> {
+        let name = name.to_lowercase();
+        let mut scored: Vec<(usize, &Entry)> = self
+            .devices
+            .iter()
+            .map(|entry| (edit_distance(&name, &entry.name), entry))
+            .collect();
+        scored.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.name.cmp(&b.1.name)));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, entry)| entry.indexed.device.name.as_str())
+            .collect()
+    }
+
+    /// The boards whose names contain every one of `terms`, ignoring case.
+    pub fn boards<S: AsRef<str>>(&self, terms: &[S]) -> Vec<&Board> {
+        let terms: Vec<String> = terms.iter().map(|t| t.as_ref().to_lowercase()).collect();
+        self.boards
+            .iter()
+            .filter(|board| {
+                let name = board.name.to_lowercase();
+                terms.iter().all(|term| name.contains(term.as_str()))
+            })
+            .collect()
+    }
+
+    /// Search for `query`, returning the devices found and the boards
+    /// matching its name terms in the format of the JSON indexes.
+    pub fn search_json(&self, query: &DeviceQuery) -> Result<String, Error> {
+        let devices: BTreeMap<&str, DumpDevice> = self
+            .search(query)
+            .into_iter()
+            .map(|found| {
+                let dump = DumpDevice::from_device(&found.device, found.pack.as_from_pack());
+                (found.device.name.as_str(), dump)
+            })
+            .collect();
+        let boards: BTreeMap<&str, DumpBoard> = self
+            .boards(&query.name)
+            .into_iter()
+            .map(|board| {
+                let dump = DumpBoard {
+                    name: board.name.as_str().into(),
+                    mounted_devices: board.mounted_devices.as_slice().into(),
+                    from_pack: None,
+                };
+                (board.name.as_str(), dump)
+            })
+            .collect();
+        let mut out = serde_json::Map::new();
+        out.insert("devices".into(), serde_json::to_value(devices)?);
+        out.insert("boards".into(), serde_json::to_value(boards)?);
+        Ok(serde_json::to_string(&out)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::FromElem;
+
+    const PDSC: &str = r#"<package>
+  <vendor>Acme</vendor>
+  <name>Parts_DFP</name>
+  <description>Parts</description>
+  <url>http://example.com/</url>
+  <releases><release version="2.0.0">Newer</release></releases>
+  <devices>
+    <family Dfamily="Widgets" Dvendor="Acme:99">
+      <processor Dcore="Cortex-M4" Dfpu="SP_FPU"/>
+      <subFamily DsubFamily="W1">
+        <device Dname="ACME100">
+          <memory id="IROM1" start="0x0" size="0x10000"/>
+          <memory id="IRAM1" start="0x20000000" size="0x4000"/>
+        </device>
+        <device Dname="ACME100X">
+          <memory id="IROM1" start="0x0" size="0x40000"/>
+          <memory id="IRAM1" start="0x20000000" size="0x8000"/>
+        </device>
+      </subFamily>
+      <device Dname="XACME100">
+        <processor Dcore="Cortex-M0+" Dfpu="0"/>
+        <memory id="IROM1" start="0x0" size="0x8000"/>
+      </device>
+    </family>
+  </devices>
+  <boards>
+    <board name="Acme Eval Kit">
+      <mountedDevice Dvendor="Acme:99" Dname="ACME100X"/>
+    </board>
+  </boards>
+</package>"#;
+
+    fn index() -> DeviceIndex {
+        let package = Package::from_string(PDSC).unwrap();
+        DeviceIndex::new(&[package])
+    }
+
+    fn names(found: Vec<&IndexedDevice>) -> Vec<&str> {
+        found.iter().map(|d| d.device.name.as_str()).collect()
+    }
+
+    #[test]
+    fn search_devices() {
+        let index = index();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get("acme100x").unwrap().flash_size, 0x40000);
+        assert!(index.get("acme").is_none());
+
+        let query = DeviceQuery::new().name("acme100");
+        assert_eq!(
+            names(index.search(&query)),
+            vec!["ACME100", "ACME100X", "XACME100"]
+        );
+        let query = query.vendor("ACME").family("w1");
+        assert_eq!(names(index.search(&query)), vec!["ACME100", "ACME100X"]);
+        let query = query.flash(Some(0x20000), None);
+        assert_eq!(names(index.search(&query)), vec!["ACME100X"]);
+        let query = DeviceQuery::new().core(Core::CortexM0Plus);
+        assert_eq!(names(index.search(&query)), vec!["XACME100"]);
+        let query = DeviceQuery::new().board("eval");
+        assert_eq!(names(index.search(&query)), vec!["ACME100X"]);
+        assert_eq!(index.closest("acme1000x", 1), vec!["ACME100X"]);
+    }
+
+    #[test]
+    fn search_from_json() {
+        let index = index();
+        let query: DeviceQuery =
+            serde_json::from_str(r#"{"name": ["acme"], "fpu": "SP_FPU", "max_ram": 16384}"#)
+                .unwrap();
+        let found: serde_json::Value =
+            serde_json::from_str(&index.search_json(&query).unwrap()).unwrap();
+        let devices = found["devices"].as_object().unwrap();
+        assert_eq!(devices.keys().collect::<Vec<_>>(), vec!["ACME100"]);
+        assert_eq!(devices["ACME100"]["from_pack"]["version"], "2.0.0");
+        assert_eq!(
+            found["boards"]["Acme Eval Kit"]["mounted_devices"][0],
+            "ACME100X"
+        );
+        // Cores this crate doesn't know may still be searched for
+        let query: DeviceQuery = serde_json::from_str(r#"{"core": "Cortex-Q9"}"#).unwrap();
+        assert_eq!(query.core, Some(Core::Other("Cortex-Q9".into())));
+        assert!(serde_json::from_str::<DeviceQuery>(r#"{"fpu": "QP_FPU"}"#).is_err());
+    }
+}
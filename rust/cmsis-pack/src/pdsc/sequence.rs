@@ -0,0 +1,135 @@
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::prelude::*;
+use anyhow::{format_err, Error};
+
+/// The text of `e`, without the comments that may split it.
+fn text(e: &Node) -> String {
+    let text: String = e
+        .children()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    text.trim().to_string()
+}
+
+fn flag(e: &Node, name: &str) -> bool {
+    matches!(e.attribute(name), Some("1") | Some("true"))
+}
+
+/// A step of a debug sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SequenceStep {
+    /// Statements run in order; an atomic block runs without interruption
+    /// by the debugger
+    Block { atomic: bool, code: String },
+    /// Steps run when `condition` holds, repeated while `repeat_while`
+    /// holds, for at most `timeout` microseconds
+    Control {
+        condition: Option<String>,
+        repeat_while: Option<String>,
+        timeout: Option<u64>,
+        steps: Vec<SequenceStep>,
+    },
+}
+
+fn steps(e: &Node) -> Vec<SequenceStep> {
+    e.children()
+        .filter_map(|child| match child.tag_name().name() {
+            "block" => Some(SequenceStep::Block {
+                atomic: flag(&child, "atomic"),
+                code: text(&child),
+            }),
+            "control" => Some(SequenceStep::Control {
+                condition: child.attribute("if").map(String::from),
+                repeat_while: child.attribute("while").map(String::from),
+                timeout: attr_parse(&child, "timeout").ok(),
+                steps: steps(&child),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A debug access sequence, overriding the default behaviour of a debugger
+/// for the operation it is named after, such as `ResetSystem`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sequence {
+    pub name: String,
+    /// The processor the sequence applies to, or all when `None`
+    pub p_name: Option<String>,
+    /// The debugger should skip the operation entirely
+    pub disable: bool,
+    pub steps: Vec<SequenceStep>,
+}
+
+impl FromElem for Sequence {
+    fn from_elem(e: &Node) -> Result<Self, Error> {
+        assert_root_name(e, "sequence")?;
+        Ok(Self {
+            name: attr_map(e, "name")?,
+            p_name: e.attribute("Pname").map(String::from),
+            disable: flag(e, "disable"),
+            steps: steps(e),
+        })
+    }
+}
+
+/// Variables shared by the debug sequences of a processor, with their
+/// initial values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugVars {
+    pub p_name: Option<String>,
+    /// File in the project that may override the values
+    pub config_file: Option<String>,
+    pub code: String,
+}
+
+impl FromElem for DebugVars {
+    fn from_elem(e: &Node) -> Result<Self, Error> {
+        assert_root_name(e, "debugvars")?;
+        Ok(Self {
+            p_name: e.attribute("Pname").map(String::from),
+            config_file: e.attribute("configfile").map(String::from),
+            code: text(e),
+        })
+    }
+}
+
+/// The sequences of a `sequences` element.
+pub(crate) fn parse_sequences(e: &Node) -> Vec<Sequence> {
+    e.children()
+        .filter(|child| child.is_element())
+        .filter_map(|child| {
+            Sequence::from_elem(&child)
+                .map_err(|e| format_err!("Invalid sequence: {}", e))
+                .ok_warn_at(&child)
+        })
+        .collect()
+}
+
+/// Sequences and variables are inherited from families, unless redefined
+/// for the same processor.
+pub(crate) fn merge_sequences(mut own: Vec<Sequence>, parent: &[Sequence]) -> Vec<Sequence> {
+    let inherited: Vec<Sequence> = parent
+        .iter()
+        .filter(|seq| {
+            !own.iter()
+                .any(|mine| mine.name == seq.name && mine.p_name == seq.p_name)
+        })
+        .cloned()
+        .collect();
+    own.extend(inherited);
+    own
+}
+
+pub(crate) fn merge_debug_vars(mut own: Vec<DebugVars>, parent: &[DebugVars]) -> Vec<DebugVars> {
+    let inherited: Vec<DebugVars> = parent
+        .iter()
+        .filter(|vars| !own.iter().any(|mine| mine.p_name == vars.p_name))
+        .cloned()
+        .collect();
+    own.extend(inherited);
+    own
+}
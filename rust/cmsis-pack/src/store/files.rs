@@ -0,0 +1,172 @@
+use std::fs::{read, read_dir, File};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{format_err, Error};
+use zip::ZipArchive;
+
+use crate::pdsc::Package;
+
+/// The files of one pack, read from its `.pack` archive or from a directory
+/// it was extracted to.
+///
+/// Paths are given as written in the pdsc. Packs are often written on
+/// systems that ignore case, so a path that does not match exactly is
+/// matched ignoring case.
+pub enum PackFiles {
+    Archive(PathBuf, ZipArchive<File>),
+    Directory(PathBuf),
+}
+
+/// Pdsc files use either separator, and sometimes start with `./`. Paths
+/// leading out of the pack are refused, as pdsc files come from anywhere.
+fn normalize(name: &str) -> Result<String, Error> {
+    let name = name.replace('\\', "/");
+    let name = name.trim_start_matches("./");
+    let escapes = Path::new(name).components().any(|part| {
+        matches!(
+            part,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_)
+        )
+    });
+    if escapes {
+        return Err(format_err!("{} is not a path in the pack", name));
+    }
+    Ok(name.to_string())
+}
+
+impl PackFiles {
+    /// Open a `.pack` archive, or the root directory of an extracted pack.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            return Ok(PackFiles::Directory(path.to_path_buf()));
+        }
+        let archive = File::open(path)
+            .map_err(Error::from)
+            .and_then(|fd| Ok(ZipArchive::new(fd)?))
+            .map_err(|e| format_err!("Could not open {:?}: {}", path, e))?;
+        Ok(PackFiles::Archive(path.to_path_buf(), archive))
+    }
+
+    /// Open the pack `package` was parsed from: the archive it was read
+    /// from, the installed `<vendor>/<name>/<version>.pack` in `pack_store`,
+    /// or else the directory holding its pdsc, as found in extracted packs.
+    pub fn for_package(package: &Package, pack_store: Option<&Path>) -> Result<Self, Error> {
+        let version = &package.releases.latest_release().version;
+        let pdsc = package.path.as_deref();
+        if let Some(path) = pdsc.filter(|path| path.extension().is_some_and(|ext| ext == "pack")) {
+            return Self::open(path);
+        }
+        if let Some(store) = pack_store {
+            let installed = store
+                .join(&package.vendor)
+                .join(&package.name)
+                .join(format!("{}.pack", version));
+            if installed.exists() {
+                return Self::open(installed);
+            }
+        }
+        let dir = pdsc.and_then(Path::parent).map(|dir| {
+            if dir == Path::new("") {
+                Path::new(".")
+            } else {
+                dir
+            }
+        });
+        match dir {
+            Some(dir) if Some(dir) != pack_store => Self::open(dir),
+            _ => Err(format_err!(
+                "Pack {}.{} {} is not installed",
+                package.vendor,
+                package.name,
+                version
+            )),
+        }
+    }
+
+    /// Read the file at `name` in the pack.
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let name = normalize(name)?;
+        match self {
+            PackFiles::Archive(path, archive) => {
+                let lower = name.to_lowercase();
+                let found = archive
+                    .file_names()
+                    .find(|file| *file == name)
+                    .or_else(|| {
+                        archive
+                            .file_names()
+                            .find(|file| file.to_lowercase() == lower)
+                    })
+                    .map(String::from);
+                let found = found.ok_or_else(|| format_err!("No file {} in {:?}", name, path))?;
+                let mut contents = Vec::new();
+                archive
+                    .by_name(&found)?
+                    .read_to_end(&mut contents)
+                    .map_err(|e| format_err!("Could not read {} from {:?}: {}", name, path, e))?;
+                Ok(contents)
+            }
+            PackFiles::Directory(root) => {
+                let path = find_ignoring_case(root, &name)
+                    .ok_or_else(|| format_err!("No file {} in {:?}", name, root))?;
+                read(&path).map_err(|e| format_err!("Could not read {:?}: {}", path, e))
+            }
+        }
+    }
+}
+
+fn find_ignoring_case(root: &Path, name: &str) -> Option<PathBuf> {
+    let exact = root.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    let mut found = root.to_path_buf();
+    for part in name.split('/').filter(|part| !part.is_empty()) {
+        let lower = part.to_lowercase();
+        found = read_dir(&found)
+            .ok()?
+            .filter_map(|dirent| dirent.ok())
+            .find(|dirent| dirent.file_name().to_string_lossy().to_lowercase() == lower)?
+            .path();
+    }
+    Some(found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::FromElem;
+    use std::fs::{create_dir_all, write};
+
+    #[test]
+    fn read_from_archive_and_directory() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/test-pack-index");
+        let pack = Path::new(fixtures).join("MyVendor.MyPack.1.1.0.pack");
+        let mut files = PackFiles::open(&pack).unwrap();
+        assert!(files.read("MyVendor.MyPack.pdsc").is_ok());
+        assert!(files.read(".\\Flash\\ALGO.flm").is_ok());
+        assert!(files.read("missing.txt").is_err());
+
+        let dir = std::env::temp_dir().join(format!("cmsis-files-{}", std::process::id()));
+        create_dir_all(dir.join("SVD")).unwrap();
+        write(dir.join("SVD/Device.svd"), "<device/>").unwrap();
+        let pdsc = dir.join("MyVendor.MyPack.pdsc");
+        std::fs::copy(Path::new(fixtures).join("MyVendor.MyPack.pdsc"), &pdsc).unwrap();
+        let package = Package::from_path(&pdsc).unwrap();
+        let mut files = PackFiles::for_package(&package, None).unwrap();
+        assert_eq!(files.read("svd\\device.SVD").unwrap(), b"<device/>");
+        // Files that exist, but outside the pack
+        let up = format!(
+            "..\\{}\\SVD\\Device.svd",
+            dir.file_name().unwrap().to_string_lossy()
+        );
+        let absolute = dir.join("SVD/Device.svd").to_string_lossy().into_owned();
+        assert!(files.read(&up).is_err());
+        assert!(files.read(&absolute).is_err());
+        // A pdsc in the pack store belongs to a pack that was not installed
+        assert!(PackFiles::for_package(&package, Some(&dir)).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
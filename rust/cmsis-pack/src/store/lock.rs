@@ -0,0 +1,188 @@
+use std::fs::{create_dir_all, read_to_string, remove_file, write, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{format_err, Error};
+use fs2::FileExt;
+
+use crate::utils::json::with_suffix;
+
+const LOCK_FILE: &str = ".lock";
+const OWNER_FILE: &str = ".lock-owner";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Any number of readers may hold the lock at once
+    Shared,
+    /// A single writer holds the lock, with no readers
+    Exclusive,
+}
+
+/// An advisory lock on a directory or file shared between processes,
+/// released when dropped.
+///
+/// The lock is taken with the operating system's file locking on
+/// `<dir>/.lock`, so a lock held by a process that dies is released with it.
+/// Exclusive holders describe themselves in `<dir>/.lock-owner`; finding that
+/// file on acquiring the lock means the previous holder died while holding
+/// it, and it is reported and replaced. A single file is locked the same
+/// way, through `<file>.lock` and `<file>.lock-owner` beside it.
+pub struct StoreLock {
+    file: File,
+    owner: Option<PathBuf>,
+}
+
+fn contended(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock
+        || err.raw_os_error() == fs2::lock_contended_error().raw_os_error()
+}
+
+fn describe_self() -> String {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    format!(
+        "process {} since {}s after the epoch",
+        std::process::id(),
+        since
+    )
+}
+
+impl StoreLock {
+    /// Lock `dir`, creating it if needed, waiting up to `timeout` for other
+    /// holders to release it. Without a timeout this waits indefinitely.
+    pub fn acquire(dir: &Path, mode: LockMode, timeout: Option<Duration>) -> Result<Self, Error> {
+        create_dir_all(dir).map_err(|e| format_err!("Could not create {:?}: {}", dir, e))?;
+        Self::lock(
+            dir,
+            dir.join(LOCK_FILE),
+            dir.join(OWNER_FILE),
+            mode,
+            timeout,
+        )
+    }
+
+    /// Lock the single file `path`, which need not exist, in the same way.
+    pub fn acquire_file(
+        path: &Path,
+        mode: LockMode,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let lock_path = with_suffix(path, LOCK_FILE);
+        let owner_path = with_suffix(path, OWNER_FILE);
+        Self::lock(path, lock_path, owner_path, mode, timeout)
+    }
+
+    /// Lock `locked` through the lock file `path`.
+    fn lock(
+        locked: &Path,
+        path: PathBuf,
+        owner_path: PathBuf,
+        mode: LockMode,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| format_err!("Could not open lock file {:?}: {}", path, e))?;
+
+        let start = Instant::now();
+        let mut waiting = false;
+        loop {
+            // Called through `FileExt`, as newer versions of std have
+            // inherent locking methods on `File` with other error types.
+            let res = match mode {
+                LockMode::Shared => FileExt::try_lock_shared(&file),
+                LockMode::Exclusive => FileExt::try_lock_exclusive(&file),
+            };
+            match res {
+                Ok(()) => break,
+                Err(err) if contended(&err) => {
+                    let holder = read_to_string(&owner_path)
+                        .unwrap_or_else(|_| String::from("another process"));
+                    if timeout.is_some_and(|t| start.elapsed() >= t) {
+                        return Err(format_err!(
+                            "Timed out waiting for the lock on {:?}, held by {}",
+                            locked,
+                            holder.trim()
+                        ));
+                    }
+                    if !waiting {
+                        log::info!(
+                            "Waiting for the lock on {:?}, held by {}",
+                            locked,
+                            holder.trim()
+                        );
+                        waiting = true;
+                    }
+                    sleep(POLL_INTERVAL);
+                }
+                Err(err) => return Err(format_err!("Could not lock {:?}: {}", path, err)),
+            }
+        }
+
+        let owner = match mode {
+            LockMode::Shared => None,
+            LockMode::Exclusive => {
+                if let Ok(stale) = read_to_string(&owner_path) {
+                    log::warn!(
+                        "Removed stale lock on {:?} left by {}",
+                        locked,
+                        stale.trim()
+                    );
+                }
+                write(&owner_path, describe_self())?;
+                Some(owner_path)
+            }
+        };
+        Ok(StoreLock { file, owner })
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        if let Some(owner) = &self.owner {
+            let _ = remove_file(owner);
+        }
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_and_exclusive() {
+        let dir = std::env::temp_dir().join(format!("cmsis-lock-{}", std::process::id()));
+        let short = Some(Duration::from_millis(200));
+
+        let first = StoreLock::acquire(&dir, LockMode::Shared, short).unwrap();
+        let second = StoreLock::acquire(&dir, LockMode::Shared, short).unwrap();
+        assert!(StoreLock::acquire(&dir, LockMode::Exclusive, short).is_err());
+        drop(first);
+        drop(second);
+
+        let writer = StoreLock::acquire(&dir, LockMode::Exclusive, short).unwrap();
+        let err = StoreLock::acquire(&dir, LockMode::Shared, short)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains(&std::process::id().to_string()));
+        drop(writer);
+        assert!(!dir.join(OWNER_FILE).exists());
+
+        // An owner file without a lock holder is left by a crashed writer
+        write(dir.join(OWNER_FILE), "process 0").unwrap();
+        let writer = StoreLock::acquire(&dir, LockMode::Exclusive, short).unwrap();
+        drop(writer);
+        assert!(!dir.join(OWNER_FILE).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{read_dir, remove_dir, remove_file};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{format_err, Error};
+
+use crate::pack_index::Vidx;
+use crate::utils::compare_versions;
+
+mod files;
+mod lock;
+#[cfg(test)]
+pub(crate) mod test_pack;
+
+pub use files::PackFiles;
+pub use lock::{LockMode, StoreLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Pdsc,
+    Pack,
+    /// A download that never completed
+    Partial,
+}
+
+/// A file in the pack store. Partial downloads have an empty vendor, name
+/// and version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreEntry {
+    pub kind: EntryKind,
+    pub vendor: String,
+    pub name: String,
+    pub version: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// What `prune` removes. The default removes nothing.
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    /// Keep only this many of the newest versions of each pack
+    pub keep: Option<usize>,
+    /// Remove `.part` files left behind by interrupted downloads
+    pub partial: bool,
+    /// Remove packs missing from these indexes. Only vendors that appear in
+    /// the indexes are considered, so an index that failed to download does
+    /// not cause its packs to be removed.
+    pub referenced: Option<Vec<Vidx>>,
+    /// Report what would be removed without removing anything
+    pub dry_run: bool,
+    /// Give up if another process holds the pack store for this long
+    pub lock_timeout: Option<Duration>,
+}
+
+fn classify(pack_store: &Path, path: &Path) -> Option<(EntryKind, String, String, String)> {
+    let rel = path.strip_prefix(pack_store).ok()?;
+    let parts: Vec<&str> = rel.iter().filter_map(|part| part.to_str()).collect();
+    match (path.extension()?.to_str()?, parts.as_slice()) {
+        ("part", _) => Some((
+            EntryKind::Partial,
+            String::new(),
+            String::new(),
+            String::new(),
+        )),
+        // <vendor>.<name>.<version>.pdsc
+        ("pdsc", [file]) => {
+            let stem = file.strip_suffix(".pdsc")?;
+            let mut fields = stem.splitn(3, '.');
+            let vendor = fields.next()?;
+            let name = fields.next()?;
+            let version = fields.next()?;
+            Some((EntryKind::Pdsc, vendor.into(), name.into(), version.into()))
+        }
+        // <vendor>/<name>/<version>.pack
+        ("pack", [vendor, name, file]) => {
+            let version = file.strip_suffix(".pack")?;
+            Some((
+                EntryKind::Pack,
+                vendor.to_string(),
+                name.to_string(),
+                version.into(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn walk(pack_store: &Path, dir: &Path, entries: &mut Vec<StoreEntry>) -> Result<(), Error> {
+    let rd = read_dir(dir).map_err(|e| format_err!("Could not read {:?}: {}", dir, e))?;
+    for dirent in rd {
+        let dirent = dirent?;
+        let path = dirent.path();
+        let meta = dirent.metadata()?;
+        if meta.is_dir() {
+            walk(pack_store, &path, entries)?;
+        } else if let Some((kind, vendor, name, version)) = classify(pack_store, &path) {
+            entries.push(StoreEntry {
+                kind,
+                vendor,
+                name,
+                version,
+                path,
+                size: meta.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// List the pdscs, packs and partial downloads in the pack store, sorted by
+/// pack and then from newest to oldest version.
+pub fn list(pack_store: &Path) -> Result<Vec<StoreEntry>, Error> {
+    if !pack_store.exists() {
+        return Ok(Vec::new());
+    }
+    let _lock = StoreLock::acquire(pack_store, LockMode::Shared, None)?;
+    list_unlocked(pack_store)
+}
+
+fn list_unlocked(pack_store: &Path) -> Result<Vec<StoreEntry>, Error> {
+    let mut entries = Vec::new();
+    walk(pack_store, pack_store, &mut entries)?;
+    entries.sort_by(|a, b| {
+        (&a.vendor, &a.name)
+            .cmp(&(&b.vendor, &b.name))
+            .then_with(|| compare_versions(&b.version, &a.version))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    Ok(entries)
+}
+
+/// Remove entries from the pack store as selected by `options`, returning
+/// the entries removed, or that would be removed in a dry run.
+pub fn prune(pack_store: &Path, options: &PruneOptions) -> Result<Vec<StoreEntry>, Error> {
+    if !pack_store.exists() {
+        return Ok(Vec::new());
+    }
+    let mode = if options.dry_run {
+        LockMode::Shared
+    } else {
+        LockMode::Exclusive
+    };
+    let _lock = StoreLock::acquire(pack_store, mode, options.lock_timeout)?;
+    let entries = list_unlocked(pack_store)?;
+
+    let mut versions: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+    for entry in entries.iter().filter(|e| e.kind != EntryKind::Partial) {
+        let found = versions.entry((&entry.vendor, &entry.name)).or_default();
+        if !found.contains(&entry.version.as_str()) {
+            found.push(&entry.version);
+        }
+    }
+    // Entries are sorted newest first, so anything past `keep` is older
+    let mut outdated: HashSet<(&str, &str, &str)> = HashSet::new();
+    if let Some(keep) = options.keep {
+        for ((vendor, name), versions) in versions.iter() {
+            for version in versions.iter().skip(keep) {
+                outdated.insert((vendor, name, version));
+            }
+        }
+    }
+
+    let referenced = options.referenced.as_ref().map(|vidxs| {
+        let mut vendors: HashSet<&str> = HashSet::new();
+        let mut packs: HashSet<(&str, &str)> = HashSet::new();
+        for pdsc in vidxs.iter().flat_map(|vidx| vidx.pdsc_index.iter()) {
+            vendors.insert(&pdsc.vendor);
+            packs.insert((&pdsc.vendor, &pdsc.name));
+        }
+        (vendors, packs)
+    });
+
+    let mut removed = Vec::new();
+    for entry in entries.iter() {
+        let remove = match entry.kind {
+            EntryKind::Partial => options.partial,
+            EntryKind::Pdsc | EntryKind::Pack => {
+                let key = (entry.vendor.as_str(), entry.name.as_str());
+                outdated.contains(&(key.0, key.1, entry.version.as_str()))
+                    || match &referenced {
+                        Some((vendors, packs)) => vendors.contains(key.0) && !packs.contains(&key),
+                        None => false,
+                    }
+            }
+        };
+        if !remove {
+            continue;
+        }
+        if !options.dry_run {
+            remove_file(&entry.path)
+                .map_err(|e| format_err!("Could not remove {:?}: {}", entry.path, e))?;
+            // Clean up the <vendor>/<name> directories once they are empty
+            let mut dir = entry.path.parent();
+            while let Some(parent) = dir {
+                if parent == pack_store || remove_dir(parent).is_err() {
+                    break;
+                }
+                dir = parent.parent();
+            }
+        }
+        removed.push(entry.clone());
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pack_index::PdscRef;
+    use std::fs::{create_dir_all, write};
+
+    fn make_store(name: &str) -> PathBuf {
+        let store =
+            std::env::temp_dir().join(format!("cmsis-store-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&store);
+        for file in [
+            "V.N.1.0.0.pdsc",
+            "V.N.1.9.0.pdsc",
+            "V.N.1.10.0.pdsc",
+            "V/N/1.0.0.pack",
+            "V/N/1.10.0.pack",
+            "V/N/1.11.0.part",
+            "V.Gone.2.0.0.pdsc",
+            "Other.P.1.0.0.pdsc",
+            "vendors.list",
+        ] {
+            let path = store.join(file);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, file).unwrap();
+        }
+        store
+    }
+
+    fn names(entries: &[StoreEntry], store: &Path) -> Vec<String> {
+        entries
+            .iter()
+            .map(|e| {
+                e.path
+                    .strip_prefix(store)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn list_store() {
+        let store = make_store("list");
+        let entries = list(&store).unwrap();
+        assert_eq!(
+            names(&entries, &store),
+            vec![
+                "V/N/1.11.0.part",
+                "Other.P.1.0.0.pdsc",
+                "V.Gone.2.0.0.pdsc",
+                "V/N/1.10.0.pack",
+                "V.N.1.10.0.pdsc",
+                "V.N.1.9.0.pdsc",
+                "V/N/1.0.0.pack",
+                "V.N.1.0.0.pdsc",
+            ]
+        );
+        assert_eq!(entries[3].kind, EntryKind::Pack);
+        assert_eq!(entries[3].size, "V/N/1.10.0.pack".len() as u64);
+        std::fs::remove_dir_all(store).unwrap();
+    }
+
+    #[test]
+    fn prune_store() {
+        let store = make_store("prune");
+        let index = Vidx {
+            vendor: String::from("V"),
+            url: String::from("./"),
+            timestamp: None,
+            pdsc_index: vec![PdscRef {
+                url: String::from("./"),
+                vendor: String::from("V"),
+                name: String::from("N"),
+                version: String::from("1.10.0"),
+                date: None,
+                deprecated: None,
+                replacement: None,
+                size: None,
+            }],
+            vendor_index: Vec::new(),
+        };
+        let options = PruneOptions {
+            keep: Some(1),
+            partial: true,
+            referenced: Some(vec![index]),
+            dry_run: true,
+            lock_timeout: None,
+        };
+        let expected = vec![
+            "V/N/1.11.0.part",
+            "V.Gone.2.0.0.pdsc",
+            "V.N.1.9.0.pdsc",
+            "V/N/1.0.0.pack",
+            "V.N.1.0.0.pdsc",
+        ];
+        let removed = prune(&store, &options).unwrap();
+        assert_eq!(names(&removed, &store), expected);
+        assert_eq!(list(&store).unwrap().len(), 8);
+
+        let options = PruneOptions {
+            dry_run: false,
+            ..options
+        };
+        let removed = prune(&store, &options).unwrap();
+        assert_eq!(names(&removed, &store), expected);
+        assert_eq!(
+            names(&list(&store).unwrap(), &store),
+            vec!["Other.P.1.0.0.pdsc", "V/N/1.10.0.pack", "V.N.1.10.0.pdsc"]
+        );
+        assert!(store.join("vendors.list").exists());
+        std::fs::remove_dir_all(store).unwrap();
+    }
+}
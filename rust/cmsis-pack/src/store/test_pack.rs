@@ -0,0 +1,67 @@
+//! An unpacked pack for tests of code reading files from packs.
+
+use std::fs::{copy, create_dir_all, remove_dir_all, write};
+use std::path::PathBuf;
+
+use crate::pdsc::Package;
+use crate::store::PackFiles;
+use crate::utils::FromElem;
+
+/// The flash algorithm built for tests, erasing 0x400 byte pages
+pub(crate) const FLASH_ALGO: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../tests/flash-algo/Test.FLM"
+);
+
+/// A pack unpacked into a temporary directory, which is removed when
+/// dropped, also when a test fails.
+pub(crate) struct TestPack {
+    pub dir: PathBuf,
+}
+
+impl TestPack {
+    /// An empty pack, in a directory named after `name` and this process.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("cmsis-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    /// Add `contents` as the file at `path`.
+    pub fn write(&self, path: &str, contents: &str) -> &Self {
+        let path = self.dir.join(path);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, contents).unwrap();
+        self
+    }
+
+    /// Add the test flash algorithm as the file at `path`.
+    pub fn flash_algo(&self, path: &str) -> &Self {
+        let path = self.dir.join(path);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        copy(FLASH_ALGO, path).unwrap();
+        self
+    }
+
+    pub fn files(&self) -> PackFiles {
+        PackFiles::open(&self.dir).unwrap()
+    }
+}
+
+impl Drop for TestPack {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.dir);
+    }
+}
+
+/// Parse a package of vendor `V`, named `P`, holding the devices `family`.
+pub(crate) fn package(family: &str) -> Package {
+    Package::from_string(&format!(
+        r#"<package><vendor>V</vendor><name>P</name><description/><url>./</url>
+           <releases><release version="1.2.0">r</release></releases>
+           <devices>{}</devices></package>"#,
+        family
+    ))
+    .unwrap()
+}
@@ -0,0 +1,192 @@
+//! System View Description files, which describe the registers of a device.
+//!
+//! Packs only name the SVD of each processor, as a path in the pack. This
+//! finds that file for a device and reads the list of its peripherals.
+
+use anyhow::{format_err, Error};
+use roxmltree::Node;
+use serde::Serialize;
+
+use crate::pdsc::Device;
+use crate::store::PackFiles;
+use crate::utils::prelude::*;
+
+impl Device {
+    /// The path in its pack of the SVD for `processor`. It may only be left
+    /// out when all processors of the device share one SVD.
+    pub fn svd_path(&self, processor: Option<&str>) -> Result<&str, Error> {
+        if let Some(name) = processor {
+            let found = self
+                .processors
+                .iter()
+                .find(|proc| proc.name.as_deref() == Some(name))
+                .ok_or_else(|| {
+                    format_err!(
+                        "{} has no processor {}; choose one of {}",
+                        self.name,
+                        name,
+                        self.processor_names().join(", ")
+                    )
+                })?;
+            return found
+                .svd
+                .as_deref()
+                .ok_or_else(|| format_err!("No SVD for processor {} of {}", name, self.name));
+        }
+        let mut paths: Vec<&str> = self
+            .processors
+            .iter()
+            .filter_map(|proc| proc.svd.as_deref())
+            .collect();
+        paths.dedup();
+        match paths.as_slice() {
+            [] => Err(format_err!("No SVD for {}", self.name)),
+            [path] => Ok(path),
+            _ => Err(format_err!(
+                "The processors of {} have different SVDs; choose one of {}",
+                self.name,
+                self.processor_names().join(", ")
+            )),
+        }
+    }
+
+    fn processor_names(&self) -> Vec<&str> {
+        self.processors
+            .iter()
+            .filter_map(|proc| proc.name.as_deref())
+            .collect()
+    }
+}
+
+/// An SVD file, read from a pack.
+#[derive(Debug, Clone)]
+pub struct Svd {
+    /// The path of the file in its pack
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+/// A peripheral, as listed at the top level of an SVD.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Peripheral {
+    pub name: String,
+    pub base_address: u64,
+    pub description: Option<String>,
+    pub group_name: Option<String>,
+    /// A peripheral this one copies its registers from
+    pub derived_from: Option<String>,
+}
+
+/// SVD numbers may be written in decimal, or in hexadecimal with `0x`.
+fn parse_number(text: &str) -> Result<u64, Error> {
+    let text = text.trim();
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|e| format_err!("Invalid number {:?}: {}", text, e))
+}
+
+fn optional_text(e: &Node, name: &str) -> Option<String> {
+    child_text(e, name)
+        .ok()
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+impl FromElem for Peripheral {
+    fn from_elem(e: &Node) -> Result<Self, Error> {
+        assert_root_name(e, "peripheral")?;
+        let name = child_text(e, "name")?;
+        let base_address = child_text(e, "baseAddress")
+            .and_then(|text| parse_number(&text))
+            .map_err(|err| format_err!("Peripheral {}: {}", name, err))?;
+        Ok(Self {
+            name: name.trim().to_string(),
+            base_address,
+            description: optional_text(e, "description"),
+            group_name: optional_text(e, "groupName"),
+            derived_from: e.attribute("derivedFrom").map(String::from),
+        })
+    }
+}
+
+impl Svd {
+    /// Read the SVD for `processor` of `device` from its pack.
+    pub fn for_device(
+        device: &Device,
+        processor: Option<&str>,
+        pack: &mut PackFiles,
+    ) -> Result<Self, Error> {
+        let path = device.svd_path(processor)?;
+        Ok(Self {
+            path: path.to_string(),
+            contents: pack.read(path)?,
+        })
+    }
+
+    /// The peripherals of the device, in the order of the file.
+    pub fn peripherals(&self) -> Result<Vec<Peripheral>, Error> {
+        let text = std::str::from_utf8(&self.contents)
+            .map_err(|e| format_err!("{} is not UTF-8: {}", self.path, e))?;
+        let doc = roxmltree::Document::parse(text)
+            .map_err(|e| format_err!("Could not parse {}: {}", self.path, e))?;
+        let root = doc.root_element();
+        assert_root_name(&root, "device")?;
+        let peripherals = root
+            .children()
+            .find(|child| child.has_tag_name("peripherals"))
+            .ok_or_else(|| format_err!("No peripherals in {}", self.path))?;
+        peripherals
+            .children()
+            .filter(|child| child.has_tag_name("peripheral"))
+            .map(|child| Peripheral::from_elem(&child))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::test_pack::{package, TestPack};
+
+    #[test]
+    fn read_device_svd() {
+        let test_pack = TestPack::new("svd");
+        test_pack.write(
+            "SVD/Test.svd",
+            r#"<device schemaVersion="1.3"><name>TEST</name>
+               <peripherals>
+                 <peripheral><name>GPIOA</name><groupName>GPIO</groupName>
+                   <description>General purpose
+                     I/O</description>
+                   <baseAddress>0x40020000</baseAddress>
+                   <registers/>
+                 </peripheral>
+                 <peripheral derivedFrom="GPIOA"><name>GPIOB</name>
+                   <baseAddress>1073873920</baseAddress>
+                 </peripheral>
+               </peripherals></device>"#,
+        );
+        let package = package(
+            r#"<family Dfamily="Test Series">
+                 <processor Dcore="Cortex-M4"/>
+                 <device Dname="TEST1"><debug svd="SVD\Test.svd"/></device>
+                 <device Dname="TEST2"/>
+               </family>"#,
+        );
+        let mut pack = test_pack.files();
+        let device = &package.devices.0["TEST1"];
+        let svd = Svd::for_device(device, None, &mut pack).unwrap();
+        let peripherals = svd.peripherals().unwrap();
+        assert_eq!(peripherals.len(), 2);
+        assert_eq!(
+            peripherals[0].description.as_deref(),
+            Some("General purpose I/O")
+        );
+        assert_eq!(peripherals[1].base_address, 0x4002_0400);
+        assert_eq!(peripherals[1].derived_from.as_deref(), Some("GPIOA"));
+
+        assert!(Svd::for_device(device, Some("cm0"), &mut pack).is_err());
+        assert!(Svd::for_device(&package.devices.0["TEST2"], None, &mut pack).is_err());
+    }
+}
@@ -1,37 +1,138 @@
-use std::fs::{create_dir_all, rename, OpenOptions};
+use std::fs::{create_dir_all, read, remove_file, rename, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Error};
-use futures::prelude::*;
 use futures::stream::futures_unordered::FuturesUnordered;
-use reqwest::{redirect, Url};
+use reqwest::{redirect, Certificate, Identity, Proxy, Url};
 use reqwest::{Client, ClientBuilder, Response};
-use tokio::task::JoinHandle;
-use tokio::time::{sleep, Duration};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+use tokio::time::Duration;
 
-use crate::pack_index::{PdscRef, Vidx};
+use crate::pack_index::{PdscRef, Pidx, Vidx};
 use crate::pdsc::Package;
 use crate::utils::parse::FromElem;
 use futures::StreamExt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 const CONCURRENCY: usize = 32;
 const HOST_LIMIT: usize = 6;
 const MAX_RETRIES: usize = 3;
 const CONNECT_TIMEOUT: u64 = 15;
 const READ_TIMEOUT: u64 = 15;
+const MAX_REDIRECTS: usize = 5;
 
-fn pdsc_url(pdsc: &mut PdscRef) -> String {
-    if pdsc.url.ends_with('/') {
-        format!("{}{}.{}.pdsc", pdsc.url, pdsc.vendor, pdsc.name)
-    } else {
-        format!("{}/{}.{}.pdsc", pdsc.url, pdsc.vendor, pdsc.name)
+/// Settings used when downloading indexes, descriptions and packs.
+///
+/// Only `pack_store` is required. Every other setting returns `None` by
+/// default, in which case the built-in default is used.
+pub trait DownloadConfig {
+    fn pack_store(&self) -> PathBuf;
+
+    /// Maximum number of downloads in flight at once.
+    fn concurrency(&self) -> Option<usize> {
+        None
+    }
+
+    /// Maximum number of downloads in flight to a single host.
+    fn host_limit(&self) -> Option<usize> {
+        None
+    }
+
+    /// Disable the connect and read timeouts entirely.
+    fn no_timeouts(&self) -> bool {
+        false
+    }
+
+    fn connect_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Maximum number of redirects followed for a single request.
+    fn max_redirects(&self) -> Option<usize> {
+        None
+    }
+
+    fn user_agent(&self) -> Option<String> {
+        None
+    }
+
+    /// Proxy URL used for all requests, e.g. `http://proxy.example.com:3128`.
+    fn proxy(&self) -> Option<String> {
+        None
+    }
+
+    /// PEM file with additional root certificates to trust.
+    fn ca_bundle(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// PEM file holding a client certificate chain and its private key.
+    fn client_identity(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Work offline, resolving every remote URL from this mirror directory.
+    ///
+    /// A URL is mapped to the file in the mirror named after its last path
+    /// segment, so `http://www.keil.com/pack/index.pidx` is read from
+    /// `<mirror>/index.pidx` and packs from `<mirror>/Vendor.Name.1.0.0.pack`.
+    fn offline_mirror(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// How long to wait for another process to release the pack store.
+    /// `None`, the default, waits for as long as it takes.
+    fn lock_timeout(&self) -> Option<Duration> {
+        None
     }
 }
 
-pub trait DownloadConfig {
-    fn pack_store(&self) -> PathBuf;
+fn build_client<D: DownloadConfig>(config: &D) -> Result<Client, Error> {
+    let mut builder = ClientBuilder::new().redirect(redirect::Policy::limited(
+        config.max_redirects().unwrap_or(MAX_REDIRECTS),
+    ));
+    if !config.no_timeouts() {
+        builder = builder
+            .connect_timeout(
+                config
+                    .connect_timeout()
+                    .unwrap_or(Duration::from_secs(CONNECT_TIMEOUT)),
+            )
+            .read_timeout(
+                config
+                    .read_timeout()
+                    .unwrap_or(Duration::from_secs(READ_TIMEOUT)),
+            );
+    }
+    if let Some(user_agent) = config.user_agent() {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(proxy) = config.proxy() {
+        let proxy = Proxy::all(&proxy).map_err(|e| anyhow!("Invalid proxy {:?}: {}", proxy, e))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(ca_bundle) = config.ca_bundle() {
+        let pem = read(&ca_bundle).map_err(|e| anyhow!("Could not read {:?}: {}", ca_bundle, e))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| anyhow!("Invalid CA bundle {:?}: {}", ca_bundle, e))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(identity) = config.client_identity() {
+        let pem = read(&identity).map_err(|e| anyhow!("Could not read {:?}: {}", identity, e))?;
+        let identity = Identity::from_pem(&pem)
+            .map_err(|e| anyhow!("Invalid client identity {:?}: {}", identity, e))?;
+        builder = builder.identity(identity);
+    }
+    Ok(builder.build()?)
 }
 
 #[allow(clippy::wrong_self_convention)]
@@ -103,42 +204,86 @@ impl IntoDownload for &Package {
     }
 }
 
+/// A `.part` file that is removed when dropped, unless it was renamed into
+/// place. This cleans up after failed and cancelled downloads alike.
+struct PartFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Drop for PartFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = remove_file(&self.path);
+        }
+    }
+}
+
 async fn save_response(response: Response, dest: PathBuf) -> Result<(usize, PathBuf), Error> {
-    let temp = dest.with_extension("part");
-    let file = OpenOptions::new()
+    let mut temp = PartFile {
+        path: dest.with_extension("part"),
+        keep: false,
+    };
+    let mut file = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(&temp);
-
-    let mut file = match file {
-        Err(err) => return Err(anyhow!(err.to_string())),
-        Ok(f) => f,
-    };
+        .open(&temp.path)
+        .map_err(|err| anyhow!(err.to_string()))?;
 
     let mut fsize: usize = 0;
     let mut stream = response.bytes_stream();
     while let Some(chunk) = stream.next().await {
-        match chunk {
-            Ok(bytes) => {
-                fsize += bytes.len();
+        let bytes = chunk.map_err(|err| anyhow!(err.to_string()))?;
+        fsize += bytes.len();
+        file.write_all(bytes.as_ref())
+            .map_err(|err| anyhow!(err.to_string()))?;
+    }
+    drop(file);
+    rename(&temp.path, &dest).map_err(|err| anyhow!(err.to_string()))?;
+    temp.keep = true;
+    Ok((fsize, dest))
+}
 
-                if let Err(err) = file.write_all(bytes.as_ref()) {
-                    let _ = std::fs::remove_file(temp);
-                    return Err(anyhow!(err.to_string()));
-                }
-            }
-            Err(err) => {
-                let _ = std::fs::remove_file(temp);
-                return Err(anyhow!(err.to_string()));
+/// A handle used to stop a running update or install from another thread.
+///
+/// Cancelling aborts all in-flight requests and removes their partially
+/// downloaded files.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<CancelState>);
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Release);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Completes once `cancel` has been called.
+    pub async fn cancelled(&self) {
+        loop {
+            // Register for wakeups before checking the flag, so a concurrent
+            // call to `cancel` cannot be missed.
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
             }
+            notified.await;
         }
     }
-    if let Err(err) = rename(&temp, &dest) {
-        let _ = std::fs::remove_file(temp);
-        return Err(anyhow!(err.to_string()));
-    }
-    Ok((fsize, dest))
 }
 
 pub trait DownloadProgress: Send {
@@ -155,6 +300,119 @@ impl DownloadProgress for () {
     fn for_file(&self, _: &str) -> Self {}
 }
 
+/// Limits on the number of requests in flight, both overall and per host.
+///
+/// Permits are handed out in FIFO order, so a host with a long queue cannot
+/// starve the others.
+struct HostLimits {
+    global: Semaphore,
+    host_limit: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    /// Limits of 0 are raised to 1; a semaphore without permits would never
+    /// let a download start.
+    fn new(concurrency: usize, host_limit: usize) -> Self {
+        HostLimits {
+            global: Semaphore::new(concurrency.max(1)),
+            host_limit: host_limit.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait for a slot on `host`, then for a global slot. Waiting on the host
+    /// first keeps requests to a busy host from holding global slots.
+    async fn acquire(&self, host: &str) -> (OwnedSemaphorePermit, SemaphorePermit<'_>) {
+        let host = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.host_limit)))
+            .clone();
+        // The semaphores are never closed, so acquiring cannot fail.
+        let host_permit = host.acquire_owned().await.unwrap();
+        let permit = self.global.acquire().await.unwrap();
+        (host_permit, permit)
+    }
+}
+
+/// Turn an entry of the vendor index list into a URL.
+///
+/// Entries may be URLs or plain file system paths. Paths and `file://` URLs
+/// that name a directory refer to the `index.pidx` inside it. Parsing into a
+/// `Url` also normalises the spelling, so duplicates compare equal.
+fn source_url(src: &str) -> Result<Url, Error> {
+    let url = match Url::parse(src) {
+        // A single letter scheme is a Windows drive letter, not a URL.
+        Ok(url) if url.scheme().len() > 1 => url,
+        _ => {
+            let path = Path::new(src)
+                .canonicalize()
+                .map_err(|e| anyhow!("Could not find {}: {}", src, e))?;
+            Url::from_file_path(&path).map_err(|_| anyhow!("Invalid path {:?}", path))?
+        }
+    };
+    if url.scheme() == "file" {
+        if let Ok(path) = url.to_file_path() {
+            if path.is_dir() {
+                return Url::from_file_path(path.join("index.pidx"))
+                    .map_err(|_| anyhow!("Invalid path {:?}", path));
+            }
+        }
+    }
+    Ok(url)
+}
+
+/// Resolve a possibly relative URL found in the index at `base`.
+fn resolve_url(base: &Url, url: &str) -> String {
+    if Url::parse(url).is_ok() {
+        return url.to_string();
+    }
+    match base.join(url) {
+        Ok(u) => u.to_string(),
+        Err(_) => url.to_string(),
+    }
+}
+
+fn read_file(url: &Url) -> Result<(PathBuf, Vec<u8>), Error> {
+    let path = url
+        .to_file_path()
+        .map_err(|_| anyhow!("Invalid file URL {}", url))?;
+    let content = read(&path).map_err(|e| anyhow!("Could not read {:?}: {}", path, e))?;
+    Ok((path, content))
+}
+
+fn save_file(source: &Url, dest: PathBuf) -> Result<(usize, PathBuf), Error> {
+    let (_, content) = read_file(source)?;
+    let mut temp = PartFile {
+        path: dest.with_extension("part"),
+        keep: false,
+    };
+    std::fs::write(&temp.path, &content).map_err(|err| anyhow!(err.to_string()))?;
+    rename(&temp.path, &dest).map_err(|err| anyhow!(err.to_string()))?;
+    temp.keep = true;
+    Ok((content.len(), dest))
+}
+
+fn pidx_url(pidx: &Pidx) -> String {
+    if pidx.url.ends_with('/') {
+        format!("{}{}.pidx", pidx.url, pidx.vendor)
+    } else {
+        format!("{}/{}.pidx", pidx.url, pidx.vendor)
+    }
+}
+
+/// A vendor index waiting to be fetched, along with the chain of indexes
+/// that led to it.
+struct VidxFetch {
+    order: usize,
+    url: Url,
+    chain: Arc<Vec<Url>>,
+    tries: usize,
+}
+
 pub struct DownloadContext<'a, Conf, Prog>
 where
     Conf: DownloadConfig,
@@ -163,6 +421,8 @@ where
     config: &'a Conf,
     prog: Prog,
     client: Client,
+    limits: HostLimits,
+    cancel: CancellationToken,
 }
 
 impl<'a, Conf, Prog> DownloadContext<'a, Conf, Prog>
@@ -171,218 +431,280 @@ where
     Prog: DownloadProgress + 'a,
 {
     pub fn new(config: &'a Conf, prog: Prog) -> Result<Self, Error> {
-        let client = ClientBuilder::new()
-            .redirect(redirect::Policy::limited(5))
-            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT))
-            .read_timeout(Duration::from_secs(READ_TIMEOUT))
-            .build()?;
+        let client = build_client(config)?;
+        let limits = HostLimits::new(
+            config.concurrency().unwrap_or(CONCURRENCY),
+            config.host_limit().unwrap_or(HOST_LIMIT),
+        );
 
         Ok(DownloadContext {
             config,
             prog,
             client,
+            limits,
+            cancel: CancellationToken::new(),
         })
     }
 
+    /// Stop downloading as soon as `cancel` is cancelled.
+    pub fn with_cancel(self, cancel: CancellationToken) -> Self {
+        Self { cancel, ..self }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Map a URL to where it is actually fetched from, which differs from
+    /// the URL itself only in offline mode.
+    fn locate(&self, url: &Url) -> Result<Url, Error> {
+        let mirror = match self.config.offline_mirror() {
+            Some(mirror) if url.scheme() != "file" => mirror,
+            _ => return Ok(url.clone()),
+        };
+        let name = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| anyhow!("Cannot find {} in the offline mirror", url))?;
+        let mirror = if mirror.is_relative() {
+            std::env::current_dir()?.join(mirror)
+        } else {
+            mirror
+        };
+        Url::from_file_path(mirror.join(name))
+            .map_err(|_| anyhow!("Invalid offline mirror {:?}", mirror))
+    }
+
+    async fn download_file(&self, source: Url, dest: PathBuf) -> Option<(usize, PathBuf)> {
+        dest.parent().map(create_dir_all);
+        let res = match self.locate(&source) {
+            Ok(from) if from.scheme() == "file" => save_file(&from, dest),
+            Ok(from) => match self.client.get(from).send().await {
+                Ok(r) => {
+                    let rc = r.status().as_u16();
+                    if rc >= 400 {
+                        Err(anyhow!("Response code in invalid range: {}", rc))
+                    } else {
+                        save_response(r, dest).await
+                    }
+                }
+                Err(err) => Err(anyhow!(err.to_string())),
+            },
+            Err(err) => Err(err),
+        };
+        match res {
+            Ok(r) => Some(r),
+            Err(err) => {
+                log::warn!("Download of {} failed: {}", source, err);
+                None
+            }
+        }
+    }
+
+    /// Download every item not yet in the pack store, returning the paths of
+    /// all items that are present afterwards in the order they were given.
     pub async fn download_iterator<I>(&'a self, iter: I) -> Vec<PathBuf>
     where
         I: IntoIterator + 'a,
         <I as IntoIterator>::Item: IntoDownload,
     {
-        let mut to_dl: Vec<(Url, String, PathBuf)> = iter
+        let to_dl: Vec<(Url, PathBuf)> = iter
             .into_iter()
             .filter_map(|i| {
                 if let Ok(uri) = i.into_uri() {
-                    let c = uri.clone();
-                    c.host_str()
-                        .map(|host| (uri, host.to_string(), i.into_fd(self.config)))
+                    Some((uri, i.into_fd(self.config)))
                 } else {
                     None
                 }
             })
             .collect();
+        self.download_list(to_dl, false)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Download each source URL to its destination path, skipping existing
+    /// destinations unless `refresh` is set. The result holds, in the order
+    /// given, the destination of each download that succeeded.
+    pub(crate) async fn download_list(
+        &'a self,
+        to_dl: Vec<(Url, PathBuf)>,
+        refresh: bool,
+    ) -> Vec<Option<PathBuf>> {
         self.prog.size(to_dl.len());
 
-        let mut hosts: HashMap<String, usize> = HashMap::new();
-        let mut results: Vec<PathBuf> = vec![];
-        let mut started: usize = 0;
-        let mut handles: Vec<JoinHandle<(String, usize, Option<PathBuf>)>> = vec![];
-
-        while !to_dl.is_empty() || !handles.is_empty() {
-            let mut wait_list: Vec<(Url, String, PathBuf)> = vec![];
-            let mut next: Vec<JoinHandle<(String, usize, Option<PathBuf>)>> = vec![];
-
-            while let Some(handle) = handles.pop() {
-                if handle.is_finished() {
-                    let r = handle.await.unwrap();
-                    *hosts.entry(r.0).or_insert(1) -= 1;
-                    started -= 1;
-                    self.prog.progress(r.1);
-                    self.prog.complete();
-                    if let Some(path) = r.2 {
-                        results.push(path);
-                    }
-                } else {
-                    next.push(handle);
+        let mut results: Vec<Option<PathBuf>> = vec![None; to_dl.len()];
+        let mut running: FuturesUnordered<_> = to_dl
+            .into_iter()
+            .enumerate()
+            .map(|(index, (source, dest))| async move {
+                if !refresh && dest.exists() {
+                    return (index, 0, Some(dest));
                 }
-            }
+                let host = source.host_str().unwrap_or_default().to_string();
+                let _permits = self.limits.acquire(&host).await;
+                match self.download_file(source, dest).await {
+                    Some((size, path)) => (index, size, Some(path)),
+                    None => (index, 0, None),
+                }
+            })
+            .collect();
 
-            while !to_dl.is_empty() && started < CONCURRENCY {
-                let from = to_dl.pop().unwrap();
-                let host = from.1.clone();
-                let entry = hosts.entry(host).or_insert(0);
-                if *entry >= HOST_LIMIT {
-                    wait_list.push(from);
-                } else {
-                    let source = from.0.clone();
-                    let host = from.1.clone();
-                    let dest = from.2.clone();
-                    if dest.exists() {
-                        self.prog.complete();
-                        results.push(dest);
-                    } else {
-                        let client = self.client.clone();
-                        let handle: JoinHandle<(String, usize, Option<PathBuf>)> =
-                            tokio::spawn(async move {
-                                dest.parent().map(create_dir_all);
-                                let res = client.get(source.clone()).send().await;
-                                let res: Result<(usize, PathBuf), Error> = match res {
-                                    Ok(r) => {
-                                        let rc = r.status().as_u16();
-                                        if rc >= 400 {
-                                            Err(anyhow!(format!(
-                                                "Response code in invalid range: {}",
-                                                rc
-                                            )
-                                            .to_string()))
-                                        } else {
-                                            save_response(r, dest).await
-                                        }
-                                    }
-                                    Err(err) => Err(anyhow!(err.to_string())),
-                                };
-                                match res {
-                                    Ok(r) => (host, r.0, Some(r.1)),
-                                    Err(err) => {
-                                        log::warn!(
-                                            "Download of {} failed: {}",
-                                            source.to_string(),
-                                            err
-                                        );
-                                        (host, 0, None)
-                                    }
-                                }
-                            });
-                        handles.push(handle);
-                        started += 1;
-                        *entry += 1;
-                    }
+        // Dropping `running` on cancellation aborts every in-flight request.
+        loop {
+            let next = tokio::select! {
+                next = running.next() => next,
+                _ = self.cancel.cancelled() => break,
+            };
+            match next {
+                Some((index, size, path)) => {
+                    self.prog.progress(size);
+                    self.prog.complete();
+                    results[index] = path;
                 }
+                None => break,
             }
+        }
 
-            for w in wait_list {
-                to_dl.push(w);
-            }
+        results
+    }
+
+    pub(crate) async fn update_vidx<I>(&'a self, list: I) -> Result<Vec<PathBuf>, Error>
+    where
+        I: IntoIterator + 'a,
+        <I as IntoIterator>::Item: Into<String>,
+    {
+        let vidxs = self.crawl_vidx(list).await;
 
-            for w in next {
-                handles.push(w);
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut pdscs: Vec<PdscRef> = Vec::new();
+        for v in vidxs {
+            for pdsc in v.pdsc_index {
+                let key = match pdsc.into_uri() {
+                    Ok(uri) => uri.to_string(),
+                    Err(_) => format!("{}/{}.{}", pdsc.url, pdsc.vendor, pdsc.name),
+                };
+                if seen.insert(key) {
+                    pdscs.push(pdsc);
+                }
             }
-            sleep(Duration::from_millis(100)).await;
         }
+        log::info!("Found {} Pdsc entries", pdscs.len());
 
-        results
+        Ok(self.download_iterator(pdscs).await)
     }
 
-    pub(crate) async fn update_vidx<I>(&'a self, list: I) -> Result<Vec<PathBuf>, Error>
+    /// Fetch the given vendor indexes and every vendor index they reference,
+    /// concurrently and within the host limits.
+    ///
+    /// Each index is fetched at most once. Indexes are returned in the order
+    /// they were discovered, regardless of the order the downloads finish in.
+    pub(crate) async fn crawl_vidx<I>(&'a self, list: I) -> Vec<Vidx>
     where
         I: IntoIterator + 'a,
         <I as IntoIterator>::Item: Into<String>,
     {
-        let mut downloaded: HashMap<String, bool> = HashMap::new();
-        let mut failures: HashMap<String, usize> = HashMap::new();
-        let mut urls: Vec<String> = list.into_iter().map(|x| x.into()).collect();
-        let mut vidxs: Vec<Vidx> = Vec::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut vidxs: BTreeMap<usize, Vidx> = BTreeMap::new();
+        let mut running = FuturesUnordered::new();
+        let root = Arc::new(Vec::new());
+        for src in list {
+            let src = src.into();
+            let url = match source_url(&src) {
+                Ok(url) => url,
+                Err(err) => {
+                    log::warn!("Skipping vendor index {}: {}", src, err);
+                    continue;
+                }
+            };
+            if seen.insert(url.to_string()) {
+                running.push(self.fetch_vidx(VidxFetch {
+                    order: seen.len(),
+                    url,
+                    chain: root.clone(),
+                    tries: 0,
+                }));
+            }
+        }
+
         loop {
-            // Remove from list all duplicate URLs and those already downloaded
-            urls.dedup();
-            urls.retain(|u| !*downloaded.get(u).unwrap_or(&false));
-
-            // TODO: Make this section asynchronous
-            let mut next: Vec<String> = Vec::new();
-            for url in urls {
-                match self.download_vidx(url.clone()).await {
-                    Ok(t) => {
-                        log::info!("Downloaded {}", url);
-                        downloaded.insert(url, true);
-                        for v in &t.vendor_index {
-                            let u = format!("{}{}.pidx", v.url, v.vendor);
-                            if !downloaded.contains_key(&u) {
-                                downloaded.insert(u.clone(), false);
-                                next.push(u);
+            let next = tokio::select! {
+                next = running.next() => next,
+                _ = self.cancel.cancelled() => break,
+            };
+            let (fetch, res) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            match res {
+                Ok(mut vidx) => {
+                    log::info!("Downloaded {}", fetch.url);
+                    for pdsc in vidx.pdsc_index.iter_mut() {
+                        pdsc.url = resolve_url(&fetch.url, &pdsc.url);
+                    }
+                    let mut chain = (*fetch.chain).clone();
+                    chain.push(fetch.url.clone());
+                    let chain = Arc::new(chain);
+                    for pidx in vidx.vendor_index.iter() {
+                        let url = match Url::parse(&resolve_url(&fetch.url, &pidx_url(pidx))) {
+                            Ok(url) => url,
+                            Err(err) => {
+                                log::warn!("Invalid vendor index URL {}: {}", pidx.url, err);
+                                continue;
                             }
+                        };
+                        if seen.insert(url.to_string()) {
+                            running.push(self.fetch_vidx(VidxFetch {
+                                order: seen.len(),
+                                url,
+                                chain: chain.clone(),
+                                tries: 0,
+                            }));
+                        } else if chain.contains(&url) {
+                            let chain: Vec<&str> = chain.iter().map(Url::as_str).collect();
+                            log::warn!(
+                                "Vendor index cycle detected: {} -> {}",
+                                chain.join(" -> "),
+                                url
+                            );
                         }
-                        vidxs.push(t);
                     }
-                    Err(_err) => {
-                        let tries = failures.entry(url.clone()).or_insert(0);
-                        *tries += 1;
-                        if *tries < MAX_RETRIES {
-                            next.push(url);
-                        }
+                    vidxs.insert(fetch.order, vidx);
+                }
+                Err(err) => {
+                    let tries = fetch.tries + 1;
+                    if tries < MAX_RETRIES {
+                        running.push(self.fetch_vidx(VidxFetch { tries, ..fetch }));
+                    } else {
+                        log::warn!("Download of {} failed: {}", fetch.url, err);
                     }
                 }
             }
-            if next.is_empty() {
-                break;
-            }
-            urls = next;
-        }
-
-        let mut pdscs: Vec<PdscRef> = Vec::new();
-        for mut v in vidxs {
-            pdscs.append(&mut v.pdsc_index);
         }
 
-        pdscs.dedup_by_key(pdsc_url);
-        log::info!("Found {} Pdsc entries", pdscs.len());
+        vidxs.into_values().collect()
+    }
 
-        Ok(self.download_iterator(pdscs.into_iter()).await)
+    async fn fetch_vidx(&self, fetch: VidxFetch) -> (VidxFetch, Result<Vidx, Error>) {
+        let res = self.download_vidx(fetch.url.clone()).await;
+        (fetch, res)
     }
 
-    pub(crate) async fn download_vidx<I: Into<String>>(
-        &'a self,
-        vidx_ref: I,
-    ) -> Result<Vidx, Error> {
-        let vidx = vidx_ref.into();
-        let uri = vidx.parse::<Url>().unwrap();
+    pub(crate) async fn download_vidx(&'a self, url: Url) -> Result<Vidx, Error> {
+        let uri = self.locate(&url)?;
+        if uri.scheme() == "file" {
+            let (path, content) = read_file(&uri)?;
+            let text = String::from_utf8(content)
+                .map_err(|e| anyhow!("Could not read {:?}: {}", path, e))?;
+            return Vidx::from_string(&text);
+        }
+        let host = uri.host_str().unwrap_or_default().to_string();
 
-        let req: reqwest::Response = self.client.get(uri).send().await?;
+        let _permits = self.limits.acquire(&host).await;
+        let req: reqwest::Response = self.client.get(uri).send().await?.error_for_status()?;
         Vidx::from_string(req.text().await?.as_str())
     }
-
-    #[allow(dead_code)]
-    pub(crate) fn download_vidx_list<I>(&'a self, list: I) -> impl Stream<Item = Option<Vidx>> + 'a
-    where
-        I: IntoIterator + 'a,
-        <I as IntoIterator>::Item: Into<String>,
-    {
-        list.into_iter()
-            .map(|vidx_ref| {
-                let vidx = vidx_ref.into();
-                println!("{}", vidx);
-                self.download_vidx(vidx.clone()).then(|r| async move {
-                    match r {
-                        Ok(v) => {
-                            println!("{} success", vidx);
-                            Some(v)
-                        }
-                        Err(e) => {
-                            log::error!("{}", format!("{}", e).replace("uri", &vidx));
-                            None
-                        }
-                    }
-                })
-            })
-            .collect::<FuturesUnordered<_>>()
-    }
 }
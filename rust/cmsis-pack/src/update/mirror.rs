@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error};
+use reqwest::Url;
+
+use crate::pack_index::{PdscRef, Pidx, Vidx};
+use crate::pdsc::Package;
+use crate::update::download::{DownloadConfig, DownloadContext, DownloadProgress, IntoDownload};
+use crate::utils::parse::FromElem;
+use crate::utils::write::escape;
+use crate::utils::ToElem;
+
+/// Selects what goes into a mirror. Empty lists select everything.
+#[derive(Debug, Clone, Default)]
+pub struct MirrorOptions {
+    /// Only mirror packs from these vendors
+    pub vendors: Vec<String>,
+    /// Only mirror these packs, named `Vendor.Name`
+    pub packs: Vec<String>,
+    /// Mirror the descriptors without their packs
+    pub skip_packs: bool,
+    /// Rewrite the `<url>` of each mirrored pdsc to this URL, so that
+    /// installing from the mirror also fetches the packs from it
+    pub pack_url: Option<String>,
+}
+
+impl MirrorOptions {
+    fn accepts(&self, pdsc: &PdscRef) -> bool {
+        let full_name = format!("{}.{}", pdsc.vendor, pdsc.name);
+        (self.vendors.is_empty() || self.vendors.iter().any(|v| v == &pdsc.vendor))
+            && (self.packs.is_empty() || self.packs.iter().any(|p| p == &full_name))
+    }
+}
+
+/// A vendor index in a mirror, referring to its entries relative to itself.
+fn mirror_index(vendor: &str, vendor_index: Vec<Pidx>, pdsc_index: Vec<PdscRef>) -> Vidx {
+    Vidx {
+        vendor: vendor.to_string(),
+        url: String::from("./"),
+        timestamp: None,
+        vendor_index,
+        pdsc_index,
+    }
+}
+
+/// Replace the text of the package level `<url>` element, leaving the rest
+/// of the descriptor untouched.
+fn rewrite_pack_url(text: &str, url: &str) -> Result<String, Error> {
+    let doc = roxmltree::Document::parse(text)?;
+    let node = doc
+        .root_element()
+        .children()
+        .find(|child| child.has_tag_name("url"))
+        .ok_or_else(|| anyhow!("Pdsc has no <url> element"))?;
+    let range = node.range();
+    Ok(format!(
+        "{}<url>{}</url>{}",
+        &text[..range.start],
+        escape(url),
+        &text[range.end..]
+    ))
+}
+
+/// Load a freshly mirrored pdsc, rewriting its pack URL when asked to.
+fn load_pdsc(path: &Path, options: &MirrorOptions) -> Result<Package, Error> {
+    let text = read_to_string(path)?;
+    let package = Package::from_string(&text)?;
+    if let Some(url) = &options.pack_url {
+        write(path, rewrite_pack_url(&text, url)?)?;
+    }
+    Ok(package)
+}
+
+/// Crawl `list` and mirror every selected pdsc, and the latest pack of each,
+/// into `dest`, along with an `index.pidx` and a `<Vendor>.pidx` per vendor
+/// that refer to them by relative URLs.
+pub(crate) async fn mirror_vidx<'a, I, C, P>(
+    ctx: &'a DownloadContext<'a, C, P>,
+    list: I,
+    dest: &Path,
+    options: &MirrorOptions,
+) -> Result<Vec<PathBuf>, Error>
+where
+    I: IntoIterator + 'a,
+    <I as IntoIterator>::Item: Into<String>,
+    C: DownloadConfig,
+    P: DownloadProgress + 'a,
+{
+    create_dir_all(dest).map_err(|e| anyhow!("Could not create {:?}: {}", dest, e))?;
+    let vidxs = ctx.crawl_vidx(list).await;
+
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    let mut pdscs: Vec<(PdscRef, Url)> = Vec::new();
+    for vidx in vidxs {
+        for pdsc in vidx.pdsc_index {
+            let key = (pdsc.vendor.clone(), pdsc.name.clone());
+            if !options.accepts(&pdsc) || !seen.insert(key) {
+                continue;
+            }
+            match pdsc.into_uri() {
+                Ok(uri) => pdscs.push((pdsc, uri)),
+                Err(err) => {
+                    log::warn!("Skipping pdsc {}.{}: {}", pdsc.vendor, pdsc.name, err)
+                }
+            }
+        }
+    }
+    log::info!("Mirroring {} Pdsc entries", pdscs.len());
+
+    // Descriptors keep their name but not their contents between releases,
+    // so they are always fetched again.
+    let to_dl = pdscs
+        .iter()
+        .map(|(pdsc, uri)| {
+            let name = format!("{}.{}.pdsc", pdsc.vendor, pdsc.name);
+            (uri.clone(), dest.join(name))
+        })
+        .collect();
+    let fetched = ctx.download_list(to_dl, true).await;
+
+    let mut mirrored: Vec<PathBuf> = Vec::new();
+    let mut packages: Vec<Package> = Vec::new();
+    let mut by_vendor: BTreeMap<String, Vec<PdscRef>> = BTreeMap::new();
+    for ((pdsc, _), path) in pdscs.into_iter().zip(fetched) {
+        let path = match path {
+            Some(path) => path,
+            None => continue,
+        };
+        match load_pdsc(&path, options) {
+            Ok(package) => packages.push(package),
+            Err(err) => {
+                log::warn!("Could not mirror {:?}: {}", path, err);
+                continue;
+            }
+        }
+        mirrored.push(path);
+        by_vendor.entry(pdsc.vendor.clone()).or_default().push(pdsc);
+    }
+
+    if !options.skip_packs && !ctx.is_cancelled() {
+        // Packs are immutable once released, so existing ones are kept.
+        let to_dl = packages
+            .iter()
+            .filter_map(|package| {
+                let version = &package.releases.latest_release().version;
+                let name = format!("{}.{}.{}.pack", package.vendor, package.name, version);
+                package.into_uri().ok().map(|uri| (uri, dest.join(name)))
+            })
+            .collect();
+        mirrored.extend(ctx.download_list(to_dl, false).await.into_iter().flatten());
+    }
+
+    let mut vendors: Vec<Pidx> = Vec::new();
+    for (vendor, pdscs) in by_vendor.into_iter() {
+        let pdscs = pdscs
+            .into_iter()
+            .map(|pdsc| PdscRef {
+                url: String::from("./"),
+                ..pdsc
+            })
+            .collect();
+        mirror_index(&vendor, Vec::new(), pdscs).to_path(&dest.join(format!("{}.pidx", vendor)))?;
+        vendors.push(Pidx {
+            url: String::from("./"),
+            vendor,
+            date: None,
+        });
+    }
+    mirror_index("Mirror", vendors, Vec::new()).to_path(&dest.join("index.pidx"))?;
+
+    Ok(mirrored)
+}
@@ -1,13 +1,19 @@
-use anyhow::Error;
-use std::path::PathBuf;
+use anyhow::{anyhow, Error};
+use std::path::{Path, PathBuf};
 use tokio::runtime;
 
+use crate::pack_index::Vidx;
 use crate::pdsc::Package;
+use crate::store::{LockMode, StoreLock};
 
 mod download;
+mod mirror;
+#[cfg(test)]
+pub(crate) mod test_server;
 
 use crate::update::download::DownloadContext;
-pub use crate::update::download::{DownloadConfig, DownloadProgress};
+pub use crate::update::download::{CancellationToken, DownloadConfig, DownloadProgress};
+pub use crate::update::mirror::MirrorOptions;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -18,12 +24,39 @@ where
     P: DownloadProgress,
     D: DownloadConfig,
 {
+    update_with_cancel(config, vidx_list, progress, CancellationToken::new())
+}
+
+/// Like `update`, but stops early with an error once `cancel` is cancelled
+pub fn update_with_cancel<I, P, D>(
+    config: &D,
+    vidx_list: I,
+    progress: P,
+    cancel: CancellationToken,
+) -> Result<Vec<PathBuf>>
+where
+    I: IntoIterator<Item = String>,
+    P: DownloadProgress,
+    D: DownloadConfig,
+{
+    if cancel.is_cancelled() {
+        return Err(anyhow!("Update cancelled"));
+    }
+    let _lock = StoreLock::acquire(
+        &config.pack_store(),
+        LockMode::Exclusive,
+        config.lock_timeout(),
+    )?;
     let rt = runtime::Builder::new_current_thread()
         .enable_all()
         .build()?;
 
-    let dl_cntx = DownloadContext::new(config, progress)?;
-    rt.block_on(dl_cntx.update_vidx(vidx_list))
+    let dl_cntx = DownloadContext::new(config, progress)?.with_cancel(cancel);
+    let res = rt.block_on(dl_cntx.update_vidx(vidx_list))?;
+    if dl_cntx.is_cancelled() {
+        return Err(anyhow!("Update cancelled"));
+    }
+    Ok(res)
 }
 
 /// Flatten a list of Vidx Urls into a list of updated CMSIS packs
@@ -32,11 +65,325 @@ where
     I: IntoIterator<Item = &'a Package>,
     P: DownloadProgress + 'a,
     D: DownloadConfig,
+{
+    install_with_cancel(config, pdsc_list, progress, CancellationToken::new())
+}
+
+/// Like `install`, but stops early with an error once `cancel` is cancelled
+pub fn install_with_cancel<'a, I, P, D>(
+    config: &'a D,
+    pdsc_list: I,
+    progress: P,
+    cancel: CancellationToken,
+) -> Result<Vec<PathBuf>>
+where
+    I: IntoIterator<Item = &'a Package>,
+    P: DownloadProgress + 'a,
+    D: DownloadConfig,
+{
+    if cancel.is_cancelled() {
+        return Err(anyhow!("Install cancelled"));
+    }
+    let _lock = StoreLock::acquire(
+        &config.pack_store(),
+        LockMode::Exclusive,
+        config.lock_timeout(),
+    )?;
+    let rt = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let dl_cntx = DownloadContext::new(config, progress)?.with_cancel(cancel);
+    let res = rt.block_on(dl_cntx.download_iterator(pdsc_list));
+    if dl_cntx.is_cancelled() {
+        return Err(anyhow!("Install cancelled"));
+    }
+    Ok(res)
+}
+
+/// Fetch the given Vidx Urls and every vendor index they refer to
+pub fn crawl<I, D>(config: &D, vidx_list: I) -> Result<Vec<Vidx>>
+where
+    I: IntoIterator<Item = String>,
+    D: DownloadConfig,
+{
+    let rt = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let dl_cntx = DownloadContext::new(config, ())?;
+    Ok(rt.block_on(dl_cntx.crawl_vidx(vidx_list)))
+}
+
+/// Write a self-contained mirror of the packs selected by `options` from
+/// the given Vidx Urls into `dest`
+pub fn mirror<I, P, D>(
+    config: &D,
+    vidx_list: I,
+    dest: &Path,
+    options: &MirrorOptions,
+    progress: P,
+) -> Result<Vec<PathBuf>>
+where
+    I: IntoIterator<Item = String>,
+    P: DownloadProgress,
+    D: DownloadConfig,
 {
     let rt = runtime::Builder::new_current_thread()
         .enable_all()
         .build()?;
 
+    let _lock = StoreLock::acquire(dest, LockMode::Exclusive, config.lock_timeout())?;
     let dl_cntx = DownloadContext::new(config, progress)?;
-    rt.block_on(async { Ok(dl_cntx.download_iterator(pdsc_list).await) })
+    rt.block_on(mirror::mirror_vidx(&dl_cntx, vidx_list, dest, options))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::parse::FromElem;
+    use reqwest::Url;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::Duration;
+    use test_server::{Reply, Requests, Server};
+
+    const MIRROR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/test-pack-index");
+
+    struct TestConfig(PathBuf, Option<PathBuf>);
+
+    impl DownloadConfig for TestConfig {
+        fn pack_store(&self) -> PathBuf {
+            self.0.clone()
+        }
+
+        fn offline_mirror(&self) -> Option<PathBuf> {
+            self.1.clone()
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cmsis-pack-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Serve `files`, keyed by path, on a loopback port and record the path
+    /// of every request. The response for `stall` starts, then never
+    /// finishes. Returns the base URL of the server.
+    fn start_server(
+        files: Vec<(&'static str, String)>,
+        stall: Option<&'static str>,
+    ) -> (String, Requests) {
+        let files: HashMap<_, _> = files.into_iter().collect();
+        let server = Server::start(&[], Duration::ZERO, move |path| match files.get(path) {
+            _ if Some(path) == stall => Reply::Stall,
+            Some(body) => Reply::Body(body.clone()),
+            None => Reply::NotFound,
+        });
+        (
+            format!("http://127.0.0.1:{}/", server.port),
+            server.requests,
+        )
+    }
+
+    #[test]
+    fn crawl_skips_duplicates_and_cycles() {
+        // The index lists its pack twice, and refers to itself
+        let index = r#"<index schemaVersion="1.0.0">
+  <vendor>MyVendor</vendor>
+  <url>./</url>
+  <timestamp>2017-01-25T15:00:10.7300074+00:00</timestamp>
+  <vindex>
+    <pidx url="./" vendor="index"/>
+  </vindex>
+  <pindex>
+    <pdsc url="./" vendor="MyVendor" name="MyPack" version="1.1.0"/>
+    <pdsc url="./" vendor="MyVendor" name="MyPack" version="1.1.0"/>
+  </pindex>
+</index>"#;
+        let pdsc = fs::read_to_string(Path::new(MIRROR).join("MyVendor.MyPack.pdsc")).unwrap();
+        let (base, requests) = start_server(
+            vec![
+                ("/index.pidx", index.to_string()),
+                ("/MyVendor.MyPack.pdsc", pdsc),
+            ],
+            None,
+        );
+        let store = temp_dir("crawl");
+        let config = TestConfig(store.clone(), None);
+        let vidx_list = vec![format!("{}index.pidx", base)];
+
+        assert_eq!(crawl(&config, vidx_list.clone()).unwrap().len(), 1);
+        let pdscs = update(&config, vidx_list, ()).unwrap();
+        assert_eq!(pdscs, vec![store.join("MyVendor.MyPack.1.1.0.pdsc")]);
+        let requests = requests.lock().unwrap();
+        let count = |path: &str| requests.iter().filter(|req| *req == path).count();
+        // Once for the crawl, once for the update
+        assert_eq!(count("/index.pidx"), 2);
+        assert_eq!(count("/MyVendor.MyPack.pdsc"), 1);
+        fs::remove_dir_all(store).unwrap();
+    }
+
+    #[test]
+    fn cancelled_update_fails() {
+        let store = temp_dir("cancel");
+        let config = TestConfig(store.clone(), None);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let vidx_list = vec![String::from("http://127.0.0.1:9/index.pidx")];
+        assert!(update_with_cancel(&config, vidx_list, (), cancel).is_err());
+        assert!(!store.exists());
+    }
+
+    #[test]
+    fn cancel_aborts_running_downloads() {
+        let (base, _) = start_server(Vec::new(), Some("/V.P.1.0.0.pack"));
+        let package = Package::from_string(&format!(
+            r#"<package><vendor>V</vendor><name>P</name><description/><url>{}</url>
+               <releases><release version="1.0.0">r</release></releases></package>"#,
+            base
+        ))
+        .unwrap();
+        let store = temp_dir("cancel-running");
+        let config = TestConfig(store.clone(), None);
+        let part = store.join("V/P/1.0.0.part");
+        let cancel = CancellationToken::new();
+        let canceller = {
+            let cancel = cancel.clone();
+            let part = part.clone();
+            std::thread::spawn(move || {
+                let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+                while !part.exists() && std::time::Instant::now() < deadline {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                let started = part.exists();
+                cancel.cancel();
+                started
+            })
+        };
+        assert!(install_with_cancel(&config, Some(&package), (), cancel).is_err());
+        assert!(canceller.join().unwrap(), "the download never started");
+        assert!(!part.exists());
+        assert!(!store.join("V/P/1.0.0.pack").exists());
+        fs::remove_dir_all(store).unwrap();
+    }
+
+    #[test]
+    fn offline_update_and_install() {
+        let store = temp_dir("offline");
+        let config = TestConfig(store.clone(), Some(PathBuf::from(MIRROR)));
+        let vidx_list = vec![String::from(
+            "http://localhost:8001/tests/test-pack-index/index.pidx",
+        )];
+        let pdscs = update(&config, vidx_list, ()).unwrap();
+        assert_eq!(pdscs, vec![store.join("MyVendor.MyPack.1.1.0.pdsc")]);
+
+        let package = Package::from_path(&pdscs[0]).unwrap();
+        let packs = install(&config, Some(&package), ()).unwrap();
+        assert_eq!(packs, vec![store.join("MyVendor/MyPack/1.1.0.pack")]);
+        assert_eq!(
+            fs::read(&packs[0]).unwrap(),
+            fs::read(Path::new(MIRROR).join("MyVendor.MyPack.1.1.0.pack")).unwrap()
+        );
+        fs::remove_dir_all(store).unwrap();
+    }
+
+    #[test]
+    fn update_from_directory() {
+        let mirror = temp_dir("directory-mirror");
+        fs::create_dir_all(&mirror).unwrap();
+        fs::copy(
+            Path::new(MIRROR).join("MyVendor.MyPack.pdsc"),
+            mirror.join("MyVendor.MyPack.pdsc"),
+        )
+        .unwrap();
+        // The vendor index refers back to the top level index, forming a cycle
+        fs::write(
+            mirror.join("index.pidx"),
+            r#"<index schemaVersion="1.0.0">
+  <vendor>MyVendor</vendor>
+  <url>./</url>
+  <timestamp>2017-01-25T15:00:10.7300074+00:00</timestamp>
+  <vindex>
+    <pidx url="./" vendor="MyVendor"/>
+  </vindex>
+</index>"#,
+        )
+        .unwrap();
+        fs::write(
+            mirror.join("MyVendor.pidx"),
+            r#"<index schemaVersion="1.0.0">
+  <vendor>MyVendor</vendor>
+  <url>./</url>
+  <timestamp>2017-01-25T15:00:10.7300074+00:00</timestamp>
+  <vindex>
+    <pidx url="./" vendor="index"/>
+  </vindex>
+  <pindex>
+    <pdsc url="./" vendor="MyVendor" name="MyPack" version="1.1.0"/>
+  </pindex>
+</index>"#,
+        )
+        .unwrap();
+
+        let store = temp_dir("directory-store");
+        let config = TestConfig(store.clone(), None);
+        let vidx_list = vec![mirror.to_string_lossy().into_owned()];
+        let pdscs = update(&config, vidx_list, ()).unwrap();
+        assert_eq!(pdscs, vec![store.join("MyVendor.MyPack.1.1.0.pdsc")]);
+        fs::remove_dir_all(store).unwrap();
+        fs::remove_dir_all(mirror).unwrap();
+    }
+
+    #[test]
+    fn mirror_round_trip() {
+        let dest = temp_dir("mirror");
+        let config = TestConfig(temp_dir("mirror-unused"), Some(PathBuf::from(MIRROR)));
+        let options = MirrorOptions {
+            vendors: vec![String::from("MyVendor")],
+            pack_url: Some(Url::from_directory_path(&dest).unwrap().to_string()),
+            ..MirrorOptions::default()
+        };
+        let vidx_list = vec![String::from(
+            "http://localhost:8001/tests/test-pack-index/index.pidx",
+        )];
+        let mut files = mirror(&config, vidx_list, &dest, &options, ()).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                dest.join("MyVendor.MyPack.1.1.0.pack"),
+                dest.join("MyVendor.MyPack.pdsc"),
+            ]
+        );
+        assert!(dest.join("index.pidx").exists());
+        assert!(dest.join("MyVendor.pidx").exists());
+
+        // The mirror serves both descriptors and packs without any network
+        let store = temp_dir("mirror-store");
+        let config = TestConfig(store.clone(), None);
+        let pdscs = update(&config, vec![dest.to_string_lossy().into_owned()], ()).unwrap();
+        assert_eq!(pdscs, vec![store.join("MyVendor.MyPack.1.1.0.pdsc")]);
+        let package = Package::from_path(&pdscs[0]).unwrap();
+        let packs = install(&config, Some(&package), ()).unwrap();
+        assert_eq!(packs, vec![store.join("MyVendor/MyPack/1.1.0.pack")]);
+
+        let filtered = temp_dir("mirror-filtered");
+        let options = MirrorOptions {
+            vendors: vec![String::from("OtherVendor")],
+            ..MirrorOptions::default()
+        };
+        let config = TestConfig(store.clone(), Some(PathBuf::from(MIRROR)));
+        let vidx_list = vec![String::from(
+            "http://localhost:8001/tests/test-pack-index/index.pidx",
+        )];
+        assert!(mirror(&config, vidx_list, &filtered, &options, ())
+            .unwrap()
+            .is_empty());
+
+        fs::remove_dir_all(store).unwrap();
+        fs::remove_dir_all(dest).unwrap();
+        fs::remove_dir_all(filtered).unwrap();
+    }
 }
@@ -0,0 +1,135 @@
+//! A minimal HTTP/1.1 server on loopback addresses, shared by the download
+//! tests and the download benchmark.
+// The benchmark includes this file too, and only uses part of it
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// What to answer a request with.
+pub enum Reply {
+    /// A 200 response with this body
+    Body(String),
+    NotFound,
+    /// Start a large body, then hold the connection open without finishing
+    Stall,
+}
+
+pub type Requests = Arc<Mutex<Vec<String>>>;
+
+pub struct Server {
+    /// The port every host is served on
+    pub port: u16,
+    /// The loopback addresses served, starting with 127.0.0.1
+    pub hosts: Vec<&'static str>,
+    /// The path of every request, in the order received
+    pub requests: Requests,
+}
+
+async fn serve<F>(mut stream: TcpStream, latency: Duration, reply: Arc<F>, requests: Requests)
+where
+    F: Fn(&str) -> Reply,
+{
+    let mut buf = vec![0u8; 4096];
+    let mut pending = Vec::new();
+    loop {
+        let read = match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        pending.extend_from_slice(&buf[..read]);
+        while let Some(end) = pending.windows(4).position(|w| w == b"\r\n\r\n") {
+            let request: Vec<u8> = pending.drain(..end + 4).collect();
+            let request = String::from_utf8_lossy(&request);
+            let path = request.split_whitespace().nth(1).unwrap_or("/");
+            requests.lock().unwrap().push(path.to_string());
+            let response = match reply(path) {
+                Reply::Body(body) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                ),
+                Reply::NotFound => {
+                    String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                }
+                Reply::Stall => {
+                    let head = "HTTP/1.1 200 OK\r\nContent-Length: 1048576\r\n\r\n";
+                    let _ = stream.write_all(head.as_bytes()).await;
+                    let _ = stream.write_all(&[0u8; 1024]).await;
+                    // Hold the connection open until the client drops it
+                    let _ = stream.read(&mut buf).await;
+                    return;
+                }
+            };
+            if !latency.is_zero() {
+                tokio::time::sleep(latency).await;
+            }
+            if stream.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Server {
+    /// Answer requests with `reply`, given the request path, after waiting
+    /// `latency`. The server listens on 127.0.0.1 and on those of
+    /// `extra_hosts` that can be bound; only Linux routes all of 127.0.0.0/8
+    /// to the loopback interface by default.
+    pub fn start<F>(extra_hosts: &[&'static str], latency: Duration, reply: F) -> Self
+    where
+        F: Fn(&str) -> Reply + Send + Sync + 'static,
+    {
+        let requests = Requests::default();
+        let log = requests.clone();
+        let extra_hosts = extra_hosts.to_vec();
+        let (send, recv) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(async move {
+                let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let port = first.local_addr().unwrap().port();
+                let mut hosts = vec!["127.0.0.1"];
+                let mut listeners = vec![first];
+                for host in extra_hosts {
+                    let addr: SocketAddr = format!("{}:{}", host, port).parse().unwrap();
+                    match TcpListener::bind(addr).await {
+                        Ok(listener) => {
+                            hosts.push(host);
+                            listeners.push(listener);
+                        }
+                        Err(e) => eprintln!("Not serving on {}: {}", host, e),
+                    }
+                }
+                send.send((port, hosts)).unwrap();
+                let reply = Arc::new(reply);
+                let mut accepts = Vec::new();
+                for listener in listeners {
+                    let reply = reply.clone();
+                    let log = log.clone();
+                    accepts.push(tokio::spawn(async move {
+                        while let Ok((stream, _)) = listener.accept().await {
+                            tokio::spawn(serve(stream, latency, reply.clone(), log.clone()));
+                        }
+                    }));
+                }
+                for accept in accepts {
+                    let _ = accept.await;
+                }
+            });
+        });
+        let (port, hosts) = recv.recv().unwrap();
+        Self {
+            port,
+            hosts,
+            requests,
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use anyhow::Error;
+use roxmltree::{Node, TextPos};
+use serde::{Deserialize, Serialize};
+
+/// How parsing treats elements it can't make sense of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Leave them out, with a warning
+    #[default]
+    Lenient,
+    /// Fail, after reporting every one of them
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A problem found while parsing, and where in the document it is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The element, such as `/package/devices/family[Dfamily=STM32F4]/device[Dname=STM32F407VG]/memory`,
+    /// or empty when the document isn't well formed
+    pub path: String,
+    /// 1-based line and column
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}: ", self.line, self.column, self.severity)?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// The error of a parse that failed, with everything found on the way.
+#[derive(Debug, Clone)]
+pub struct ParseFailure {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.diagnostics.as_slice() {
+            [only] => write!(f, "{}", only),
+            all => {
+                write!(f, "{} problems", all.len())?;
+                for diagnostic in all {
+                    write!(f, "\n  {}", diagnostic)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseFailure {}
+
+/// Attributes that tell apart elements of the same name, most specific first.
+const IDENTIFYING: &[&str] = &[
+    "Dname",
+    "Dvariant",
+    "DsubFamily",
+    "Dfamily",
+    "Bname",
+    "id",
+    "name",
+    "Cclass",
+];
+
+fn element_path(node: &Node) -> String {
+    let mut parts: Vec<String> = node
+        .ancestors()
+        .filter(|n| n.is_element())
+        .map(|n| {
+            let tag = n.tag_name().name();
+            match IDENTIFYING
+                .iter()
+                .find_map(|attr| n.attribute(*attr).map(|value| (attr, value)))
+            {
+                Some((attr, value)) => format!("{}[{}={}]", tag, attr, value),
+                None => tag.to_string(),
+            }
+        })
+        .collect();
+    parts.reverse();
+    format!("/{}", parts.join("/"))
+}
+
+fn position(node: &Node) -> TextPos {
+    node.document().text_pos_at(node.range().start)
+}
+
+struct Collector {
+    mode: ParseMode,
+    diagnostics: Vec<Diagnostic>,
+}
+
+thread_local! {
+    static COLLECTOR: RefCell<Option<Collector>> = const { RefCell::new(None) };
+}
+
+fn severity(fatal: bool) -> Severity {
+    let strict = COLLECTOR.with(|collector| {
+        collector
+            .borrow()
+            .as_ref()
+            .is_some_and(|collector| collector.mode == ParseMode::Strict)
+    });
+    if fatal || strict {
+        Severity::Error
+    } else {
+        Severity::Warning
+    }
+}
+
+/// Keep `diagnostic` for the parse collecting them, returning it back when
+/// there is none.
+fn record(diagnostic: Diagnostic) -> Option<Diagnostic> {
+    COLLECTOR.with(|collector| match collector.borrow_mut().as_mut() {
+        Some(collector) => {
+            collector.diagnostics.push(diagnostic);
+            None
+        }
+        None => Some(diagnostic),
+    })
+}
+
+/// Report that `node` was left out because of `message`. Parses collecting
+/// diagnostics report them to their caller; others log them here.
+pub(crate) fn report_dropped(node: &Node, message: &dyn fmt::Display) {
+    let pos = position(node);
+    let diagnostic = Diagnostic {
+        severity: severity(false),
+        path: element_path(node),
+        line: pos.row,
+        column: pos.col,
+        message: message.to_string(),
+    };
+    if let Some(diagnostic) = record(diagnostic) {
+        log::warn!("{}", diagnostic);
+    }
+}
+
+/// Run `parse` on `text`, collecting the diagnostics reported on the way.
+pub(crate) fn collect<T, F>(
+    text: &str,
+    mode: ParseMode,
+    parse: F,
+) -> Result<(T, Vec<Diagnostic>), Error>
+where
+    F: FnOnce(&Node) -> Result<T, Error>,
+{
+    let outer = COLLECTOR.with(|collector| {
+        collector.replace(Some(Collector {
+            mode,
+            diagnostics: Vec::new(),
+        }))
+    });
+    let parsed = match roxmltree::Document::parse(text) {
+        Ok(doc) => {
+            let root = doc.root_element();
+            parse(&root).map_err(|e| {
+                let pos = position(&root);
+                record(Diagnostic {
+                    severity: severity(true),
+                    path: element_path(&root),
+                    line: pos.row,
+                    column: pos.col,
+                    message: e.to_string(),
+                });
+            })
+        }
+        Err(e) => {
+            let pos = e.pos();
+            record(Diagnostic {
+                severity: severity(true),
+                path: String::new(),
+                line: pos.row,
+                column: pos.col,
+                message: e.to_string(),
+            });
+            Err(())
+        }
+    };
+    let diagnostics = COLLECTOR
+        .with(|collector| collector.replace(outer))
+        .map(|collector| collector.diagnostics)
+        .unwrap_or_default();
+    match parsed {
+        Ok(parsed) if mode == ParseMode::Lenient || diagnostics.is_empty() => {
+            Ok((parsed, diagnostics))
+        }
+        _ => Err(ParseFailure { diagnostics }.into()),
+    }
+}
@@ -0,0 +1,139 @@
+use std::ffi::OsString;
+use std::fs::{copy, read, remove_file, rename, File};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Error};
+use serde::Serialize;
+
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+pub(crate) fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn check(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    serde_json::from_slice::<serde_json::Value>(bytes)
+        .map(|_| ())
+        .map_err(|e| format_err!("{:?} is corrupt: {}", path, e))
+}
+
+/// Read a JSON file written by `write_json`, falling back to its backup when
+/// the file is missing or corrupt. A missing file without a backup reads as
+/// an empty object; a corrupt file without a usable backup is an error.
+pub(crate) fn read_json(path: &Path) -> Result<Vec<u8>, Error> {
+    let corrupt = match read(path) {
+        Ok(bytes) => match check(path, &bytes) {
+            Ok(()) => return Ok(bytes),
+            Err(err) => Some(err),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(format_err!("Could not read {:?}: {}", path, e)),
+    };
+    let backup = backup_path(path);
+    match read(&backup) {
+        Ok(bytes) if check(&backup, &bytes).is_ok() => {
+            match &corrupt {
+                Some(err) => log::warn!("{}; using the backup {:?}", err, backup),
+                None => log::warn!("{:?} is missing; using the backup {:?}", path, backup),
+            }
+            Ok(bytes)
+        }
+        _ => match corrupt {
+            Some(err) => Err(err),
+            None => Ok(b"{}".to_vec()),
+        },
+    }
+}
+
+/// Replace `path` with `value` as pretty printed JSON. The new contents are
+/// written to a temporary file that is renamed over `path`, so a crash leaves
+/// either the old or the new file. The old file is kept as a backup unless it
+/// is corrupt, in which case the previous backup is left alone.
+pub(crate) fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), Error> {
+    let temp = with_suffix(path, ".tmp");
+    let res = File::create(&temp).map_err(Error::from).and_then(|fd| {
+        let mut writer = BufWriter::new(fd);
+        serde_json::to_writer_pretty(&mut writer, value)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        Ok(())
+    });
+    if let Err(err) = res {
+        let _ = remove_file(&temp);
+        return Err(format_err!("Could not write {:?}: {}", temp, err));
+    }
+    match read(path) {
+        Ok(bytes) => match check(path, &bytes) {
+            Ok(()) => {
+                copy(path, backup_path(path))
+                    .map_err(|e| format_err!("Could not back up {:?}: {}", path, e))?;
+            }
+            Err(err) => log::warn!("{}; keeping the previous backup", err),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(format_err!("Could not back up {:?}: {}", path, e)),
+    }
+    rename(&temp, path).map_err(|e| format_err!("Could not replace {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::fs::write;
+
+    #[test]
+    fn write_backup_and_recover() {
+        let dir = std::env::temp_dir().join(format!("cmsis-json-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.json");
+        assert_eq!(read_json(&path).unwrap(), b"{}");
+
+        let mut map = BTreeMap::new();
+        map.insert("first", 1);
+        write_json(&path, &map).unwrap();
+        map.insert("second", 2);
+        write_json(&path, &map).unwrap();
+        let backup: BTreeMap<String, u32> =
+            serde_json::from_slice(&read(backup_path(&path)).unwrap()).unwrap();
+        assert_eq!(backup.len(), 1);
+        assert!(!with_suffix(&path, ".tmp").exists());
+
+        // A truncated file falls back to the previous generation
+        write(&path, "{\"first\": 1, \"sec").unwrap();
+        assert_eq!(read_json(&path).unwrap(), read(backup_path(&path)).unwrap());
+
+        // Without a usable backup, corruption is reported
+        write(backup_path(&path), "").unwrap();
+        let err = read_json(&path).unwrap_err();
+        assert!(err.to_string().contains("is corrupt"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_file_keeps_backup() {
+        let dir = std::env::temp_dir().join(format!("cmsis-json-bak-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.json");
+
+        let mut map = BTreeMap::new();
+        map.insert("first", 1);
+        write_json(&path, &map).unwrap();
+        map.insert("second", 2);
+        write_json(&path, &map).unwrap();
+
+        // Writing over a truncated file must not replace the good backup
+        write(&path, "{\"first\": 1, \"sec").unwrap();
+        map.insert("third", 3);
+        write_json(&path, &map).unwrap();
+        let backup: BTreeMap<String, u32> =
+            serde_json::from_slice(&read(backup_path(&path)).unwrap()).unwrap();
+        assert_eq!(backup.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,13 +1,26 @@
+pub(crate) mod diagnostic;
+pub(crate) mod json;
 pub(crate) mod parse;
 pub(crate) mod prelude;
+pub(crate) mod write;
 
+pub use self::diagnostic::{Diagnostic, ParseFailure, ParseMode, Severity};
 pub use self::parse::FromElem;
+pub use self::write::{ToElem, XmlWriter};
 
+use std::cmp::Ordering;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::thread;
+
+use roxmltree::Node;
 
 pub trait ResultLogExt<T, E> {
     fn ok_warn(self) -> Option<T>;
     fn ok_error(self) -> Option<T>;
+    /// Like `ok_warn`, for the result of parsing `node`: the warning says
+    /// where `node` is, and is kept as a diagnostic of the parse.
+    fn ok_warn_at(self, node: &Node) -> Option<T>;
 }
 
 impl<T, E> ResultLogExt<T, E> for Result<T, E>
@@ -32,4 +45,108 @@ where
             }
         }
     }
+    fn ok_warn_at(self, node: &Node) -> Option<T> {
+        match self {
+            Ok(x) => Some(x),
+            Err(e) => {
+                diagnostic::report_dropped(node, &e);
+                None
+            }
+        }
+    }
+}
+
+/// Order pack versions such as `1.10.0` and `2.0.0-beta` numerically, with
+/// pre-releases sorting before the release they precede.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(version: &str) -> (Vec<u64>, Option<&str>) {
+        let version = version.trim().trim_start_matches(['v', 'V']);
+        let (core, pre) = match version.find(['-', '+']) {
+            Some(idx) if version[idx..].starts_with('-') => {
+                (&version[..idx], Some(&version[idx + 1..]))
+            }
+            Some(idx) => (&version[..idx], None),
+            None => (version, None),
+        };
+        let core = core.split('.').map(|n| n.parse().unwrap_or(0)).collect();
+        (core, pre)
+    }
+    let (a_core, a_pre) = split(a);
+    let (b_core, b_pre) = split(b);
+    let len = a_core.len().max(b_core.len());
+    for i in 0..len {
+        let ord = a_core.get(i).unwrap_or(&0).cmp(b_core.get(i).unwrap_or(&0));
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => a.cmp(b),
+    }
+}
+
+/// Apply `f` to every item on a pool of threads, one per available core,
+/// returning the results in the order of `items`.
+pub(crate) fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+    // Items are handed out one at a time, as their cost varies widely
+    let next = AtomicUsize::new(0);
+    let mut done: Vec<(usize, R)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, AtomicOrdering::Relaxed);
+                        match items.get(idx) {
+                            Some(item) => done.push((idx, f(item))),
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    done.sort_unstable_by_key(|(idx, _)| *idx);
+    done.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn version_ordering() {
+        assert_eq!(compare_versions("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("2.0.0-beta", "2.0.0"), Ordering::Less);
+        assert_eq!(compare_versions("2.0.0-beta", "1.9.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0.0+build", "1.0.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn parallel_map_keeps_order() {
+        let items: Vec<u64> = (0..1000).collect();
+        let squares = parallel_map(&items, |n| n * n);
+        assert_eq!(squares, items.iter().map(|n| n * n).collect::<Vec<_>>());
+        assert!(parallel_map(&[] as &[u64], |n| *n).is_empty());
+    }
 }
@@ -4,6 +4,7 @@ use std::io::{BufRead, BufReader};
 use std::path::Path;
 use std::str::FromStr;
 
+use crate::utils::diagnostic::{self, Diagnostic, ParseMode};
 use crate::utils::ResultLogExt;
 use roxmltree::{Children, Node};
 
@@ -89,9 +90,20 @@ pub trait FromElem: Sized {
         Self::from_reader(&mut r)
     }
 
+    /// Parse `s`, returning what was left out along with the result. In
+    /// strict mode, leaving anything out fails the parse; the error is then
+    /// a `ParseFailure` holding every diagnostic.
+    fn from_string_with(s: &str, mode: ParseMode) -> Result<(Self, Vec<Diagnostic>), Error> {
+        diagnostic::collect(s, mode, Self::from_elem)
+    }
+
+    fn from_path_with(p: &Path, mode: ParseMode) -> Result<(Self, Vec<Diagnostic>), Error> {
+        Self::from_string_with(&std::fs::read_to_string(p)?, mode)
+    }
+
     fn vec_from_children(clds: Children) -> Vec<Self> {
         clds.filter(|e| e.is_element())
-            .flat_map(move |cld| Self::from_elem(&cld).ok_warn().into_iter())
+            .flat_map(move |cld| Self::from_elem(&cld).ok_warn_at(&cld).into_iter())
             .collect()
     }
 }
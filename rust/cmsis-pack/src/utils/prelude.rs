@@ -1,4 +1,5 @@
 pub use super::parse::{
     assert_root_name, attr_map, attr_parse, attr_parse_hex, child_text, FromElem,
 };
+pub use super::write::{ToElem, XmlWriter};
 pub use super::ResultLogExt;
@@ -0,0 +1,93 @@
+use std::fs::write;
+use std::path::Path;
+
+use anyhow::{format_err, Error};
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Builds an indented XML document one element at a time.
+pub struct XmlWriter {
+    out: String,
+    depth: usize,
+}
+
+impl Default for XmlWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XmlWriter {
+    pub fn new() -> Self {
+        XmlWriter {
+            out: String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"),
+            depth: 0,
+        }
+    }
+
+    fn open_tag(&mut self, name: &str, attrs: &[(&str, Option<&str>)]) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        self.out.push('<');
+        self.out.push_str(name);
+        for (attr, value) in attrs {
+            if let Some(value) = value {
+                self.out
+                    .push_str(&format!(" {}=\"{}\"", attr, escape(value)));
+            }
+        }
+    }
+
+    /// Write `<name attrs.../>`, leaving out attributes that are `None`.
+    pub fn empty(&mut self, name: &str, attrs: &[(&str, Option<&str>)]) {
+        self.open_tag(name, attrs);
+        self.out.push_str("/>\n");
+    }
+
+    /// Open `<name attrs...>`; children are written until the matching `end`.
+    pub fn start(&mut self, name: &str, attrs: &[(&str, Option<&str>)]) {
+        self.open_tag(name, attrs);
+        self.out.push_str(">\n");
+        self.depth += 1;
+    }
+
+    pub fn end(&mut self, name: &str) {
+        self.depth = self.depth.saturating_sub(1);
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(&format!("</{}>\n", name));
+    }
+
+    /// Write `<name>text</name>`.
+    pub fn text(&mut self, name: &str, text: &str) {
+        self.open_tag(name, &[]);
+        self.out
+            .push_str(&format!(">{}</{}>\n", escape(text), name));
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+pub trait ToElem {
+    fn to_elem(&self, w: &mut XmlWriter);
+
+    fn to_xml_string(&self) -> String {
+        let mut w = XmlWriter::new();
+        self.to_elem(&mut w);
+        w.finish()
+    }
+
+    fn to_path(&self, p: &Path) -> Result<(), Error> {
+        write(p, self.to_xml_string()).map_err(|e| format_err!("Could not write {:?}: {}", p, e))
+    }
+}